@@ -0,0 +1,258 @@
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use failure::{format_err, ResultExt};
+use serde_json::json;
+use snips_nlu_ontology::{BuiltinEntityKind, Language};
+use snips_nlu_parsers::ontology::{complete_entity_ontology, language_entity_ontology};
+use snips_nlu_parsers::{
+    BuiltinEntityParser, BuiltinEntityParserLoader, GazetteerParser, GazetteerParserBuilder,
+};
+use std::fs::File;
+use std::io::{self, BufRead, Write};
+use std::process;
+use std::str::FromStr;
+
+type Result<T> = ::std::result::Result<T, failure::Error>;
+
+const INPUT_ERRORS_HELP: &str = "Lines which cannot be parsed are reported as \
+    {\"line\": <line number>, \"error\": <message>} and the following lines are still parsed. \
+    The exit status is non-zero if any line failed.";
+
+fn main() {
+    let scope_arg = Arg::with_name("scope")
+        .short("s")
+        .long("scope")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .help("Entity to extract, can be repeated (default: all entities)");
+    let max_alternatives_arg = Arg::with_name("max_alternatives")
+        .short("a")
+        .long("max-alternatives")
+        .takes_value(true)
+        .default_value("5")
+        .help("Maximum number of alternative resolved values");
+    let config_arg = Arg::with_name("config")
+        .required(true)
+        .help("Path to the JSON configuration of the parser");
+    let output_arg = Arg::with_name("output")
+        .required(true)
+        .help("Directory where the parser is persisted");
+    let parser_arg = Arg::with_name("parser")
+        .required(true)
+        .help("Directory of a persisted parser");
+
+    let matches = App::new("snips-nlu-parsers")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Build, persist and run Snips NLU builtin and gazetteer entity parsers")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("build-builtin-parser")
+                .about("Builds a builtin entity parser from a JSON config and persists it")
+                .arg(config_arg.clone())
+                .arg(output_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("build-gazetteer-parser")
+                .about("Builds a gazetteer entity parser from a JSON config and persists it")
+                .arg(config_arg)
+                .arg(output_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("parse-builtin")
+                .about("Extracts builtin entities from each line of stdin, as JSON lines")
+                .after_help(INPUT_ERRORS_HELP)
+                .arg(parser_arg.clone())
+                .arg(scope_arg.clone())
+                .arg(max_alternatives_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("parse-gazetteer")
+                .about("Extracts gazetteer entities from each line of stdin, as JSON lines")
+                .after_help(INPUT_ERRORS_HELP)
+                .arg(parser_arg.clone())
+                .arg(scope_arg)
+                .arg(max_alternatives_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("verify-builtin")
+                .about("Checks the integrity of a persisted builtin entity parser")
+                .arg(parser_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("verify-gazetteer")
+                .about("Checks the integrity of a persisted gazetteer entity parser")
+                .arg(parser_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("ontology")
+                .about("Dumps the builtin entity ontology")
+                .arg(
+                    Arg::with_name("language")
+                        .short("l")
+                        .long("language")
+                        .takes_value(true)
+                        .help("Only dump the ontology of this language"),
+                ),
+        )
+        .get_matches();
+
+    let result = match matches.subcommand() {
+        ("build-builtin-parser", Some(matches)) => build_builtin_parser(matches),
+        ("build-gazetteer-parser", Some(matches)) => build_gazetteer_parser(matches),
+        ("parse-builtin", Some(matches)) => parse_builtin(matches),
+        ("parse-gazetteer", Some(matches)) => parse_gazetteer(matches),
+        ("verify-builtin", Some(matches)) => verify_builtin(matches),
+        ("verify-gazetteer", Some(matches)) => verify_gazetteer(matches),
+        ("ontology", Some(matches)) => dump_ontology(matches),
+        _ => unreachable!(),
+    };
+
+    if let Err(error) = result {
+        eprintln!("Error: {}", error);
+        for cause in error.iter_causes() {
+            eprintln!("Caused by: {}", cause);
+        }
+        process::exit(1);
+    }
+}
+
+fn build_builtin_parser(matches: &ArgMatches) -> Result<()> {
+    let config_path = matches.value_of("config").unwrap();
+    let config_file = File::open(config_path)
+        .with_context(|_| format!("Cannot open configuration file: {}", config_path))?;
+    let parser_loader: BuiltinEntityParserLoader = serde_json::from_reader(config_file)
+        .with_context(|_| "Cannot deserialize builtin entity parser configuration")?;
+    parser_loader
+        .load()?
+        .persist(matches.value_of("output").unwrap())?;
+    Ok(())
+}
+
+fn build_gazetteer_parser(matches: &ArgMatches) -> Result<()> {
+    let config_path = matches.value_of("config").unwrap();
+    let config_file = File::open(config_path)
+        .with_context(|_| format!("Cannot open configuration file: {}", config_path))?;
+    let parser_builder: GazetteerParserBuilder = serde_json::from_reader(config_file)
+        .with_context(|_| "Cannot deserialize gazetteer entity parser configuration")?;
+    parser_builder
+        .build::<String>()?
+        .persist(matches.value_of("output").unwrap())?;
+    Ok(())
+}
+
+fn parse_builtin(matches: &ArgMatches) -> Result<()> {
+    let parser = BuiltinEntityParser::from_path(matches.value_of("parser").unwrap())?;
+    let scope = match matches.values_of("scope") {
+        Some(identifiers) => Some(
+            identifiers
+                .map(|identifier| {
+                    BuiltinEntityKind::from_identifier(identifier)
+                        .map_err(|_| format_err!("Unknown entity kind '{}'", identifier))
+                })
+                .collect::<Result<Vec<_>>>()?,
+        ),
+        None => None,
+    };
+    let max_alternatives = get_max_alternatives(matches)?;
+    for_each_input_line(|sentence| {
+        let entities = parser.extract_entities(
+            sentence,
+            scope.as_ref().map(|kinds| &**kinds),
+            max_alternatives,
+        )?;
+        Ok(serde_json::to_string(&entities)?)
+    })
+}
+
+fn parse_gazetteer(matches: &ArgMatches) -> Result<()> {
+    let parser = GazetteerParser::<String>::from_path(matches.value_of("parser").unwrap())?;
+    let scope: Option<Vec<String>> = matches.values_of("scope").map(|identifiers| {
+        identifiers
+            .map(|identifier| identifier.to_string())
+            .collect()
+    });
+    let max_alternatives = get_max_alternatives(matches)?;
+    for_each_input_line(|sentence| {
+        let entities = parser.extract_entities(
+            sentence,
+            scope.as_ref().map(|identifiers| &**identifiers),
+            max_alternatives,
+        )?;
+        Ok(serde_json::to_string(&entities)?)
+    })
+}
+
+fn verify_builtin(matches: &ArgMatches) -> Result<()> {
+    let parser_path = matches.value_of("parser").unwrap();
+    BuiltinEntityParser::verify(parser_path)?;
+    println!("Builtin entity parser at {} is valid", parser_path);
+    Ok(())
+}
+
+fn verify_gazetteer(matches: &ArgMatches) -> Result<()> {
+    let parser_path = matches.value_of("parser").unwrap();
+    GazetteerParser::<String>::verify(parser_path)?;
+    println!("Gazetteer entity parser at {} is valid", parser_path);
+    Ok(())
+}
+
+fn dump_ontology(matches: &ArgMatches) -> Result<()> {
+    let ontology = match matches.value_of("language") {
+        Some(language) => {
+            let language = Language::from_str(&language.to_uppercase())
+                .map_err(|_| format_err!("Unknown language '{}'", language))?;
+            serde_json::to_string_pretty(&language_entity_ontology(language))?
+        }
+        None => serde_json::to_string_pretty(&complete_entity_ontology())?,
+    };
+    println!("{}", ontology);
+    Ok(())
+}
+
+fn get_max_alternatives(matches: &ArgMatches) -> Result<usize> {
+    let max_alternatives = matches.value_of("max_alternatives").unwrap();
+    Ok(max_alternatives
+        .parse()
+        .with_context(|_| format!("Invalid max alternatives: {}", max_alternatives))?)
+}
+
+/// Runs `process` on each line of stdin and writes its output as a line of stdout
+///
+/// Lines which are not valid UTF-8 or cannot be processed are reported as a JSON line holding
+/// their 1-based number and the error, and the following lines are still processed. The command
+/// fails once all the input has been consumed if any line failed.
+fn for_each_input_line<F>(process: F) -> Result<()>
+where
+    F: Fn(&str) -> Result<String>,
+{
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut nb_failed_lines = 0;
+    for (line_index, line) in stdin.lock().lines().enumerate() {
+        let output = match line {
+            Ok(line) => process(line.trim_end()),
+            // Lines which are not valid UTF-8 are consumed before the error is returned
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => Err(error.into()),
+            Err(error) => return Err(error.into()),
+        };
+        match output {
+            Ok(output) => writeln!(stdout, "{}", output)?,
+            Err(error) => {
+                nb_failed_lines += 1;
+                let error_output = json!({
+                    "line": line_index + 1,
+                    "error": error.to_string(),
+                });
+                writeln!(stdout, "{}", error_output)?
+            }
+        }
+    }
+    if nb_failed_lines > 0 {
+        return Err(format_err!(
+            "{} input line(s) could not be parsed",
+            nb_failed_lines
+        ));
+    }
+    Ok(())
+}
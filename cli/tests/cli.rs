@@ -0,0 +1,187 @@
+use assert_cmd::Command;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+fn test_path() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("..")
+        .join("data")
+        .join("tests")
+}
+
+fn cli() -> Command {
+    Command::cargo_bin("snips-nlu-parsers").unwrap()
+}
+
+fn json_lines(output: &[u8]) -> Vec<Value> {
+    String::from_utf8(output.to_vec())
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_should_build_builtin_parser() {
+    // Given
+    let temp_dir = tempdir().unwrap();
+    let config_path = temp_dir.path().join("config.json");
+    let parser_dir = temp_dir.path().join("builtin_entity_parser");
+    fs::write(
+        &config_path,
+        r#"{"language": "EN", "gazetteer_parser_path": null}"#,
+    )
+    .unwrap();
+
+    // When
+    cli()
+        .arg("build-builtin-parser")
+        .arg(&config_path)
+        .arg(&parser_dir)
+        .assert()
+        .success();
+
+    // Then
+    assert!(parser_dir.join("metadata.json").exists());
+}
+
+#[test]
+fn test_should_build_gazetteer_parser() {
+    // Given
+    let temp_dir = tempdir().unwrap();
+    let config_path = temp_dir.path().join("config.json");
+    let parser_dir = temp_dir.path().join("gazetteer_entity_parser");
+    let config = r#"{
+        "entity_parsers": [
+            {
+                "entity_identifier": "music_artist",
+                "entity_parser": {
+                    "gazetteer": [
+                        {
+                            "raw_value": "the rolling stones",
+                            "resolved_value": "The Rolling Stones"
+                        }
+                    ],
+                    "threshold": 0.6,
+                    "n_gazetteer_stop_words": null,
+                    "additional_stop_words": null
+                }
+            }
+        ]
+    }"#;
+    fs::write(&config_path, config).unwrap();
+
+    // When
+    cli()
+        .arg("build-gazetteer-parser")
+        .arg(&config_path)
+        .arg(&parser_dir)
+        .assert()
+        .success();
+
+    // Then
+    assert!(parser_dir.join("metadata.json").exists());
+}
+
+#[test]
+fn test_should_parse_builtin_entities_and_report_failing_lines() {
+    // Given
+    let parser_dir = test_path().join("builtin_entity_parser_no_gazetteer");
+    let input = b"raise to sixty two degrees\n\xff\xfe\nhello world\n".to_vec();
+
+    // When
+    let assert = cli()
+        .arg("parse-builtin")
+        .arg(&parser_dir)
+        .args(&["--scope", "snips/number"])
+        .write_stdin(input)
+        .assert()
+        .failure();
+
+    // Then
+    let outputs = json_lines(&assert.get_output().stdout);
+    assert_eq!(3, outputs.len());
+    assert_eq!("sixty two", outputs[0][0]["value"]);
+    assert_eq!("snips/number", outputs[0][0]["entity_kind"]);
+    assert_eq!(2, outputs[1]["line"]);
+    assert!(outputs[1]["error"].is_string());
+    assert_eq!(Value::Array(vec![]), outputs[2]);
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("1 input line(s) could not be parsed"));
+}
+
+#[test]
+fn test_should_parse_gazetteer_entities() {
+    // Given
+    let parser_dir = test_path().join("custom_gazetteer_parser");
+
+    // When
+    let assert = cli()
+        .arg("parse-gazetteer")
+        .arg(&parser_dir)
+        .args(&["--scope", "music_artist"])
+        .write_stdin("I want to listen to the rolling stones\nhello world\n")
+        .assert()
+        .success();
+
+    // Then
+    let outputs = json_lines(&assert.get_output().stdout);
+    assert_eq!(2, outputs.len());
+    assert_eq!("The Rolling Stones", outputs[0][0]["resolved_value"]);
+    assert_eq!("music_artist", outputs[0][0]["entity_identifier"]);
+    assert_eq!(Value::Array(vec![]), outputs[1]);
+}
+
+#[test]
+fn test_should_verify_builtin_parser() {
+    cli()
+        .arg("verify-builtin")
+        .arg(test_path().join("builtin_entity_parser"))
+        .assert()
+        .success();
+    cli()
+        .arg("verify-builtin")
+        .arg(test_path().join("custom_gazetteer_parser"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_should_verify_gazetteer_parser() {
+    cli()
+        .arg("verify-gazetteer")
+        .arg(test_path().join("custom_gazetteer_parser"))
+        .assert()
+        .success();
+    cli()
+        .arg("verify-gazetteer")
+        .arg(test_path().join("does_not_exist"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_should_dump_ontology() {
+    // When
+    let complete_assert = cli().arg("ontology").assert().success();
+    let language_assert = cli()
+        .args(&["ontology", "--language", "en"])
+        .assert()
+        .success();
+    let unknown_language_assert = cli()
+        .args(&["ontology", "--language", "xx"])
+        .assert()
+        .failure();
+
+    // Then
+    let complete_ontology: Value =
+        serde_json::from_slice(&complete_assert.get_output().stdout).unwrap();
+    let language_ontology: Value =
+        serde_json::from_slice(&language_assert.get_output().stdout).unwrap();
+    let stderr = String::from_utf8(unknown_language_assert.get_output().stderr.clone()).unwrap();
+    assert!(complete_ontology.is_array());
+    assert_eq!("en", language_ontology["language"]);
+    assert!(stderr.contains("Unknown language 'xx'"));
+}
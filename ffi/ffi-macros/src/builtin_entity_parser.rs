@@ -1,29 +1,32 @@
+use crate::get_persist_mode;
 use crate::Result;
-use failure::ResultExt;
 use ffi_utils::{convert_to_c_string, CReprOf, CStringArray, RawPointerConverter};
 use snips_nlu_ontology::{BuiltinEntity, BuiltinEntityKind, BuiltinGazetteerEntityKind};
 use snips_nlu_ontology_ffi_macros::{CBuiltinEntity, CBuiltinEntityArray};
-use snips_nlu_parsers::{BuiltinEntityParser, BuiltinEntityParserLoader, EntityValue};
+use snips_nlu_parsers::errors::ErrorKind;
+use snips_nlu_parsers::{
+    BuiltinEntityParser, BuiltinEntityParserLoader, EntityValue, ExtractionContext,
+    ExtractionDiagnostics, LoadingMode, SharedBuiltinEntityParser,
+};
 use std::ffi::CStr;
 use std::slice;
 
 #[repr(C)]
 pub struct CBuiltinEntityParser(*const libc::c_void);
 
+/// The parser is wrapped in a `SharedBuiltinEntityParser`, so that the functions taking a
+/// `CBuiltinEntityParser` can be called concurrently from several threads
 macro_rules! get_parser {
     ($opaque:ident) => {{
         let container: &$crate::CBuiltinEntityParser = unsafe { &*$opaque };
-        let x = container.0 as *const BuiltinEntityParser;
+        let x = container.0 as *const SharedBuiltinEntityParser;
         unsafe { &*x }
     }};
 }
 
-macro_rules! get_parser_mut {
-    ($opaque:ident) => {{
-        let container: &$crate::CBuiltinEntityParser = unsafe { &*$opaque };
-        let x = container.0 as *mut BuiltinEntityParser;
-        unsafe { &mut *x }
-    }};
+fn into_c_parser(parser: BuiltinEntityParser) -> *const CBuiltinEntityParser {
+    let shared_parser = SharedBuiltinEntityParser::new(parser);
+    CBuiltinEntityParser(shared_parser.into_raw_pointer() as _).into_raw_pointer()
 }
 
 pub fn create_builtin_entity_parser(
@@ -32,9 +35,7 @@ pub fn create_builtin_entity_parser(
 ) -> Result<()> {
     let json_config = unsafe { CStr::from_ptr(json_config) }.to_str()?;
     let parser_loader: BuiltinEntityParserLoader = serde_json::from_str(json_config)?;
-    let parser = parser_loader.load()?;
-
-    let c_parser = CBuiltinEntityParser(parser.into_raw_pointer() as _).into_raw_pointer();
+    let c_parser = into_c_parser(parser_loader.load()?);
 
     unsafe {
         *ptr = c_parser;
@@ -47,23 +48,93 @@ pub fn extend_gazetteer_entity_json(
     entity_name: *const libc::c_char,
     entity_values_json: *const libc::c_char,
 ) -> Result<()> {
-    let parser = get_parser_mut!(ptr);
-    let entity_identifier = unsafe { CStr::from_ptr(entity_name) }.to_str()?;
-    let entity_kind = BuiltinGazetteerEntityKind::from_identifier(entity_identifier)?;
+    let parser = get_parser!(ptr);
+    let entity_kind = get_gazetteer_entity_kind(entity_name)?;
+    let entity_values_json_str = unsafe { CStr::from_ptr(entity_values_json) }.to_str()?;
+    let entity_values: Vec<EntityValue> = serde_json::from_str(entity_values_json_str)?;
+
+    parser
+        .update(|parser| parser.extend_gazetteer_entity(entity_kind, entity_values.into_iter()))?;
+    Ok(())
+}
+
+pub fn remove_gazetteer_entity_values_json(
+    ptr: *const CBuiltinEntityParser,
+    entity_name: *const libc::c_char,
+    raw_values_json: *const libc::c_char,
+) -> Result<()> {
+    let parser = get_parser!(ptr);
+    let entity_kind = get_gazetteer_entity_kind(entity_name)?;
+    let raw_values_json_str = unsafe { CStr::from_ptr(raw_values_json) }.to_str()?;
+    let raw_values: Vec<String> = serde_json::from_str(raw_values_json_str)?;
+
+    parser.update(|parser| parser.remove_gazetteer_values(entity_kind, &raw_values))?;
+    Ok(())
+}
+
+pub fn replace_gazetteer_entity_values_json(
+    ptr: *const CBuiltinEntityParser,
+    entity_name: *const libc::c_char,
+    entity_values_json: *const libc::c_char,
+) -> Result<()> {
+    let parser = get_parser!(ptr);
+    let entity_kind = get_gazetteer_entity_kind(entity_name)?;
     let entity_values_json_str = unsafe { CStr::from_ptr(entity_values_json) }.to_str()?;
     let entity_values: Vec<EntityValue> = serde_json::from_str(entity_values_json_str)?;
 
-    parser.extend_gazetteer_entity(entity_kind, entity_values.into_iter())?;
+    parser
+        .update(|parser| parser.replace_gazetteer_values(entity_kind, entity_values.into_iter()))?;
     Ok(())
 }
 
+pub fn reset_gazetteer_entity(
+    ptr: *const CBuiltinEntityParser,
+    entity_name: *const libc::c_char,
+) -> Result<()> {
+    let parser = get_parser!(ptr);
+    let entity_kind = get_gazetteer_entity_kind(entity_name)?;
+
+    parser.update(|parser| parser.reset_gazetteer_entity(entity_kind))?;
+    Ok(())
+}
+
+fn get_gazetteer_entity_kind(
+    entity_name: *const libc::c_char,
+) -> Result<BuiltinGazetteerEntityKind> {
+    let entity_identifier = unsafe { CStr::from_ptr(entity_name) }.to_str()?;
+    Ok(
+        BuiltinGazetteerEntityKind::from_identifier(entity_identifier)
+            .map_err(|_| ErrorKind::UnknownEntityKind(entity_identifier.to_string()))?,
+    )
+}
+
 pub fn persist_builtin_entity_parser(
     ptr: *const CBuiltinEntityParser,
     path: *const libc::c_char,
+) -> Result<()> {
+    persist_builtin_entity_parser_with_mode(ptr, path, false)
+}
+
+pub fn persist_builtin_entity_parser_with_mode(
+    ptr: *const CBuiltinEntityParser,
+    path: *const libc::c_char,
+    overwrite: bool,
 ) -> Result<()> {
     let parser = get_parser!(ptr);
     let parser_path = unsafe { CStr::from_ptr(path) }.to_str()?;
-    parser.persist(parser_path)?;
+    parser
+        .read()
+        .persist_with_mode(parser_path, get_persist_mode(overwrite))?;
+    Ok(())
+}
+
+pub fn persist_builtin_entity_parser_to_archive(
+    ptr: *const CBuiltinEntityParser,
+    path: *const libc::c_char,
+) -> Result<()> {
+    let parser = get_parser!(ptr);
+    let archive_path = unsafe { CStr::from_ptr(path) }.to_str()?;
+    parser.read().persist_to_archive(archive_path)?;
     Ok(())
 }
 
@@ -73,8 +144,7 @@ pub fn load_builtin_entity_parser(
 ) -> Result<()> {
     let parser_path = unsafe { CStr::from_ptr(path) }.to_str()?;
     let builtin_entity_parser = BuiltinEntityParser::from_path(parser_path)?;
-    let c_parser =
-        CBuiltinEntityParser(builtin_entity_parser.into_raw_pointer() as _).into_raw_pointer();
+    let c_parser = into_c_parser(builtin_entity_parser);
 
     unsafe {
         *ptr = c_parser;
@@ -82,11 +152,82 @@ pub fn load_builtin_entity_parser(
     Ok(())
 }
 
+pub fn load_builtin_entity_parser_with_mode(
+    ptr: *mut *const CBuiltinEntityParser,
+    path: *const libc::c_char,
+    loading_mode_json: *const libc::c_char,
+) -> Result<()> {
+    let parser_path = unsafe { CStr::from_ptr(path) }.to_str()?;
+    let loading_mode_json = unsafe { CStr::from_ptr(loading_mode_json) }.to_str()?;
+    let loading_mode = serde_json::from_str::<LoadingMode>(loading_mode_json)?;
+    let builtin_entity_parser =
+        BuiltinEntityParser::from_path_with_loading_mode(parser_path, &loading_mode)?;
+    let c_parser = into_c_parser(builtin_entity_parser);
+
+    unsafe {
+        *ptr = c_parser;
+    }
+    Ok(())
+}
+
+pub fn load_builtin_entity_parser_from_archive(
+    ptr: *mut *const CBuiltinEntityParser,
+    path: *const libc::c_char,
+    loading_mode_json: *const libc::c_char,
+) -> Result<()> {
+    let archive_path = unsafe { CStr::from_ptr(path) }.to_str()?;
+    let loading_mode_json = unsafe { CStr::from_ptr(loading_mode_json) }.to_str()?;
+    let loading_mode = serde_json::from_str::<LoadingMode>(loading_mode_json)?;
+    let builtin_entity_parser = BuiltinEntityParser::from_archive(archive_path, &loading_mode)?;
+    let c_parser = into_c_parser(builtin_entity_parser);
+
+    unsafe {
+        *ptr = c_parser;
+    }
+    Ok(())
+}
+
+pub fn load_builtin_entity_parser_from_bytes(
+    ptr: *mut *const CBuiltinEntityParser,
+    bytes: *const libc::c_uchar,
+    nb_bytes: libc::c_uint,
+    loading_mode_json: *const libc::c_char,
+) -> Result<()> {
+    let bytes = unsafe { slice::from_raw_parts(bytes, nb_bytes as usize) };
+    let loading_mode_json = unsafe { CStr::from_ptr(loading_mode_json) }.to_str()?;
+    let loading_mode = serde_json::from_str::<LoadingMode>(loading_mode_json)?;
+    let builtin_entity_parser = BuiltinEntityParser::from_bytes(bytes, &loading_mode)?;
+    let c_parser = into_c_parser(builtin_entity_parser);
+
+    unsafe {
+        *ptr = c_parser;
+    }
+    Ok(())
+}
+
+pub fn verify_builtin_entity_parser(path: *const libc::c_char) -> Result<()> {
+    let parser_path = unsafe { CStr::from_ptr(path) }.to_str()?;
+    BuiltinEntityParser::verify(parser_path)?;
+    Ok(())
+}
+
+pub fn get_builtin_entity_parser_fingerprint(
+    ptr: *const CBuiltinEntityParser,
+    result: *mut *const libc::c_char,
+) -> Result<()> {
+    let parser = get_parser!(ptr);
+    let fingerprint = parser.read().fingerprint()?;
+    let cs = convert_to_c_string!(fingerprint);
+    unsafe { *result = cs }
+    Ok(())
+}
+
 pub fn extract_builtin_entity_c(
     ptr: *const CBuiltinEntityParser,
     sentence: *const libc::c_char,
     filter_entity_kinds: *const CStringArray,
     max_alternative_resolved_values: libc::c_uint,
+    context_json: *const libc::c_char,
     results: *mut *const CBuiltinEntityArray,
 ) -> Result<()> {
     let c_entities = extract_builtin_entity(
@@ -94,6 +235,7 @@ pub fn extract_builtin_entity_c(
         sentence,
         filter_entity_kinds,
         max_alternative_resolved_values,
+        context_json,
     )?
     .into_iter()
     .map(CBuiltinEntity::from)
@@ -112,6 +254,7 @@ pub fn extract_builtin_entity_json(
     sentence: *const libc::c_char,
     filter_entity_kinds: *const CStringArray,
     max_alternative_resolved_values: libc::c_uint,
+    context_json: *const libc::c_char,
     results: *mut *const libc::c_char,
 ) -> Result<()> {
     let entities = extract_builtin_entity(
@@ -119,6 +262,60 @@ pub fn extract_builtin_entity_json(
         sentence,
         filter_entity_kinds,
         max_alternative_resolved_values,
+        context_json,
+    )?;
+    let json = ::serde_json::to_string(&entities)?;
+
+    let cs = convert_to_c_string!(json);
+    unsafe { *results = cs }
+
+    Ok(())
+}
+
+pub fn extract_builtin_entity_with_diagnostics_json(
+    ptr: *const CBuiltinEntityParser,
+    sentence: *const libc::c_char,
+    filter_entity_kinds: *const CStringArray,
+    max_alternative_resolved_values: libc::c_uint,
+    context_json: *const libc::c_char,
+    results: *mut *const libc::c_char,
+) -> Result<()> {
+    let (entities, diagnostics) = extract_builtin_entity_with_diagnostics(
+        ptr,
+        sentence,
+        filter_entity_kinds,
+        max_alternative_resolved_values,
+        context_json,
+    )?;
+    let json = ::serde_json::to_string(&::serde_json::json!({
+        "entities": entities,
+        "diagnostics": diagnostics,
+    }))?;
+
+    let cs = convert_to_c_string!(json);
+    unsafe { *results = cs }
+
+    Ok(())
+}
+
+pub fn extract_builtin_entity_with_offsets_json(
+    ptr: *const CBuiltinEntityParser,
+    sentence: *const libc::c_char,
+    filter_entity_kinds: *const CStringArray,
+    max_alternative_resolved_values: libc::c_uint,
+    context_json: *const libc::c_char,
+    results: *mut *const libc::c_char,
+) -> Result<()> {
+    let parser = get_parser!(ptr);
+    let sentence = unsafe { CStr::from_ptr(sentence) }.to_str()?;
+    let opt_filters = get_entity_kinds_filter(filter_entity_kinds)?;
+    let context = get_extraction_context(context_json)?;
+
+    let entities = parser.read().extract_entities_with_offsets(
+        sentence,
+        opt_filters.as_ref().map(|vec| vec.as_slice()),
+        max_alternative_resolved_values as usize,
+        &context,
     )?;
     let json = ::serde_json::to_string(&entities)?;
 
@@ -133,43 +330,108 @@ pub fn extract_builtin_entity(
     sentence: *const libc::c_char,
     filter_entity_kinds: *const CStringArray,
     max_alternative_resolved_values: libc::c_uint,
+    context_json: *const libc::c_char,
 ) -> Result<Vec<BuiltinEntity>> {
+    extract_builtin_entity_with_diagnostics(
+        ptr,
+        sentence,
+        filter_entity_kinds,
+        max_alternative_resolved_values,
+        context_json,
+    )
+    .map(|(entities, _)| entities)
+}
+
+pub fn extract_builtin_entity_with_diagnostics(
+    ptr: *const CBuiltinEntityParser,
+    sentence: *const libc::c_char,
+    filter_entity_kinds: *const CStringArray,
+    max_alternative_resolved_values: libc::c_uint,
+    context_json: *const libc::c_char,
+) -> Result<(Vec<BuiltinEntity>, ExtractionDiagnostics)> {
     let parser = get_parser!(ptr);
     let sentence = unsafe { CStr::from_ptr(sentence) }.to_str()?;
+    let opt_filters = get_entity_kinds_filter(filter_entity_kinds)?;
+    let context = get_extraction_context(context_json)?;
 
-    let opt_filters: Option<Vec<_>> = if !filter_entity_kinds.is_null() {
-        let filters = unsafe {
-            let array = &*filter_entity_kinds;
-            slice::from_raw_parts(array.data, array.size as usize)
-        }
-        .into_iter()
-        .map(|&ptr| {
-            Ok(unsafe { CStr::from_ptr(ptr) }
-                .to_str()
-                .map_err(::failure::Error::from)
-                .and_then(|s| {
-                    Ok(BuiltinEntityKind::from_identifier(s)
-                        .with_context(|_| format!("`{}` isn't a known builtin entity kind", s))?)
-                })?)
-        })
-        .collect::<Result<Vec<_>>>()?;
-        Some(filters)
-    } else {
-        None
-    };
-    let opt_filters = opt_filters.as_ref().map(|vec| vec.as_slice());
-
-    parser.extract_entities(
+    parser.read().extract_entities_with_diagnostics(
         sentence,
-        opt_filters,
+        opt_filters.as_ref().map(|vec| vec.as_slice()),
         max_alternative_resolved_values as usize,
+        &context,
     )
 }
 
+pub fn extract_builtin_entity_batch_json(
+    ptr: *const CBuiltinEntityParser,
+    sentences: *const CStringArray,
+    filter_entity_kinds: *const CStringArray,
+    max_alternative_resolved_values: libc::c_uint,
+    context_json: *const libc::c_char,
+    num_threads: libc::c_uint,
+    results: *mut *const libc::c_char,
+) -> Result<()> {
+    let parser = get_parser!(ptr);
+    let sentences = unsafe {
+        let array = &*sentences;
+        slice::from_raw_parts(array.data, array.size as usize)
+    }
+    .iter()
+    .map(|&ptr| Ok(unsafe { CStr::from_ptr(ptr) }.to_str()?))
+    .collect::<Result<Vec<_>>>()?;
+    let opt_filters = get_entity_kinds_filter(filter_entity_kinds)?;
+    let context = get_extraction_context(context_json)?;
+    let entities = parser.read().extract_entities_batch(
+        &sentences,
+        opt_filters.as_ref().map(|vec| vec.as_slice()),
+        max_alternative_resolved_values as usize,
+        &context,
+        num_threads as usize,
+    )?;
+    let json = ::serde_json::to_string(&entities)?;
+
+    let cs = convert_to_c_string!(json);
+    unsafe { *results = cs }
+
+    Ok(())
+}
+
+fn get_entity_kinds_filter(
+    filter_entity_kinds: *const CStringArray,
+) -> Result<Option<Vec<BuiltinEntityKind>>> {
+    if filter_entity_kinds.is_null() {
+        return Ok(None);
+    }
+    let filters = unsafe {
+        let array = &*filter_entity_kinds;
+        slice::from_raw_parts(array.data, array.size as usize)
+    }
+    .into_iter()
+    .map(|&ptr| {
+        Ok(unsafe { CStr::from_ptr(ptr) }
+            .to_str()
+            .map_err(::failure::Error::from)
+            .and_then(|s| {
+                Ok(BuiltinEntityKind::from_identifier(s)
+                    .map_err(|_| ErrorKind::UnknownEntityKind(s.to_string()))?)
+            })?)
+    })
+    .collect::<Result<Vec<_>>>()?;
+    Ok(Some(filters))
+}
+
+fn get_extraction_context(context_json: *const libc::c_char) -> Result<ExtractionContext> {
+    if context_json.is_null() {
+        return Ok(ExtractionContext::default());
+    }
+    let context_json = unsafe { CStr::from_ptr(context_json) }.to_str()?;
+    Ok(serde_json::from_str(context_json)?)
+}
+
 pub fn destroy_builtin_entity_parser(ptr: *mut CBuiltinEntityParser) -> Result<()> {
     unsafe {
         let parser = CBuiltinEntityParser::from_raw_pointer(ptr)?.0;
-        let _ = BuiltinEntityParser::from_raw_pointer(parser as _);
+        let _ = SharedBuiltinEntityParser::from_raw_pointer(parser as _);
     }
     Ok(())
 }
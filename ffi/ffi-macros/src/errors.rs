@@ -0,0 +1,84 @@
+use snips_nlu_parsers::errors::{Error, ErrorKind};
+use std::cell::Cell;
+
+/// Code describing the kind of the last error which occurred in the current thread
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum SNIPS_NLU_PARSERS_ERROR_CODE {
+    SNIPS_NLU_PARSERS_ERROR_NONE = 0,
+    SNIPS_NLU_PARSERS_ERROR_OTHER = 1,
+    SNIPS_NLU_PARSERS_ERROR_PERSISTENCE = 2,
+    SNIPS_NLU_PARSERS_ERROR_METADATA_DESERIALIZATION = 3,
+    SNIPS_NLU_PARSERS_ERROR_UNKNOWN_LANGUAGE = 4,
+    SNIPS_NLU_PARSERS_ERROR_UNKNOWN_ENTITY_KIND = 5,
+    SNIPS_NLU_PARSERS_ERROR_GAZETTEER_NOT_LOADED = 6,
+    SNIPS_NLU_PARSERS_ERROR_PARSER_BUILD = 7,
+    SNIPS_NLU_PARSERS_ERROR_GRAMMAR_PARSING = 8,
+    SNIPS_NLU_PARSERS_ERROR_GAZETTEER_PARSING = 9,
+    SNIPS_NLU_PARSERS_ERROR_ENTITY_KIND_NOT_LOADED = 10,
+    SNIPS_NLU_PARSERS_ERROR_INCOMPATIBLE_VERSION = 11,
+    SNIPS_NLU_PARSERS_ERROR_INTEGRITY_CHECK = 12,
+    SNIPS_NLU_PARSERS_ERROR_UNSAFE_LAYOUT = 13,
+    SNIPS_NLU_PARSERS_ERROR_INVALID_GAZETTEER_THRESHOLD = 14,
+}
+
+thread_local! {
+    static LAST_ERROR_CODE: Cell<SNIPS_NLU_PARSERS_ERROR_CODE> =
+        Cell::new(SNIPS_NLU_PARSERS_ERROR_CODE::SNIPS_NLU_PARSERS_ERROR_NONE);
+}
+
+impl<'a> From<&'a ErrorKind> for SNIPS_NLU_PARSERS_ERROR_CODE {
+    fn from(kind: &'a ErrorKind) -> Self {
+        use SNIPS_NLU_PARSERS_ERROR_CODE::*;
+        match kind {
+            ErrorKind::Persistence(_) => SNIPS_NLU_PARSERS_ERROR_PERSISTENCE,
+            ErrorKind::MetadataDeserialization(_) => {
+                SNIPS_NLU_PARSERS_ERROR_METADATA_DESERIALIZATION
+            }
+            ErrorKind::IncompatibleVersion(_) => SNIPS_NLU_PARSERS_ERROR_INCOMPATIBLE_VERSION,
+            ErrorKind::IntegrityCheck(_) => SNIPS_NLU_PARSERS_ERROR_INTEGRITY_CHECK,
+            ErrorKind::UnsafeLayout(_) => SNIPS_NLU_PARSERS_ERROR_UNSAFE_LAYOUT,
+            ErrorKind::UnknownLanguage(_) => SNIPS_NLU_PARSERS_ERROR_UNKNOWN_LANGUAGE,
+            ErrorKind::UnknownEntityKind(_) => SNIPS_NLU_PARSERS_ERROR_UNKNOWN_ENTITY_KIND,
+            ErrorKind::GazetteerNotLoaded(_) => SNIPS_NLU_PARSERS_ERROR_GAZETTEER_NOT_LOADED,
+            ErrorKind::EntityKindNotLoaded(_) => SNIPS_NLU_PARSERS_ERROR_ENTITY_KIND_NOT_LOADED,
+            ErrorKind::InvalidGazetteerThreshold { .. } => {
+                SNIPS_NLU_PARSERS_ERROR_INVALID_GAZETTEER_THRESHOLD
+            }
+            ErrorKind::ParserBuild(_) => SNIPS_NLU_PARSERS_ERROR_PARSER_BUILD,
+            ErrorKind::ThreadPool(_) => SNIPS_NLU_PARSERS_ERROR_OTHER,
+            ErrorKind::GrammarParsing { .. } => SNIPS_NLU_PARSERS_ERROR_GRAMMAR_PARSING,
+            ErrorKind::GazetteerParsing { .. } => SNIPS_NLU_PARSERS_ERROR_GAZETTEER_PARSING,
+        }
+    }
+}
+
+/// Stores the code of the error contained in `result`, if any, so that it can be retrieved with
+/// `snips_nlu_parsers_get_last_error_code`
+pub fn record_error_code<T>(
+    result: ::std::result::Result<T, ::failure::Error>,
+) -> ::std::result::Result<T, ::failure::Error> {
+    let code = match &result {
+        Ok(_) => SNIPS_NLU_PARSERS_ERROR_CODE::SNIPS_NLU_PARSERS_ERROR_NONE,
+        Err(error) => error
+            .downcast_ref::<Error>()
+            .map(|error| error.kind())
+            .or_else(|| error.downcast_ref::<ErrorKind>())
+            .map(SNIPS_NLU_PARSERS_ERROR_CODE::from)
+            .unwrap_or(SNIPS_NLU_PARSERS_ERROR_CODE::SNIPS_NLU_PARSERS_ERROR_OTHER),
+    };
+    LAST_ERROR_CODE.with(|last_code| last_code.set(code));
+    result
+}
+
+pub fn get_last_error_code() -> SNIPS_NLU_PARSERS_ERROR_CODE {
+    LAST_ERROR_CODE.with(|last_code| last_code.get())
+}
+
+#[macro_export]
+macro_rules! wrap_with_error_code {
+    ($e:expr) => {
+        wrap!($crate::record_error_code($e))
+    };
+}
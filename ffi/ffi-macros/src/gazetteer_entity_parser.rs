@@ -1,28 +1,38 @@
-use crate::Result;
+use crate::{get_persist_mode, Result};
 use ffi_utils::{convert_to_c_string, CReprOf, CStringArray, RawPointerConverter};
-use snips_nlu_parsers::{GazetteerEntityMatch, GazetteerParser, GazetteerParserBuilder};
+use snips_nlu_parsers::{
+    EntityValue, GazetteerEntityMatch, GazetteerParser, GazetteerParserBuilder, LoadingMode,
+    SharedGazetteerParser,
+};
+use std::collections::BTreeMap;
 use std::ffi::CStr;
 use std::slice;
 
 #[repr(C)]
 pub struct CGazetteerEntityParser(*const libc::c_void);
 
+/// The parser is wrapped in a `SharedGazetteerParser`, so that the functions taking a
+/// `CGazetteerEntityParser` can be called concurrently from several threads
 macro_rules! get_parser {
     ($opaque:ident) => {{
         let container: &$crate::CGazetteerEntityParser = unsafe { &*$opaque };
-        let x = container.0 as *const GazetteerParser<String>;
+        let x = container.0 as *const SharedGazetteerParser<String>;
         unsafe { &*x }
     }};
 }
 
+fn into_c_parser(parser: GazetteerParser<String>) -> *const CGazetteerEntityParser {
+    let shared_parser = SharedGazetteerParser::new(parser);
+    CGazetteerEntityParser(shared_parser.into_raw_pointer() as _).into_raw_pointer()
+}
+
 pub fn load_gazetteer_entity_parser(
     ptr: *mut *const CGazetteerEntityParser,
     path: *const libc::c_char,
 ) -> Result<()> {
     let parser_path = unsafe { CStr::from_ptr(path) }.to_str()?;
     let gazetteer_parser = GazetteerParser::<String>::from_path(parser_path)?;
-    let c_parser =
-        CGazetteerEntityParser(gazetteer_parser.into_raw_pointer() as _).into_raw_pointer();
+    let c_parser = into_c_parser(gazetteer_parser);
 
     unsafe {
         *ptr = c_parser;
@@ -30,6 +40,76 @@ pub fn load_gazetteer_entity_parser(
     Ok(())
 }
 
+pub fn load_gazetteer_entity_parser_with_mode(
+    ptr: *mut *const CGazetteerEntityParser,
+    path: *const libc::c_char,
+    loading_mode_json: *const libc::c_char,
+) -> Result<()> {
+    let parser_path = unsafe { CStr::from_ptr(path) }.to_str()?;
+    let loading_mode_json = unsafe { CStr::from_ptr(loading_mode_json) }.to_str()?;
+    let loading_mode = serde_json::from_str::<LoadingMode>(loading_mode_json)?;
+    let gazetteer_parser =
+        GazetteerParser::<String>::from_path_with_loading_mode(parser_path, &loading_mode)?;
+    let c_parser = into_c_parser(gazetteer_parser);
+
+    unsafe {
+        *ptr = c_parser;
+    }
+    Ok(())
+}
+
+pub fn load_gazetteer_entity_parser_from_archive(
+    ptr: *mut *const CGazetteerEntityParser,
+    path: *const libc::c_char,
+    loading_mode_json: *const libc::c_char,
+) -> Result<()> {
+    let archive_path = unsafe { CStr::from_ptr(path) }.to_str()?;
+    let loading_mode_json = unsafe { CStr::from_ptr(loading_mode_json) }.to_str()?;
+    let loading_mode = serde_json::from_str::<LoadingMode>(loading_mode_json)?;
+    let gazetteer_parser = GazetteerParser::<String>::from_archive(archive_path, &loading_mode)?;
+    let c_parser = into_c_parser(gazetteer_parser);
+
+    unsafe {
+        *ptr = c_parser;
+    }
+    Ok(())
+}
+
+pub fn load_gazetteer_entity_parser_from_bytes(
+    ptr: *mut *const CGazetteerEntityParser,
+    bytes: *const libc::c_uchar,
+    nb_bytes: libc::c_uint,
+    loading_mode_json: *const libc::c_char,
+) -> Result<()> {
+    let bytes = unsafe { slice::from_raw_parts(bytes, nb_bytes as usize) };
+    let loading_mode_json = unsafe { CStr::from_ptr(loading_mode_json) }.to_str()?;
+    let loading_mode = serde_json::from_str::<LoadingMode>(loading_mode_json)?;
+    let gazetteer_parser = GazetteerParser::<String>::from_bytes(bytes, &loading_mode)?;
+    let c_parser = into_c_parser(gazetteer_parser);
+
+    unsafe {
+        *ptr = c_parser;
+    }
+    Ok(())
+}
+
+pub fn verify_gazetteer_entity_parser(path: *const libc::c_char) -> Result<()> {
+    let parser_path = unsafe { CStr::from_ptr(path) }.to_str()?;
+    GazetteerParser::<String>::verify(parser_path)?;
+    Ok(())
+}
+
+pub fn get_gazetteer_entity_parser_fingerprint(
+    ptr: *const CGazetteerEntityParser,
+    result: *mut *const libc::c_char,
+) -> Result<()> {
+    let parser = get_parser!(ptr);
+    let fingerprint = parser.read().fingerprint()?;
+    let cs = convert_to_c_string!(fingerprint);
+    unsafe { *result = cs }
+    Ok(())
+}
+
 pub fn build_gazetteer_entity_parser(
     ptr: *mut *const CGazetteerEntityParser,
     json_config: *const libc::c_char,
@@ -37,8 +117,7 @@ pub fn build_gazetteer_entity_parser(
     let json_config = unsafe { CStr::from_ptr(json_config) }.to_str()?;
     let gazetteer_parser =
         serde_json::from_str::<GazetteerParserBuilder>(json_config)?.build::<String>()?;
-    let c_parser =
-        CGazetteerEntityParser(gazetteer_parser.into_raw_pointer() as _).into_raw_pointer();
+    let c_parser = into_c_parser(gazetteer_parser);
 
     unsafe {
         *ptr = c_parser;
@@ -46,13 +125,92 @@ pub fn build_gazetteer_entity_parser(
     Ok(())
 }
 
+pub fn extend_gazetteer_entity_parser_json(
+    ptr: *const CGazetteerEntityParser,
+    entity_identifier: *const libc::c_char,
+    entity_values_json: *const libc::c_char,
+) -> Result<()> {
+    let parser = get_parser!(ptr);
+    let entity_identifier = unsafe { CStr::from_ptr(entity_identifier) }.to_str()?;
+    let entity_values_json_str = unsafe { CStr::from_ptr(entity_values_json) }.to_str()?;
+    let entity_values: Vec<EntityValue> = serde_json::from_str(entity_values_json_str)?;
+
+    parser.update(|parser| {
+        parser.extend_gazetteer_entity(entity_identifier.to_string(), entity_values.into_iter())
+    })?;
+    Ok(())
+}
+
+pub fn remove_gazetteer_entity_parser_values_json(
+    ptr: *const CGazetteerEntityParser,
+    entity_identifier: *const libc::c_char,
+    raw_values_json: *const libc::c_char,
+) -> Result<()> {
+    let parser = get_parser!(ptr);
+    let entity_identifier = unsafe { CStr::from_ptr(entity_identifier) }.to_str()?;
+    let raw_values_json_str = unsafe { CStr::from_ptr(raw_values_json) }.to_str()?;
+    let raw_values: Vec<String> = serde_json::from_str(raw_values_json_str)?;
+
+    parser.update(|parser| {
+        parser.remove_gazetteer_values(entity_identifier.to_string(), &raw_values)
+    })?;
+    Ok(())
+}
+
+pub fn replace_gazetteer_entity_parser_values_json(
+    ptr: *const CGazetteerEntityParser,
+    entity_identifier: *const libc::c_char,
+    entity_values_json: *const libc::c_char,
+) -> Result<()> {
+    let parser = get_parser!(ptr);
+    let entity_identifier = unsafe { CStr::from_ptr(entity_identifier) }.to_str()?;
+    let entity_values_json_str = unsafe { CStr::from_ptr(entity_values_json) }.to_str()?;
+    let entity_values: Vec<EntityValue> = serde_json::from_str(entity_values_json_str)?;
+
+    parser.update(|parser| {
+        parser.replace_gazetteer_values(entity_identifier.to_string(), entity_values.into_iter())
+    })?;
+    Ok(())
+}
+
+pub fn reset_gazetteer_entity_parser_entity(
+    ptr: *const CGazetteerEntityParser,
+    entity_identifier: *const libc::c_char,
+) -> Result<()> {
+    let parser = get_parser!(ptr);
+    let entity_identifier = unsafe { CStr::from_ptr(entity_identifier) }.to_str()?;
+
+    parser.update(|parser| parser.reset_gazetteer_entity(entity_identifier.to_string()))?;
+    Ok(())
+}
+
 pub fn persist_gazetteer_entity_parser(
     ptr: *const CGazetteerEntityParser,
     path: *const libc::c_char,
+) -> Result<()> {
+    persist_gazetteer_entity_parser_with_mode(ptr, path, false)
+}
+
+pub fn persist_gazetteer_entity_parser_with_mode(
+    ptr: *const CGazetteerEntityParser,
+    path: *const libc::c_char,
+    overwrite: bool,
 ) -> Result<()> {
     let parser = get_parser!(ptr);
     let parser_path = unsafe { CStr::from_ptr(path) }.to_str()?;
-    parser.persist(parser_path)?;
+    parser
+        .read()
+        .persist_with_mode(parser_path, get_persist_mode(overwrite))?;
+    Ok(())
+}
+
+pub fn persist_gazetteer_entity_parser_to_archive(
+    ptr: *const CGazetteerEntityParser,
+    path: *const libc::c_char,
+) -> Result<()> {
+    let parser = get_parser!(ptr);
+    let archive_path = unsafe { CStr::from_ptr(path) }.to_str()?;
+    parser.read().persist_to_archive(archive_path)?;
     Ok(())
 }
 
@@ -85,36 +243,158 @@ pub fn extract_gazetteer_entity(
 ) -> Result<Vec<GazetteerEntityMatch<String>>> {
     let parser = get_parser!(ptr);
     let sentence = unsafe { CStr::from_ptr(sentence) }.to_str()?;
+    let opt_filters = get_entity_identifiers_filter(filter_entity_kinds)?;
 
-    let opt_filters: Option<Vec<_>> = if !filter_entity_kinds.is_null() {
-        let filters = unsafe {
-            let array = &*filter_entity_kinds;
-            slice::from_raw_parts(array.data, array.size as usize)
-        }
-        .into_iter()
-        .map(|&ptr| {
-            Ok(unsafe { CStr::from_ptr(ptr) }
-                .to_str()
-                .map_err(::failure::Error::from)?
-                .to_string())
-        })
-        .collect::<Result<Vec<_>>>()?;
-        Some(filters)
-    } else {
-        None
-    };
-
-    parser.extract_entities(
+    parser.read().extract_entities(
         sentence,
         opt_filters.as_ref().map(|filters| &**filters),
         max_alternative_resolved_values as usize,
     )
 }
 
+pub fn extract_gazetteer_entity_with_offsets_json(
+    ptr: *const CGazetteerEntityParser,
+    sentence: *const libc::c_char,
+    filter_entity_kinds: *const CStringArray,
+    max_alternative_resolved_values: libc::c_uint,
+    thresholds_json: *const libc::c_char,
+    include_scores: bool,
+    results: *mut *const libc::c_char,
+) -> Result<()> {
+    let parser = get_parser!(ptr);
+    let sentence = unsafe { CStr::from_ptr(sentence) }.to_str()?;
+    let opt_filters = get_entity_identifiers_filter(filter_entity_kinds)?;
+    let thresholds = get_thresholds(thresholds_json)?;
+
+    let entities = parser.read().extract_entities_with_offsets(
+        sentence,
+        opt_filters.as_ref().map(|filters| &**filters),
+        max_alternative_resolved_values as usize,
+        &thresholds,
+        include_scores,
+    )?;
+    let json = ::serde_json::to_string(&entities)?;
+
+    let cs = convert_to_c_string!(json);
+    unsafe { *results = cs }
+
+    Ok(())
+}
+
+pub fn extract_gazetteer_entity_with_thresholds_json(
+    ptr: *const CGazetteerEntityParser,
+    sentence: *const libc::c_char,
+    filter_entity_kinds: *const CStringArray,
+    max_alternative_resolved_values: libc::c_uint,
+    thresholds_json: *const libc::c_char,
+    results: *mut *const libc::c_char,
+) -> Result<()> {
+    let entities = extract_gazetteer_entity_with_thresholds(
+        ptr,
+        sentence,
+        filter_entity_kinds,
+        max_alternative_resolved_values,
+        thresholds_json,
+        false,
+    )?;
+    let json = ::serde_json::to_string(&entities)?;
+
+    let cs = convert_to_c_string!(json);
+    unsafe { *results = cs }
+
+    Ok(())
+}
+
+pub fn extract_gazetteer_entity_with_scores_json(
+    ptr: *const CGazetteerEntityParser,
+    sentence: *const libc::c_char,
+    filter_entity_kinds: *const CStringArray,
+    max_alternative_resolved_values: libc::c_uint,
+    thresholds_json: *const libc::c_char,
+    results: *mut *const libc::c_char,
+) -> Result<()> {
+    let entities = extract_gazetteer_entity_with_thresholds(
+        ptr,
+        sentence,
+        filter_entity_kinds,
+        max_alternative_resolved_values,
+        thresholds_json,
+        true,
+    )?;
+    let json = ::serde_json::to_string(&entities)?;
+
+    let cs = convert_to_c_string!(json);
+    unsafe { *results = cs }
+
+    Ok(())
+}
+
+pub fn extract_gazetteer_entity_with_thresholds(
+    ptr: *const CGazetteerEntityParser,
+    sentence: *const libc::c_char,
+    filter_entity_kinds: *const CStringArray,
+    max_alternative_resolved_values: libc::c_uint,
+    thresholds_json: *const libc::c_char,
+    include_scores: bool,
+) -> Result<Vec<GazetteerEntityMatch<String>>> {
+    let parser = get_parser!(ptr);
+    let sentence = unsafe { CStr::from_ptr(sentence) }.to_str()?;
+    let opt_filters = get_entity_identifiers_filter(filter_entity_kinds)?;
+    let thresholds = get_thresholds(thresholds_json)?;
+    let filters = opt_filters.as_ref().map(|filters| &**filters);
+    let max_alternative_resolved_values = max_alternative_resolved_values as usize;
+
+    let parser = parser.read();
+    if include_scores {
+        parser.extract_entities_with_scores(
+            sentence,
+            filters,
+            max_alternative_resolved_values,
+            &thresholds,
+        )
+    } else {
+        parser.extract_entities_with_thresholds(
+            sentence,
+            filters,
+            max_alternative_resolved_values,
+            &thresholds,
+        )
+    }
+}
+
+fn get_thresholds(thresholds_json: *const libc::c_char) -> Result<BTreeMap<String, f64>> {
+    if thresholds_json.is_null() {
+        return Ok(BTreeMap::new());
+    }
+    let thresholds_json = unsafe { CStr::from_ptr(thresholds_json) }.to_str()?;
+    Ok(serde_json::from_str(thresholds_json)?)
+}
+
+fn get_entity_identifiers_filter(
+    filter_entity_kinds: *const CStringArray,
+) -> Result<Option<Vec<String>>> {
+    if filter_entity_kinds.is_null() {
+        return Ok(None);
+    }
+    let filters = unsafe {
+        let array = &*filter_entity_kinds;
+        slice::from_raw_parts(array.data, array.size as usize)
+    }
+    .into_iter()
+    .map(|&ptr| {
+        Ok(unsafe { CStr::from_ptr(ptr) }
+            .to_str()
+            .map_err(::failure::Error::from)?
+            .to_string())
+    })
+    .collect::<Result<Vec<_>>>()?;
+    Ok(Some(filters))
+}
+
 pub fn destroy_gazetteer_entity_parser(ptr: *mut CGazetteerEntityParser) -> Result<()> {
     unsafe {
         let parser = CGazetteerEntityParser::from_raw_pointer(ptr)?.0;
-        let _ = GazetteerParser::<String>::from_raw_pointer(parser as _);
+        let _ = SharedGazetteerParser::<String>::from_raw_pointer(parser as _);
     }
     Ok(())
 }
@@ -1,13 +1,33 @@
 pub use builtin_entity_parser::*;
+pub use errors::*;
 pub use gazetteer_entity_parser::*;
 pub use ontology::*;
 
 mod builtin_entity_parser;
+mod errors;
 mod gazetteer_entity_parser;
 mod ontology;
 
 type Result<T> = ::std::result::Result<T, ::failure::Error>;
 
+fn get_persist_mode(overwrite: bool) -> ::snips_nlu_parsers::PersistMode {
+    if overwrite {
+        ::snips_nlu_parsers::PersistMode::Overwrite
+    } else {
+        ::snips_nlu_parsers::PersistMode::CreateNew
+    }
+}
+
+/// Exports the C symbols of the parsers
+///
+/// The functions taking a parser pointer can be called concurrently on the same parser from
+/// several threads: extractions run in parallel, while updates of gazetteer entities are applied
+/// atomically. The only exception is the destruction of a parser, which must not happen
+/// concurrently with any other call on it.
+///
+/// The `num_threads` argument of `snips_nlu_parsers_extract_builtin_entities_batch_json` has the
+/// same meaning as in `BuiltinEntityParser::extract_entities_batch`: 1 processes the sentences
+/// sequentially and 0 uses one thread per CPU.
 #[macro_export]
 macro_rules! export_nlu_parsers_c_symbols {
     () => {
@@ -16,7 +36,7 @@ macro_rules! export_nlu_parsers_c_symbols {
             ptr: *mut *const $crate::CBuiltinEntityParser,
             json_config: *const ::libc::c_char,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::create_builtin_entity_parser(ptr, json_config))
+            $crate::wrap_with_error_code!($crate::create_builtin_entity_parser(ptr, json_config))
         }
 
         #[no_mangle]
@@ -24,7 +44,28 @@ macro_rules! export_nlu_parsers_c_symbols {
             ptr: *const $crate::CBuiltinEntityParser,
             path: *const ::libc::c_char,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::persist_builtin_entity_parser(ptr, path))
+            $crate::wrap_with_error_code!($crate::persist_builtin_entity_parser(ptr, path))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_persist_builtin_entity_parser_with_mode(
+            ptr: *const $crate::CBuiltinEntityParser,
+            path: *const ::libc::c_char,
+            overwrite: bool,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::persist_builtin_entity_parser_with_mode(
+                ptr, path, overwrite
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_persist_builtin_entity_parser_to_archive(
+            ptr: *const $crate::CBuiltinEntityParser,
+            path: *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::persist_builtin_entity_parser_to_archive(
+                ptr, path
+            ))
         }
 
         #[no_mangle]
@@ -32,7 +73,65 @@ macro_rules! export_nlu_parsers_c_symbols {
             ptr: *mut *const $crate::CBuiltinEntityParser,
             parser_path: *const ::libc::c_char,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::load_builtin_entity_parser(ptr, parser_path))
+            $crate::wrap_with_error_code!($crate::load_builtin_entity_parser(ptr, parser_path))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_load_builtin_entity_parser_with_mode(
+            ptr: *mut *const $crate::CBuiltinEntityParser,
+            parser_path: *const ::libc::c_char,
+            loading_mode_json: *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::load_builtin_entity_parser_with_mode(
+                ptr,
+                parser_path,
+                loading_mode_json
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_load_builtin_entity_parser_from_archive(
+            ptr: *mut *const $crate::CBuiltinEntityParser,
+            archive_path: *const ::libc::c_char,
+            loading_mode_json: *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::load_builtin_entity_parser_from_archive(
+                ptr,
+                archive_path,
+                loading_mode_json
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_load_builtin_entity_parser_from_bytes(
+            ptr: *mut *const $crate::CBuiltinEntityParser,
+            bytes: *const ::libc::c_uchar,
+            nb_bytes: ::libc::c_uint,
+            loading_mode_json: *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::load_builtin_entity_parser_from_bytes(
+                ptr,
+                bytes,
+                nb_bytes,
+                loading_mode_json
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_verify_builtin_entity_parser(
+            parser_path: *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::verify_builtin_entity_parser(parser_path))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_get_builtin_entity_parser_fingerprint(
+            ptr: *const $crate::CBuiltinEntityParser,
+            result: *mut *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::get_builtin_entity_parser_fingerprint(
+                ptr, result
+            ))
         }
 
         #[no_mangle]
@@ -41,13 +140,47 @@ macro_rules! export_nlu_parsers_c_symbols {
             entity_name: *const libc::c_char,
             entity_values_json: *const libc::c_char,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::extend_gazetteer_entity_json(
+            $crate::wrap_with_error_code!($crate::extend_gazetteer_entity_json(
+                ptr,
+                entity_name,
+                entity_values_json
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_remove_gazetteer_entity_values_json(
+            ptr: *const $crate::CBuiltinEntityParser,
+            entity_name: *const libc::c_char,
+            raw_values_json: *const libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::remove_gazetteer_entity_values_json(
+                ptr,
+                entity_name,
+                raw_values_json
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_replace_gazetteer_entity_values_json(
+            ptr: *const $crate::CBuiltinEntityParser,
+            entity_name: *const libc::c_char,
+            entity_values_json: *const libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::replace_gazetteer_entity_values_json(
                 ptr,
                 entity_name,
                 entity_values_json
             ))
         }
 
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_reset_gazetteer_entity(
+            ptr: *const $crate::CBuiltinEntityParser,
+            entity_name: *const libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::reset_gazetteer_entity(ptr, entity_name))
+        }
+
         #[no_mangle]
         pub extern "C" fn snips_nlu_parsers_extract_builtin_entities(
             ptr: *const $crate::CBuiltinEntityParser,
@@ -56,11 +189,31 @@ macro_rules! export_nlu_parsers_c_symbols {
             max_alternative_resolved_values: ::libc::c_uint,
             results: *mut *const snips_nlu_ontology_ffi_macros::CBuiltinEntityArray,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::extract_builtin_entity_c(
+            $crate::wrap_with_error_code!($crate::extract_builtin_entity_c(
                 ptr,
                 sentence,
                 filter_entity_kinds,
                 max_alternative_resolved_values,
+                ::std::ptr::null(),
+                results
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_extract_builtin_entities_with_context(
+            ptr: *const $crate::CBuiltinEntityParser,
+            sentence: *const ::libc::c_char,
+            filter_entity_kinds: *const ::ffi_utils::CStringArray,
+            max_alternative_resolved_values: ::libc::c_uint,
+            context_json: *const ::libc::c_char,
+            results: *mut *const snips_nlu_ontology_ffi_macros::CBuiltinEntityArray,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::extract_builtin_entity_c(
+                ptr,
+                sentence,
+                filter_entity_kinds,
+                max_alternative_resolved_values,
+                context_json,
                 results
             ))
         }
@@ -73,11 +226,90 @@ macro_rules! export_nlu_parsers_c_symbols {
             max_alternative_resolved_values: ::libc::c_uint,
             results: *mut *const ::libc::c_char,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::extract_builtin_entity_json(
+            $crate::wrap_with_error_code!($crate::extract_builtin_entity_json(
                 ptr,
                 sentence,
                 filter_entity_kinds,
                 max_alternative_resolved_values,
+                ::std::ptr::null(),
+                results
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_extract_builtin_entities_with_context_json(
+            ptr: *const $crate::CBuiltinEntityParser,
+            sentence: *const ::libc::c_char,
+            filter_entity_kinds: *const ::ffi_utils::CStringArray,
+            max_alternative_resolved_values: ::libc::c_uint,
+            context_json: *const ::libc::c_char,
+            results: *mut *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::extract_builtin_entity_json(
+                ptr,
+                sentence,
+                filter_entity_kinds,
+                max_alternative_resolved_values,
+                context_json,
+                results
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_extract_builtin_entities_with_diagnostics_json(
+            ptr: *const $crate::CBuiltinEntityParser,
+            sentence: *const ::libc::c_char,
+            filter_entity_kinds: *const ::ffi_utils::CStringArray,
+            max_alternative_resolved_values: ::libc::c_uint,
+            context_json: *const ::libc::c_char,
+            results: *mut *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::extract_builtin_entity_with_diagnostics_json(
+                ptr,
+                sentence,
+                filter_entity_kinds,
+                max_alternative_resolved_values,
+                context_json,
+                results
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_extract_builtin_entities_with_offsets_json(
+            ptr: *const $crate::CBuiltinEntityParser,
+            sentence: *const ::libc::c_char,
+            filter_entity_kinds: *const ::ffi_utils::CStringArray,
+            max_alternative_resolved_values: ::libc::c_uint,
+            context_json: *const ::libc::c_char,
+            results: *mut *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::extract_builtin_entity_with_offsets_json(
+                ptr,
+                sentence,
+                filter_entity_kinds,
+                max_alternative_resolved_values,
+                context_json,
+                results
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_extract_builtin_entities_batch_json(
+            ptr: *const $crate::CBuiltinEntityParser,
+            sentences: *const ::ffi_utils::CStringArray,
+            filter_entity_kinds: *const ::ffi_utils::CStringArray,
+            max_alternative_resolved_values: ::libc::c_uint,
+            context_json: *const ::libc::c_char,
+            num_threads: ::libc::c_uint,
+            results: *mut *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::extract_builtin_entity_batch_json(
+                ptr,
+                sentences,
+                filter_entity_kinds,
+                max_alternative_resolved_values,
+                context_json,
+                num_threads,
                 results
             ))
         }
@@ -88,14 +320,14 @@ macro_rules! export_nlu_parsers_c_symbols {
         ) -> ::ffi_utils::SNIPS_RESULT {
             use ffi_utils::RawPointerConverter;
             use snips_nlu_ontology_ffi_macros::CBuiltinEntityArray;
-            wrap!(unsafe { CBuiltinEntityArray::from_raw_pointer(ptr) })
+            $crate::wrap_with_error_code!(unsafe { CBuiltinEntityArray::from_raw_pointer(ptr) })
         }
 
         #[no_mangle]
         pub extern "C" fn snips_nlu_parsers_destroy_builtin_entity_parser(
             ptr: *mut $crate::CBuiltinEntityParser,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::destroy_builtin_entity_parser(ptr))
+            $crate::wrap_with_error_code!($crate::destroy_builtin_entity_parser(ptr))
         }
 
         #[no_mangle]
@@ -103,7 +335,7 @@ macro_rules! export_nlu_parsers_c_symbols {
             ptr: *mut *const $crate::CGazetteerEntityParser,
             json_config: *const ::libc::c_char,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::build_gazetteer_entity_parser(ptr, json_config))
+            $crate::wrap_with_error_code!($crate::build_gazetteer_entity_parser(ptr, json_config))
         }
 
         #[no_mangle]
@@ -111,7 +343,115 @@ macro_rules! export_nlu_parsers_c_symbols {
             ptr: *mut *const $crate::CGazetteerEntityParser,
             parser_path: *const ::libc::c_char,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::load_gazetteer_entity_parser(ptr, parser_path))
+            $crate::wrap_with_error_code!($crate::load_gazetteer_entity_parser(ptr, parser_path))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_load_gazetteer_entity_parser_with_mode(
+            ptr: *mut *const $crate::CGazetteerEntityParser,
+            parser_path: *const ::libc::c_char,
+            loading_mode_json: *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::load_gazetteer_entity_parser_with_mode(
+                ptr,
+                parser_path,
+                loading_mode_json
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_load_gazetteer_entity_parser_from_archive(
+            ptr: *mut *const $crate::CGazetteerEntityParser,
+            archive_path: *const ::libc::c_char,
+            loading_mode_json: *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::load_gazetteer_entity_parser_from_archive(
+                ptr,
+                archive_path,
+                loading_mode_json
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_load_gazetteer_entity_parser_from_bytes(
+            ptr: *mut *const $crate::CGazetteerEntityParser,
+            bytes: *const ::libc::c_uchar,
+            nb_bytes: ::libc::c_uint,
+            loading_mode_json: *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::load_gazetteer_entity_parser_from_bytes(
+                ptr,
+                bytes,
+                nb_bytes,
+                loading_mode_json
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_verify_gazetteer_entity_parser(
+            parser_path: *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::verify_gazetteer_entity_parser(parser_path))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_get_gazetteer_entity_parser_fingerprint(
+            ptr: *const $crate::CGazetteerEntityParser,
+            result: *mut *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::get_gazetteer_entity_parser_fingerprint(
+                ptr, result
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_extend_gazetteer_entity_parser_json(
+            ptr: *const $crate::CGazetteerEntityParser,
+            entity_identifier: *const libc::c_char,
+            entity_values_json: *const libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::extend_gazetteer_entity_parser_json(
+                ptr,
+                entity_identifier,
+                entity_values_json
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_remove_gazetteer_entity_parser_values_json(
+            ptr: *const $crate::CGazetteerEntityParser,
+            entity_identifier: *const libc::c_char,
+            raw_values_json: *const libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::remove_gazetteer_entity_parser_values_json(
+                ptr,
+                entity_identifier,
+                raw_values_json
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_replace_gazetteer_entity_parser_values_json(
+            ptr: *const $crate::CGazetteerEntityParser,
+            entity_identifier: *const libc::c_char,
+            entity_values_json: *const libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::replace_gazetteer_entity_parser_values_json(
+                ptr,
+                entity_identifier,
+                entity_values_json
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_reset_gazetteer_entity_parser_entity(
+            ptr: *const $crate::CGazetteerEntityParser,
+            entity_identifier: *const libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::reset_gazetteer_entity_parser_entity(
+                ptr,
+                entity_identifier
+            ))
         }
 
         #[no_mangle]
@@ -119,7 +459,28 @@ macro_rules! export_nlu_parsers_c_symbols {
             ptr: *const $crate::CGazetteerEntityParser,
             path: *const ::libc::c_char,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::persist_gazetteer_entity_parser(ptr, path))
+            $crate::wrap_with_error_code!($crate::persist_gazetteer_entity_parser(ptr, path))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_persist_gazetteer_entity_parser_with_mode(
+            ptr: *const $crate::CGazetteerEntityParser,
+            path: *const ::libc::c_char,
+            overwrite: bool,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::persist_gazetteer_entity_parser_with_mode(
+                ptr, path, overwrite
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_persist_gazetteer_entity_parser_to_archive(
+            ptr: *const $crate::CGazetteerEntityParser,
+            path: *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::persist_gazetteer_entity_parser_to_archive(
+                ptr, path
+            ))
         }
 
         #[no_mangle]
@@ -130,11 +491,70 @@ macro_rules! export_nlu_parsers_c_symbols {
             max_alternative_resolved_values: ::libc::c_uint,
             results: *mut *const ::libc::c_char,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::extract_gazetteer_entity_json(
+            $crate::wrap_with_error_code!($crate::extract_gazetteer_entity_json(
+                ptr,
+                sentence,
+                filter_entity_kinds,
+                max_alternative_resolved_values,
+                results
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_extract_gazetteer_entities_with_offsets_json(
+            ptr: *const $crate::CGazetteerEntityParser,
+            sentence: *const ::libc::c_char,
+            filter_entity_kinds: *const ::ffi_utils::CStringArray,
+            max_alternative_resolved_values: ::libc::c_uint,
+            thresholds_json: *const ::libc::c_char,
+            include_scores: bool,
+            results: *mut *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::extract_gazetteer_entity_with_offsets_json(
                 ptr,
                 sentence,
                 filter_entity_kinds,
                 max_alternative_resolved_values,
+                thresholds_json,
+                include_scores,
+                results
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_extract_gazetteer_entities_with_thresholds_json(
+            ptr: *const $crate::CGazetteerEntityParser,
+            sentence: *const ::libc::c_char,
+            filter_entity_kinds: *const ::ffi_utils::CStringArray,
+            max_alternative_resolved_values: ::libc::c_uint,
+            thresholds_json: *const ::libc::c_char,
+            results: *mut *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::extract_gazetteer_entity_with_thresholds_json(
+                ptr,
+                sentence,
+                filter_entity_kinds,
+                max_alternative_resolved_values,
+                thresholds_json,
+                results
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_extract_gazetteer_entities_with_scores_json(
+            ptr: *const $crate::CGazetteerEntityParser,
+            sentence: *const ::libc::c_char,
+            filter_entity_kinds: *const ::ffi_utils::CStringArray,
+            max_alternative_resolved_values: ::libc::c_uint,
+            thresholds_json: *const ::libc::c_char,
+            results: *mut *const ::libc::c_char,
+        ) -> ::ffi_utils::SNIPS_RESULT {
+            $crate::wrap_with_error_code!($crate::extract_gazetteer_entity_with_scores_json(
+                ptr,
+                sentence,
+                filter_entity_kinds,
+                max_alternative_resolved_values,
+                thresholds_json,
                 results
             ))
         }
@@ -143,7 +563,7 @@ macro_rules! export_nlu_parsers_c_symbols {
         pub extern "C" fn snips_nlu_parsers_destroy_gazetteer_entity_parser(
             ptr: *mut $crate::CGazetteerEntityParser,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::destroy_gazetteer_entity_parser(ptr))
+            $crate::wrap_with_error_code!($crate::destroy_gazetteer_entity_parser(ptr))
         }
 
         #[no_mangle]
@@ -151,7 +571,7 @@ macro_rules! export_nlu_parsers_c_symbols {
             language: *const libc::c_char,
             results: *mut *const ::ffi_utils::CStringArray,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::get_supported_builtin_entities(language, results))
+            $crate::wrap_with_error_code!($crate::get_supported_builtin_entities(language, results))
         }
 
         #[no_mangle]
@@ -159,7 +579,7 @@ macro_rules! export_nlu_parsers_c_symbols {
             language: *const libc::c_char,
             results: *mut *const ::ffi_utils::CStringArray,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::get_supported_grammar_entities(language, results))
+            $crate::wrap_with_error_code!($crate::get_supported_grammar_entities(language, results))
         }
 
         #[no_mangle]
@@ -167,7 +587,7 @@ macro_rules! export_nlu_parsers_c_symbols {
             language: *const libc::c_char,
             results: *mut *const ::ffi_utils::CStringArray,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::get_supported_builtin_gazetteer_entities(
+            $crate::wrap_with_error_code!($crate::get_supported_builtin_gazetteer_entities(
                 language, results
             ))
         }
@@ -178,7 +598,7 @@ macro_rules! export_nlu_parsers_c_symbols {
             language: *const libc::c_char,
             results: *mut *const ::ffi_utils::CStringArray,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::get_builtin_entity_examples(
+            $crate::wrap_with_error_code!($crate::get_builtin_entity_examples(
                 builtin_entity_kind,
                 language,
                 results
@@ -189,7 +609,7 @@ macro_rules! export_nlu_parsers_c_symbols {
         pub extern "C" fn snips_nlu_parsers_complete_entity_ontology_json(
             result: *mut *const libc::c_char,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::get_complete_entity_ontology_json(result))
+            $crate::wrap_with_error_code!($crate::get_complete_entity_ontology_json(result))
         }
 
         #[no_mangle]
@@ -197,7 +617,15 @@ macro_rules! export_nlu_parsers_c_symbols {
             language: *const libc::c_char,
             result: *mut *const libc::c_char,
         ) -> ::ffi_utils::SNIPS_RESULT {
-            wrap!($crate::get_language_entity_ontology_json(language, result))
+            $crate::wrap_with_error_code!($crate::get_language_entity_ontology_json(
+                language, result
+            ))
+        }
+
+        #[no_mangle]
+        pub extern "C" fn snips_nlu_parsers_get_last_error_code(
+        ) -> $crate::SNIPS_NLU_PARSERS_ERROR_CODE {
+            $crate::get_last_error_code()
         }
     };
 }
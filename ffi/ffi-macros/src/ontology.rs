@@ -4,6 +4,7 @@ use snips_nlu_ontology::{
     BuiltinEntityKind, BuiltinGazetteerEntityKind, GrammarEntityKind, IntoBuiltinEntityKind,
     Language,
 };
+use snips_nlu_parsers::errors::ErrorKind;
 use snips_nlu_parsers::ontology::*;
 use snips_nlu_parsers::parsable::ParsableEntityKind;
 use std::ffi::CStr;
@@ -14,7 +15,8 @@ pub fn get_supported_builtin_entities(
     results: *mut *const CStringArray,
 ) -> Result<()> {
     let language_str = unsafe { CStr::from_ptr(language) }.to_str()?;
-    let language = Language::from_str(&*language_str.to_uppercase())?;
+    let language = Language::from_str(&*language_str.to_uppercase())
+        .map_err(|_| ErrorKind::UnknownLanguage(language_str.to_string()))?;
     let entities = BuiltinEntityKind::all()
         .iter()
         .filter(|e| e.supported_languages().contains(&language))
@@ -32,7 +34,8 @@ pub fn get_supported_grammar_entities(
     results: *mut *const CStringArray,
 ) -> Result<()> {
     let language_str = unsafe { CStr::from_ptr(language) }.to_str()?;
-    let language = Language::from_str(&*language_str.to_uppercase())?;
+    let language = Language::from_str(&*language_str.to_uppercase())
+        .map_err(|_| ErrorKind::UnknownLanguage(language_str.to_string()))?;
     let entities = GrammarEntityKind::all()
         .iter()
         .filter(|e| e.supported_languages().contains(&language))
@@ -50,7 +53,8 @@ pub fn get_supported_builtin_gazetteer_entities(
     results: *mut *const CStringArray,
 ) -> Result<()> {
     let language_str = unsafe { CStr::from_ptr(language) }.to_str()?;
-    let language = Language::from_str(&*language_str.to_uppercase())?;
+    let language = Language::from_str(&*language_str.to_uppercase())
+        .map_err(|_| ErrorKind::UnknownLanguage(language_str.to_string()))?;
     let entities = BuiltinGazetteerEntityKind::all()
         .iter()
         .filter(|e| e.supported_languages().contains(&language))
@@ -69,9 +73,11 @@ pub fn get_builtin_entity_examples(
     results: *mut *const CStringArray,
 ) -> Result<()> {
     let entity_kind_str = unsafe { CStr::from_ptr(builtin_entity_kind) }.to_str()?;
-    let entity_kind = BuiltinEntityKind::from_identifier(&*entity_kind_str)?;
+    let entity_kind = BuiltinEntityKind::from_identifier(&*entity_kind_str)
+        .map_err(|_| ErrorKind::UnknownEntityKind(entity_kind_str.to_string()))?;
     let language_str = unsafe { CStr::from_ptr(language) }.to_str()?;
-    let language = Language::from_str(&*language_str.to_uppercase())?;
+    let language = Language::from_str(&*language_str.to_uppercase())
+        .map_err(|_| ErrorKind::UnknownLanguage(language_str.to_string()))?;
     let examples = entity_kind
         .examples(language)
         .into_iter()
@@ -94,7 +100,8 @@ pub fn get_language_entity_ontology_json(
     ontology_result: *mut *const libc::c_char,
 ) -> Result<()> {
     let language_str = unsafe { CStr::from_ptr(language) }.to_str()?;
-    let language = Language::from_str(&*language_str.to_uppercase())?;
+    let language = Language::from_str(&*language_str.to_uppercase())
+        .map_err(|_| ErrorKind::UnknownLanguage(language_str.to_string()))?;
     let ontology = serde_json::to_string_pretty(&language_entity_ontology(language))?;
     point_to_string(ontology_result, ontology)
 }
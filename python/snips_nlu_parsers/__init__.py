@@ -6,4 +6,10 @@ from snips_nlu_parsers.builtin_entities import (
     get_builtin_entity_shortname, get_supported_entities,
     get_supported_gazetteer_entities, get_supported_grammar_entities)
 from snips_nlu_parsers.builtin_entity_parser import BuiltinEntityParser
+from snips_nlu_parsers.errors import (
+    EntityKindNotLoadedError, GazetteerNotLoadedError, GazetteerParsingError,
+    GrammarParsingError, IncompatibleVersionError, IntegrityCheckError,
+    InvalidGazetteerThresholdError, MetadataDeserializationError,
+    ParserBuildError, PersistenceError, SnipsNluParsersError,
+    UnknownEntityKindError, UnknownLanguageError, UnsafeLayoutError)
 from snips_nlu_parsers.gazetteer_entity_parser import GazetteerEntityParser
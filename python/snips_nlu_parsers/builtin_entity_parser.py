@@ -1,11 +1,12 @@
 import json
 from _ctypes import byref
 from builtins import bytes, str
-from ctypes import c_char_p, c_int, c_void_p, string_at
+from ctypes import c_bool, c_char_p, c_int, c_uint, c_void_p, string_at
 from pathlib import Path
 
 from snips_nlu_parsers.utils import (
-    CStringArray, check_ffi_error, lib, string_pointer)
+    CStringArray, check_ffi_error, check_gazetteer_thresholds, lib,
+    loading_mode_json, string_pointer)
 
 
 class BuiltinEntityParser(object):
@@ -13,7 +14,10 @@ class BuiltinEntityParser(object):
         self._parser = parser
 
     @classmethod
-    def build(cls, language, gazetteer_entity_parser_path=None):
+    def build(cls, language, gazetteer_entity_parser_path=None,
+              conflict_resolution=None, grammar_error_mode=None,
+              entity_kinds=None, tokenization=None,
+              boundary_alignment=None):
         """Builds a `BuiltinEntityParser`
 
         Args:
@@ -21,6 +25,28 @@ class BuiltinEntityParser(object):
             gazetteer_entity_parser_path (str, optional): Path to a gazetteer
                 entity parser. If None, the builtin entity parser will only
                 use grammar entities.
+            conflict_resolution (str or dict, optional): Strategy used to
+                resolve overlapping entities, one of "keep_all" (default),
+                "longest_match", "grammar_first", "gazetteer_first" or a dict
+                such as ``{"priority": ["snips/number", "snips/musicAlbum"]}``
+            grammar_error_mode (str, optional): Either "lenient" (default),
+                in which case grammar parsing errors are reported in the
+                diagnostics of :meth:`parse_with_diagnostics`, or "strict", in
+                which case they are raised
+            entity_kinds (list of str, optional): Builtin entity labels to
+                load. If defined, the parser is only loaded for these entity
+                kinds and parsing any other kind raises an error.
+            tokenization (str, optional): Tokenization applied before
+                parsing, one of "space_separated", "non_space_separated",
+                in which case whitespace is removed, or "cjk_mixed", in which
+                case whitespace is only removed between Chinese or Japanese
+                characters. If None, the default tokenization of the language
+                is used.
+            boundary_alignment (str, optional): Handling of the entities
+                which do not start or end on token boundaries when whitespace
+                is removed, one of "drop" (default), "expand" or "shrink".
+                Adjusted entities are reported in the diagnostics of
+                :meth:`parse_with_diagnostics`.
         """
         if isinstance(gazetteer_entity_parser_path, Path):
             gazetteer_entity_parser_path = str(gazetteer_entity_parser_path)
@@ -30,6 +56,16 @@ class BuiltinEntityParser(object):
         parser_config = dict(
             language=language.upper(),
             gazetteer_parser_path=gazetteer_entity_parser_path)
+        if conflict_resolution is not None:
+            parser_config["conflict_resolution"] = conflict_resolution
+        if grammar_error_mode is not None:
+            parser_config["grammar_error_mode"] = grammar_error_mode
+        if entity_kinds is not None:
+            parser_config["entity_kinds"] = entity_kinds
+        if tokenization is not None:
+            parser_config["tokenization"] = tokenization
+        if boundary_alignment is not None:
+            parser_config["boundary_alignment"] = boundary_alignment
         parser = c_void_p()
         json_parser_config = bytes(json.dumps(parser_config), encoding="utf8")
         exit_code = lib.snips_nlu_parsers_create_builtin_entity_parser(
@@ -38,7 +74,9 @@ class BuiltinEntityParser(object):
                                    "builtin entity parser")
         return cls(parser)
 
-    def parse(self, text, scope=None, max_alternative_resolved_values=5):
+    def parse(self, text, scope=None, max_alternative_resolved_values=5,
+              reference_datetime=None, timezone=None, include_offsets=False,
+              gazetteer_thresholds=None):
         """Extracts builtin entities from *text*
 
         Args:
@@ -50,27 +88,108 @@ class BuiltinEntityParser(object):
             max_alternative_resolved_values (int, optional): Maximum number of
                 alternative resolved values to return in addition to the top
                 one (default 5).
+            reference_datetime (:class:`datetime.datetime`, optional):
+                Timezone-aware datetime used as reference to resolve relative
+                time expressions. If None, the current time is used.
+            timezone (str, optional): Timezone name, such as
+                "Europe/Paris", in which time expressions are resolved. If
+                None, the local timezone is used.
+            include_offsets (bool, optional): If True, each entity also
+                contains a "byte_range", expressed in bytes of the UTF-8
+                encoded text, and a "utf16_range", expressed in UTF-16 code
+                units, in addition to the "range" expressed in characters
+            gazetteer_thresholds (dict, optional): Minimum ratio of the tokens
+                of a gazetteer value which must be matched, indexed by
+                gazetteer entity label, overriding for this call the
+                threshold of the corresponding gazetteer entity parser. A
+                threshold may be lower or higher than the one the parser was
+                built with.
 
         Returns:
             list of dict: The list of extracted entities
         """
+        extract_fn = (
+            lib.snips_nlu_parsers_extract_builtin_entities_with_offsets_json
+            if include_offsets else
+            lib.snips_nlu_parsers_extract_builtin_entities_with_context_json)
+        return self._extract(
+            extract_fn, text, scope, max_alternative_resolved_values,
+            reference_datetime, timezone, gazetteer_thresholds)
+
+    def parse_with_diagnostics(self, text, scope=None,
+                               max_alternative_resolved_values=5,
+                               reference_datetime=None, timezone=None,
+                               gazetteer_thresholds=None):
+        """Extracts builtin entities from *text* along with the non-fatal
+        issues which occurred during the extraction
+
+        The arguments are the same as in :meth:`parse`.
+
+        Returns:
+            dict: A dict with an "entities" key containing the list of
+            extracted entities, and a "diagnostics" key containing a dict
+            with the list of "grammar_errors" which were ignored and the list
+            of "boundary_adjustments" of entities aligned on token boundaries
+        """
+        return self._extract(
+            lib.snips_nlu_parsers_extract_builtin_entities_with_diagnostics_json,
+            text, scope, max_alternative_resolved_values, reference_datetime,
+            timezone, gazetteer_thresholds)
+
+    def parse_batch(self, texts, scope=None,
+                    max_alternative_resolved_values=5,
+                    reference_datetime=None, timezone=None, num_threads=1,
+                    gazetteer_thresholds=None):
+        """Extracts builtin entities from each text of *texts* in a single
+        call
+
+        The other arguments are the same as in :meth:`parse`.
+
+        Args:
+            texts (list of str): Inputs
+            num_threads (int, optional): Number of threads used to process
+                the texts. Texts are processed sequentially when it is 1,
+                which is the default, and 0 means one thread per CPU.
+
+        Returns:
+            list of list of dict: The lists of entities extracted from each
+            text
+        """
+        if not all(isinstance(text, str) for text in texts):
+            raise TypeError("Expected texts to contain objects of type 'str'")
+        texts = [text.encode("utf8") for text in texts]
+        texts_arr = CStringArray()
+        texts_arr.size = c_int(len(texts))
+        texts_arr.data = (c_char_p * len(texts))(*texts)
+        context = _build_context(reference_datetime, timezone,
+                                 gazetteer_thresholds)
+        scope = _build_scope(scope)
+
+        with string_pointer(c_char_p()) as ptr:
+            exit_code = \
+                lib.snips_nlu_parsers_extract_builtin_entities_batch_json(
+                    self._parser, byref(texts_arr), scope,
+                    max_alternative_resolved_values, context,
+                    num_threads, byref(ptr))
+            check_ffi_error(exit_code, "Something went wrong when extracting "
+                                       "builtin entities")
+            result = string_at(ptr)
+            return json.loads(result.decode("utf8"))
+
+    def _extract(self, extract_fn, text, scope,
+                 max_alternative_resolved_values, reference_datetime,
+                 timezone, gazetteer_thresholds):
         if not isinstance(text, str):
             raise TypeError("Expected language to be of type 'str' but found: "
                             "%s" % type(text))
-        if scope is not None:
-            if not all(isinstance(e, str) for e in scope):
-                raise TypeError(
-                    "Expected scope to contain objects of type 'str'")
-            scope = [e.encode("utf8") for e in scope]
-            arr = CStringArray()
-            arr.size = c_int(len(scope))
-            arr.data = (c_char_p * len(scope))(*scope)
-            scope = byref(arr)
+        context = _build_context(reference_datetime, timezone,
+                                 gazetteer_thresholds)
+        scope = _build_scope(scope)
 
         with string_pointer(c_char_p()) as ptr:
-            exit_code = lib.snips_nlu_parsers_extract_builtin_entities_json(
+            exit_code = extract_fn(
                 self._parser, text.encode("utf8"), scope,
-                max_alternative_resolved_values, byref(ptr))
+                max_alternative_resolved_values, context, byref(ptr))
             check_ffi_error(exit_code, "Something went wrong when extracting "
                                        "builtin entities")
             result = string_at(ptr)
@@ -100,30 +219,230 @@ class BuiltinEntityParser(object):
                                    "builtin entity '%s'" % entity_name)
         return self
 
-    def persist(self, path):
-        """Persists the builtin entity parser on disk at the provided path"""
+    def remove_gazetteer_values(self, entity_name, raw_values):
+        """Removes values which were injected in a builtin entity
+
+        Args:
+            entity_name (str): Gazetteer entity identifier
+            raw_values (list of str): Raw values of the injected values to
+                remove
+
+        Returns:
+            The same object, updated.
+        """
+        raw_values_json = bytes(json.dumps(raw_values), encoding="utf8")
+        exit_code = lib.snips_nlu_parsers_remove_gazetteer_entity_values_json(
+            self._parser, entity_name.encode("utf8"), raw_values_json)
+        check_ffi_error(exit_code, "Something went wrong when removing "
+                                   "values of the builtin entity '%s'"
+                        % entity_name)
+        return self
+
+    def replace_gazetteer_values(self, entity_name, entity_values):
+        """Replaces all the values which were injected in a builtin entity
+
+        Args:
+            entity_name (str): Gazetteer entity identifier
+            entity_values (list of dict): List of entity values represented as
+                dictionaries with a 'raw_value' key and a 'resolved_value' key
+
+        Returns:
+            The same object, updated.
+        """
+        entity_values_json = bytes(json.dumps(entity_values), encoding="utf8")
+        exit_code = lib.snips_nlu_parsers_replace_gazetteer_entity_values_json(
+            self._parser, entity_name.encode("utf8"), entity_values_json)
+        check_ffi_error(exit_code, "Something went wrong when replacing "
+                                   "values of the builtin entity '%s'"
+                        % entity_name)
+        return self
+
+    def reset_gazetteer_entity(self, entity_name):
+        """Removes all the values which were injected in a builtin entity
+
+        Returns:
+            The same object, updated.
+        """
+        exit_code = lib.snips_nlu_parsers_reset_gazetteer_entity(
+            self._parser, entity_name.encode("utf8"))
+        check_ffi_error(exit_code, "Something went wrong when resetting the "
+                                   "builtin entity '%s'" % entity_name)
+        return self
+
+    def fingerprint(self):
+        """Computes a SHA-256 fingerprint of the persisted content of the
+        builtin entity parser
+
+        Two parsers which would be persisted into identical files have the
+        same fingerprint.
+
+        Returns:
+            str: Hexadecimal fingerprint
+        """
+        with string_pointer(c_char_p()) as ptr:
+            exit_code = \
+                lib.snips_nlu_parsers_get_builtin_entity_parser_fingerprint(
+                    self._parser, byref(ptr))
+            check_ffi_error(exit_code, "Something went wrong when computing "
+                                       "the builtin parser fingerprint")
+            return string_at(ptr).decode("utf8")
+
+    def persist(self, path, overwrite=False):
+        """Persists the builtin entity parser on disk at the provided path
+
+        The parser is written in a temporary directory first and then moved
+        to *path*, so that *path* never contains a partially persisted parser.
+
+        Args:
+            path (str or :class:`pathlib.Path`): Target directory
+            overwrite (bool, optional): Whether or not to replace the
+                directory if it already exists (default False)
+        """
         if isinstance(path, Path):
             path = str(path)
-        exit_code = lib.snips_nlu_parsers_persist_builtin_entity_parser(
-            self._parser, path.encode("utf8"))
+        exit_code = \
+            lib.snips_nlu_parsers_persist_builtin_entity_parser_with_mode(
+                self._parser, path.encode("utf8"), c_bool(overwrite))
         check_ffi_error(exit_code, "Something went wrong when persisting the "
                                    "builtin entity parser")
 
+    def persist_to_archive(self, path):
+        """Persists the builtin entity parser in a single archive file at
+        the provided path, which must not already exist
+
+        Args:
+            path (str or :class:`pathlib.Path`): Target archive file
+        """
+        if isinstance(path, Path):
+            path = str(path)
+        exit_code = \
+            lib.snips_nlu_parsers_persist_builtin_entity_parser_to_archive(
+                self._parser, path.encode("utf8"))
+        check_ffi_error(exit_code, "Something went wrong when persisting the "
+                                   "builtin entity parser archive")
+
     @classmethod
-    def from_path(cls, parser_path):
+    def from_path(cls, parser_path, untrusted=False, loading_limits=None):
         """Creates a :class:`BuiltinEntityParser` from a builtin entity parser
         persisted on disk
+
+        Args:
+            parser_path (str or :class:`pathlib.Path`): Parser directory
+            untrusted (bool, optional): Whether or not the parser comes from
+                an untrusted source, in which case the paths it references
+                must be confined to its directory, symbolic links are
+                rejected and its size is limited (default False)
+            loading_limits (dict, optional): Limits applied in untrusted
+                mode, with the optional "max_file_size" (in bytes),
+                "max_total_size" (in bytes), "max_entries" and
+                "max_entity_parsers" keys
         """
         if isinstance(parser_path, Path):
             parser_path = str(parser_path)
         parser = c_void_p()
         parser_path = bytes(parser_path, encoding="utf8")
-        exit_code = lib.snips_nlu_parsers_load_builtin_entity_parser(
-            byref(parser), parser_path)
+        if untrusted:
+            loading_mode = loading_mode_json(untrusted, loading_limits)
+            exit_code = \
+                lib.snips_nlu_parsers_load_builtin_entity_parser_with_mode(
+                    byref(parser), parser_path, loading_mode)
+        else:
+            exit_code = lib.snips_nlu_parsers_load_builtin_entity_parser(
+                byref(parser), parser_path)
         check_ffi_error(exit_code, "Something went wrong when loading the "
                                    "builtin entity parser")
         return cls(parser)
 
+    @classmethod
+    def from_archive(cls, archive_path, untrusted=False, loading_limits=None):
+        """Creates a :class:`BuiltinEntityParser` from an archive
+        file produced by :meth:`persist_to_archive`
+
+        The *untrusted* and *loading_limits* arguments are the same as in
+        :meth:`from_path`. In untrusted mode, the entries of the archive are
+        also checked before being unpacked, links being rejected.
+        """
+        if isinstance(archive_path, Path):
+            archive_path = str(archive_path)
+        parser = c_void_p()
+        archive_path = bytes(archive_path, encoding="utf8")
+        loading_mode = loading_mode_json(untrusted, loading_limits)
+        exit_code = \
+            lib.snips_nlu_parsers_load_builtin_entity_parser_from_archive(
+                byref(parser), archive_path, loading_mode)
+        check_ffi_error(exit_code, "Something went wrong when loading the "
+                                   "builtin entity parser archive")
+        return cls(parser)
+
+    @classmethod
+    def from_bytes(cls, parser_bytes, untrusted=False, loading_limits=None):
+        """Creates a :class:`BuiltinEntityParser` from the in-memory content
+        of an archive file produced by :meth:`persist_to_archive`
+
+        The *untrusted* and *loading_limits* arguments are the same as in
+        :meth:`from_archive`.
+        """
+        if not isinstance(parser_bytes, (bytes, bytearray)):
+            raise TypeError("Expected parser_bytes to be of type 'bytes' but "
+                            "found: %s" % type(parser_bytes))
+        parser = c_void_p()
+        parser_bytes = bytes(parser_bytes)
+        loading_mode = loading_mode_json(untrusted, loading_limits)
+        exit_code = \
+            lib.snips_nlu_parsers_load_builtin_entity_parser_from_bytes(
+                byref(parser), parser_bytes, c_uint(len(parser_bytes)),
+                loading_mode)
+        check_ffi_error(exit_code, "Something went wrong when loading the "
+                                   "builtin entity parser from bytes")
+        return cls(parser)
+
+    @staticmethod
+    def verify(parser_path):
+        """Checks the integrity of a builtin entity parser persisted on disk,
+        without loading it
+
+        Raises:
+            IntegrityCheckError: when the persisted files do not match the
+                checksums recorded in the parser metadata
+        """
+        if isinstance(parser_path, Path):
+            parser_path = str(parser_path)
+        parser_path = bytes(parser_path, encoding="utf8")
+        exit_code = \
+            lib.snips_nlu_parsers_verify_builtin_entity_parser(parser_path)
+        check_ffi_error(exit_code, "Something went wrong when verifying the "
+                                   "builtin entity parser")
+
     def __del__(self):
         if lib is not None and self._parser is not None:
             lib.snips_nlu_parsers_destroy_builtin_entity_parser(self._parser)
+
+
+def _build_context(reference_datetime, timezone, gazetteer_thresholds):
+    if reference_datetime is None and timezone is None \
+            and gazetteer_thresholds is None:
+        return None
+    context = dict()
+    if reference_datetime is not None:
+        if reference_datetime.utcoffset() is None:
+            raise ValueError("Expected reference_datetime to be "
+                             "timezone-aware")
+        context["reference_datetime"] = reference_datetime.isoformat()
+    if timezone is not None:
+        context["timezone"] = timezone
+    if gazetteer_thresholds is not None:
+        check_gazetteer_thresholds(gazetteer_thresholds)
+        context["gazetteer_thresholds"] = gazetteer_thresholds
+    return bytes(json.dumps(context), encoding="utf8")
+
+
+def _build_scope(scope):
+    if scope is None:
+        return None
+    if not all(isinstance(e, str) for e in scope):
+        raise TypeError("Expected scope to contain objects of type 'str'")
+    scope = [e.encode("utf8") for e in scope]
+    arr = CStringArray()
+    arr.size = c_int(len(scope))
+    arr.data = (c_char_p * len(scope))(*scope)
+    return byref(arr)
@@ -0,0 +1,73 @@
+class SnipsNluParsersError(ValueError):
+    """Base class of the errors raised by the parsers"""
+
+
+class PersistenceError(SnipsNluParsersError):
+    pass
+
+
+class MetadataDeserializationError(SnipsNluParsersError):
+    pass
+
+
+class IncompatibleVersionError(SnipsNluParsersError):
+    pass
+
+
+class IntegrityCheckError(SnipsNluParsersError):
+    pass
+
+
+class UnknownLanguageError(SnipsNluParsersError):
+    pass
+
+
+class UnknownEntityKindError(SnipsNluParsersError):
+    pass
+
+
+class GazetteerNotLoadedError(SnipsNluParsersError):
+    pass
+
+
+class EntityKindNotLoadedError(SnipsNluParsersError):
+    pass
+
+
+class UnsafeLayoutError(SnipsNluParsersError):
+    pass
+
+
+class InvalidGazetteerThresholdError(SnipsNluParsersError):
+    pass
+
+
+class ParserBuildError(SnipsNluParsersError):
+    pass
+
+
+class GrammarParsingError(SnipsNluParsersError):
+    pass
+
+
+class GazetteerParsingError(SnipsNluParsersError):
+    pass
+
+
+# Indexed by the codes returned by snips_nlu_parsers_get_last_error_code
+ERRORS_BY_CODE = {
+    1: SnipsNluParsersError,
+    2: PersistenceError,
+    3: MetadataDeserializationError,
+    4: UnknownLanguageError,
+    5: UnknownEntityKindError,
+    6: GazetteerNotLoadedError,
+    7: ParserBuildError,
+    8: GrammarParsingError,
+    9: GazetteerParsingError,
+    10: EntityKindNotLoadedError,
+    11: IncompatibleVersionError,
+    12: IntegrityCheckError,
+    13: UnsafeLayoutError,
+    14: InvalidGazetteerThresholdError,
+}
@@ -1,11 +1,12 @@
 import json
 from _ctypes import byref
 from builtins import bytes, str
-from ctypes import c_char_p, c_int, c_void_p, string_at
+from ctypes import c_bool, c_char_p, c_int, c_uint, c_void_p, string_at
 from pathlib import Path
 
-from snips_nlu_parsers.utils import (CStringArray, check_ffi_error, lib,
-                                     string_pointer)
+from snips_nlu_parsers.utils import (
+    CStringArray, check_ffi_error, check_gazetteer_thresholds, lib,
+    loading_mode_json, string_pointer)
 
 
 class GazetteerEntityParser(object):
@@ -63,7 +64,8 @@ class GazetteerEntityParser(object):
                                    "gazetteer entity parser")
         return cls(parser)
 
-    def parse(self, text, scope=None, max_alternative_resolved_values=5):
+    def parse(self, text, scope=None, max_alternative_resolved_values=5,
+              include_offsets=False, thresholds=None, include_scores=False):
         """Extract gazetteer entities from *text*
 
         Args:
@@ -75,6 +77,22 @@ class GazetteerEntityParser(object):
             max_alternative_resolved_values (int, optional): Maximum number of
                 alternative resolved values to return in addition to the top
                 one (default 5).
+            include_offsets (bool, optional): If True, each entity also
+                contains a "byte_range", expressed in bytes of the UTF-8
+                encoded text, and a "utf16_range", expressed in UTF-16 code
+                units, in addition to the "range" expressed in characters
+            thresholds (dict, optional): Minimum ratio of the tokens of a
+                gazetteer value which must be matched, indexed by entity
+                label, overriding for this call the threshold of the
+                corresponding entity parser. A threshold may be lower or
+                higher than the one the parser was built with.
+            include_scores (bool, optional): If True, each entity also
+                contains a "matched_tokens_ratio", the ratio of the tokens of
+                the matched gazetteer value found in the text, which is the
+                ratio compared to the threshold, and a "score", the Jaccard
+                similarity between the tokens of the entity and of the
+                gazetteer value, punctuation being ignored. The same scores
+                are given for each alternative in "alternative_matches"
 
         Returns:
             list of dict: The list of extracted entities
@@ -92,39 +110,248 @@ class GazetteerEntityParser(object):
             arr.data = (c_char_p * len(scope))(*scope)
             scope = byref(arr)
 
+        if thresholds is not None:
+            check_gazetteer_thresholds(thresholds)
+            thresholds = bytes(json.dumps(thresholds), encoding="utf8")
+
+        extract_fn = (
+            lib.snips_nlu_parsers_extract_gazetteer_entities_with_offsets_json
+            if include_offsets else
+            lib.snips_nlu_parsers_extract_gazetteer_entities_with_scores_json
+            if include_scores else
+            lib.snips_nlu_parsers_extract_gazetteer_entities_with_thresholds_json)
+        extract_args = [self._parser, text.encode("utf8"), scope,
+                        max_alternative_resolved_values, thresholds]
+        if include_offsets:
+            extract_args.append(c_bool(include_scores))
+
         with string_pointer(c_char_p()) as ptr:
-            exit_code = lib.snips_nlu_parsers_extract_gazetteer_entities_json(
-                self._parser, text.encode("utf8"), scope,
-                max_alternative_resolved_values, byref(ptr))
+            exit_code = extract_fn(*(extract_args + [byref(ptr)]))
             check_ffi_error(exit_code, "Something went wrong when "
                                        "extracting gazetteer entities")
             result = string_at(ptr)
             return json.loads(result.decode("utf8"))
 
-    def persist(self, path):
-        """Persist the gazetteer parser on disk at the provided path"""
+    def extend_gazetteer_entity(self, entity_name, entity_values):
+        """Extends a gazetteer entity with custom values
+
+        Args:
+            entity_name (str): Entity identifier
+            entity_values (list of dict): List of entity values represented as
+                dictionaries with a 'raw_value' key and a 'resolved_value' key
+
+        Returns:
+            The same object, updated.
+
+        Raises:
+            GazetteerNotLoadedError: when the entity is not present in the
+                parser
+        """
+        if not entity_values:
+            return self
+        entity_values_json = bytes(json.dumps(entity_values), encoding="utf8")
+        exit_code = lib.snips_nlu_parsers_extend_gazetteer_entity_parser_json(
+            self._parser, entity_name.encode("utf8"), entity_values_json)
+        check_ffi_error(exit_code, "Something went wrong when extending the "
+                                   "gazetteer entity '%s'" % entity_name)
+        return self
+
+    def remove_gazetteer_values(self, entity_name, raw_values):
+        """Removes values which were injected in a gazetteer entity
+
+        Args:
+            entity_name (str): Entity identifier
+            raw_values (list of str): Raw values of the injected values to
+                remove
+
+        Returns:
+            The same object, updated.
+        """
+        raw_values_json = bytes(json.dumps(raw_values), encoding="utf8")
+        exit_code = \
+            lib.snips_nlu_parsers_remove_gazetteer_entity_parser_values_json(
+                self._parser, entity_name.encode("utf8"), raw_values_json)
+        check_ffi_error(exit_code, "Something went wrong when removing "
+                                   "values of the gazetteer entity '%s'"
+                        % entity_name)
+        return self
+
+    def replace_gazetteer_values(self, entity_name, entity_values):
+        """Replaces all the values which were injected in a gazetteer entity
+
+        Args:
+            entity_name (str): Entity identifier
+            entity_values (list of dict): List of entity values represented as
+                dictionaries with a 'raw_value' key and a 'resolved_value' key
+
+        Returns:
+            The same object, updated.
+        """
+        entity_values_json = bytes(json.dumps(entity_values), encoding="utf8")
+        exit_code = \
+            lib.snips_nlu_parsers_replace_gazetteer_entity_parser_values_json(
+                self._parser, entity_name.encode("utf8"), entity_values_json)
+        check_ffi_error(exit_code, "Something went wrong when replacing "
+                                   "values of the gazetteer entity '%s'"
+                        % entity_name)
+        return self
+
+    def reset_gazetteer_entity(self, entity_name):
+        """Removes all the values which were injected in a gazetteer entity
+
+        Returns:
+            The same object, updated.
+        """
+        exit_code = lib.snips_nlu_parsers_reset_gazetteer_entity_parser_entity(
+            self._parser, entity_name.encode("utf8"))
+        check_ffi_error(exit_code, "Something went wrong when resetting the "
+                                   "gazetteer entity '%s'" % entity_name)
+        return self
+
+    def fingerprint(self):
+        """Compute a SHA-256 fingerprint of the persisted content of the
+        gazetteer entity parser
+
+        Two parsers which would be persisted into identical files have the
+        same fingerprint.
+
+        Returns:
+            str: Hexadecimal fingerprint
+        """
+        with string_pointer(c_char_p()) as ptr:
+            exit_code = \
+                lib.snips_nlu_parsers_get_gazetteer_entity_parser_fingerprint(
+                    self._parser, byref(ptr))
+            check_ffi_error(exit_code, "Something went wrong when computing "
+                                       "the gazetteer parser fingerprint")
+            return string_at(ptr).decode("utf8")
+
+    def persist(self, path, overwrite=False):
+        """Persist the gazetteer parser on disk at the provided path
+
+        The parser is written in a temporary directory first and then moved
+        to *path*, so that *path* never contains a partially persisted parser.
+
+        Args:
+            path (str or :class:`pathlib.Path`): Target directory
+            overwrite (bool, optional): Whether or not to replace the
+                directory if it already exists (default False)
+        """
         if isinstance(path, Path):
             path = str(path)
-        exit_code = lib.snips_nlu_parsers_persist_gazetteer_entity_parser(
-            self._parser, path.encode("utf8"))
+        exit_code = \
+            lib.snips_nlu_parsers_persist_gazetteer_entity_parser_with_mode(
+                self._parser, path.encode("utf8"), c_bool(overwrite))
         check_ffi_error(exit_code, "Something went wrong when persisting "
                                    "the gazetteer entity parser")
 
+    def persist_to_archive(self, path):
+        """Persist the gazetteer entity parser in a single archive file at
+        the provided path, which must not already exist
+
+        Args:
+            path (str or :class:`pathlib.Path`): Target archive file
+        """
+        if isinstance(path, Path):
+            path = str(path)
+        exit_code = \
+            lib.snips_nlu_parsers_persist_gazetteer_entity_parser_to_archive(
+                self._parser, path.encode("utf8"))
+        check_ffi_error(exit_code, "Something went wrong when persisting the "
+                                   "gazetteer entity parser archive")
+
     @classmethod
-    def from_path(cls, parser_path):
+    def from_path(cls, parser_path, untrusted=False, loading_limits=None):
         """Create a :class:`GazetteerEntityParser` from a gazetteer parser
         persisted on disk
+
+        Args:
+            parser_path (str or :class:`pathlib.Path`): Parser directory
+            untrusted (bool, optional): Whether or not the parser comes from
+                an untrusted source, in which case the paths it references
+                must be confined to its directory, symbolic links are
+                rejected and its size is limited (default False)
+            loading_limits (dict, optional): Limits applied in untrusted
+                mode, with the optional "max_file_size" (in bytes),
+                "max_total_size" (in bytes), "max_entries" and
+                "max_entity_parsers" keys
         """
         if isinstance(parser_path, Path):
             parser_path = str(parser_path)
         parser = c_void_p()
         parser_path = bytes(parser_path, encoding="utf8")
-        exit_code = lib.snips_nlu_parsers_load_gazetteer_entity_parser(
-            byref(parser), parser_path)
+        if untrusted:
+            loading_mode = loading_mode_json(untrusted, loading_limits)
+            exit_code = \
+                lib.snips_nlu_parsers_load_gazetteer_entity_parser_with_mode(
+                    byref(parser), parser_path, loading_mode)
+        else:
+            exit_code = lib.snips_nlu_parsers_load_gazetteer_entity_parser(
+                byref(parser), parser_path)
         check_ffi_error(exit_code, "Something went wrong when loading the "
                                    "gazetteer entity parser")
         return cls(parser)
 
+    @classmethod
+    def from_archive(cls, archive_path, untrusted=False, loading_limits=None):
+        """Create a :class:`GazetteerEntityParser` from an archive
+        file produced by :meth:`persist_to_archive`
+
+        The *untrusted* and *loading_limits* arguments are the same as in
+        :meth:`from_path`. In untrusted mode, the entries of the archive are
+        also checked before being unpacked, links being rejected.
+        """
+        if isinstance(archive_path, Path):
+            archive_path = str(archive_path)
+        parser = c_void_p()
+        archive_path = bytes(archive_path, encoding="utf8")
+        loading_mode = loading_mode_json(untrusted, loading_limits)
+        exit_code = \
+            lib.snips_nlu_parsers_load_gazetteer_entity_parser_from_archive(
+                byref(parser), archive_path, loading_mode)
+        check_ffi_error(exit_code, "Something went wrong when loading the "
+                                   "gazetteer entity parser archive")
+        return cls(parser)
+
+    @classmethod
+    def from_bytes(cls, parser_bytes, untrusted=False, loading_limits=None):
+        """Create a :class:`GazetteerEntityParser` from the in-memory content
+        of an archive file produced by :meth:`persist_to_archive`
+
+        The *untrusted* and *loading_limits* arguments are the same as in
+        :meth:`from_archive`.
+        """
+        if not isinstance(parser_bytes, (bytes, bytearray)):
+            raise TypeError("Expected parser_bytes to be of type 'bytes' but "
+                            "found: %s" % type(parser_bytes))
+        parser = c_void_p()
+        parser_bytes = bytes(parser_bytes)
+        loading_mode = loading_mode_json(untrusted, loading_limits)
+        exit_code = \
+            lib.snips_nlu_parsers_load_gazetteer_entity_parser_from_bytes(
+                byref(parser), parser_bytes, c_uint(len(parser_bytes)),
+                loading_mode)
+        check_ffi_error(exit_code, "Something went wrong when loading the "
+                                   "gazetteer entity parser from bytes")
+        return cls(parser)
+
+    @staticmethod
+    def verify(parser_path):
+        """Check the integrity of a gazetteer entity parser persisted on disk,
+        without loading it
+
+        Raises:
+            IntegrityCheckError: when the persisted files do not match the
+                checksums recorded in the parser metadata
+        """
+        if isinstance(parser_path, Path):
+            parser_path = str(parser_path)
+        parser_path = bytes(parser_path, encoding="utf8")
+        exit_code = \
+            lib.snips_nlu_parsers_verify_gazetteer_entity_parser(parser_path)
+        check_ffi_error(exit_code, "Something went wrong when verifying the "
+                                   "gazetteer entity parser")
+
     def __del__(self):
         if lib is not None:
             lib.snips_nlu_parsers_destroy_gazetteer_entity_parser(
@@ -1,8 +1,17 @@
 from __future__ import unicode_literals
 
+import json
+import os
+import subprocess
+import sys
 import unittest
+from datetime import datetime, timedelta, timezone
+from threading import Thread
 
-from snips_nlu_parsers import BuiltinEntityParser, get_all_languages
+from snips_nlu_parsers import (
+    BuiltinEntityParser, EntityKindNotLoadedError, GazetteerNotLoadedError,
+    IncompatibleVersionError, IntegrityCheckError, PersistenceError,
+    UnknownEntityKindError, get_all_languages)
 from snips_nlu_parsers.tests.utils import ROOT_DIR
 from snips_nlu_parsers.utils import temp_dir
 
@@ -61,6 +70,66 @@ class TestBuiltinEntityParser(unittest.TestCase):
 
         self.assertListEqual(expected_result, res)
 
+    def test_should_parse_with_reference_datetime_and_timezone(self):
+        # Given
+        parser = BuiltinEntityParser.build("en")
+        reference_datetime = datetime(2019, 10, 18, 12, 0, 0,
+                                      tzinfo=timezone(timedelta(hours=2)))
+
+        # When
+        res = parser.parse("Book a table for tomorrow at 8pm",
+                           scope=["snips/datetime"],
+                           reference_datetime=reference_datetime,
+                           timezone="Asia/Tokyo")
+
+        # Then
+        expected_result = [
+            {
+                "entity": {
+                    "kind": "InstantTime",
+                    "value": "2019-10-19 20:00:00 +09:00",
+                    "grain": "Hour",
+                    "precision": "Exact"
+                },
+                "alternatives": [],
+                "entity_kind": "snips/datetime",
+                "range": {"end": 32, "start": 17},
+                "value": "tomorrow at 8pm"
+            }
+        ]
+
+        self.assertListEqual(expected_result, res)
+
+    def test_should_parse_independently_of_the_host_timezone(self):
+        # Given
+        # New York and Paris switch to daylight saving time on different days
+        script = """
+import json
+from datetime import datetime, timezone
+from snips_nlu_parsers import BuiltinEntityParser
+
+parser = BuiltinEntityParser.build("en")
+reference_datetime = datetime(2019, 3, 30, 13, 0, 0, tzinfo=timezone.utc)
+res = parser.parse("Call me at 5", scope=["snips/time"],
+                   reference_datetime=reference_datetime,
+                   timezone="Europe/Paris")
+print(json.dumps([entity["entity"]["value"] for entity in res] +
+                 [alt["value"] for alt in res[0]["alternatives"]]))
+"""
+        env = dict(os.environ, TZ="America/New_York")
+
+        # When
+        output = subprocess.check_output([sys.executable, "-c", script],
+                                         env=env)
+
+        # Then
+        expected_values = [
+            "2019-03-30 17:00:00 +01:00",
+            "2019-03-31 05:00:00 +02:00"
+        ]
+        self.assertListEqual(expected_values,
+                             json.loads(output.decode("utf8")))
+
     def test_should_parse_with_gazetteer_entity(self):
         # Given
         gazetteer_parser_path = ROOT_DIR / "data" / "tests" / \
@@ -87,6 +156,185 @@ class TestBuiltinEntityParser(unittest.TestCase):
 
         self.assertListEqual(expected_result, res)
 
+    def test_should_parse_with_offsets(self):
+        # Given
+        gazetteer_parser_path = ROOT_DIR / "data" / "tests" / \
+                                "builtin_gazetteer_parser"
+        parser = BuiltinEntityParser.build("en", gazetteer_parser_path)
+        scope = ["snips/musicArtist"]
+
+        # When
+        res = parser.parse("\U0001F600 I want to listen to the stones please!",
+                           scope, include_offsets=True)
+
+        # Then
+        expected_result = [
+            {
+                "entity": {
+                    "kind": "MusicArtist",
+                    "value": "The Rolling Stones"
+                },
+                "alternatives": [],
+                "entity_kind": "snips/musicArtist",
+                "range": {"end": 32, "start": 22},
+                "byte_range": {"end": 35, "start": 25},
+                "utf16_range": {"end": 33, "start": 23},
+                "value": "the stones"
+            }
+        ]
+
+        self.assertListEqual(expected_result, res)
+
+    def test_should_parse_with_gazetteer_thresholds(self):
+        # Given
+        gazetteer_parser_path = ROOT_DIR / "data" / "tests" / \
+                                "builtin_gazetteer_parser"
+        parser = BuiltinEntityParser.build("en", gazetteer_parser_path)
+        text = "I want to listen to the stones please!"
+        scope = ["snips/musicArtist"]
+
+        # When
+        strict_res = parser.parse(
+            text, scope, gazetteer_thresholds={"snips/musicArtist": 0.7})
+        loose_res = parser.parse(
+            text, scope, gazetteer_thresholds={"snips/musicArtist": 0.6})
+
+        # Then
+        self.assertListEqual([], strict_res)
+        self.assertEqual(1, len(loose_res))
+        self.assertEqual("the stones", loose_res[0]["value"])
+
+    def test_should_parse_with_conflict_resolution(self):
+        # Given
+        gazetteer_parser_path = ROOT_DIR / "data" / "tests" / \
+                                "builtin_gazetteer_parser"
+        parser = BuiltinEntityParser.build(
+            "en", gazetteer_parser_path, conflict_resolution="longest_match")
+        parser.extend_gazetteer_entity("snips/musicTrack", [
+            {
+                "raw_value": "one nine nine nine",
+                "resolved_value": "1999"
+            }
+        ])
+        scope = ["snips/number", "snips/musicTrack"]
+
+        # When
+        res = parser.parse("play one nine nine nine by prince", scope)
+
+        # Then
+        expected_result = [
+            {
+                "entity": {
+                    "kind": "MusicTrack",
+                    "value": "1999"
+                },
+                "alternatives": [],
+                "entity_kind": "snips/musicTrack",
+                "range": {"end": 23, "start": 5},
+                "value": "one nine nine nine"
+            }
+        ]
+
+        self.assertListEqual(expected_result, res)
+
+    def test_should_parse_batch(self):
+        # Given
+        parser = BuiltinEntityParser.build("en")
+        texts = ["Raise to sixty two degrees celsius", "hello world",
+                 "Book me a restaurant for two people"]
+
+        # When
+        sequential_res = parser.parse_batch(texts)
+        parallel_res = parser.parse_batch(texts, num_threads=2)
+        per_cpu_res = parser.parse_batch(texts, num_threads=0)
+
+        # Then
+        expected_result = [parser.parse(text) for text in texts]
+        self.assertListEqual(expected_result, sequential_res)
+        self.assertListEqual(expected_result, parallel_res)
+        self.assertListEqual(expected_result, per_cpu_res)
+
+    def test_should_parse_with_entity_kinds_loaded(self):
+        # Given
+        parser = BuiltinEntityParser.build(
+            "en", entity_kinds=["snips/number", "snips/duration"])
+
+        # When
+        res = parser.parse("Raise to sixty two degrees celsius")
+
+        # Then
+        self.assertListEqual(
+            ["snips/number"], [ent["entity_kind"] for ent in res])
+        with self.assertRaises(EntityKindNotLoadedError):
+            parser.parse("Raise to sixty two", ["snips/temperature"])
+
+    def test_should_parse_with_diagnostics(self):
+        # Given
+        parser = BuiltinEntityParser.build("en")
+
+        # When
+        res = parser.parse_with_diagnostics("Raise to sixty two",
+                                            ["snips/temperature"])
+
+        # Then
+        expected_result = {
+            "entities": [
+                {
+                    "entity": {
+                        "kind": "Temperature",
+                        "unit": None,
+                        "value": 62.0
+                    },
+                    "alternatives": [],
+                    "entity_kind": "snips/temperature",
+                    "range": {"end": 18, "start": 9},
+                    "value": "sixty two"
+                }
+            ],
+            "diagnostics": {
+                "grammar_errors": [],
+                "boundary_adjustments": []
+            }
+        }
+
+        self.assertDictEqual(expected_result, res)
+
+    def test_should_parse_with_tokenization(self):
+        # Given
+        parser = BuiltinEntityParser.build("ja", tokenization="cjk_mixed")
+        date = "\u4e8c \u5343 \u5341\u4e09 \u5e74\u4e8c \u6708\u5341 \u65e5"
+        text = "\u306e %s \u306e" % date
+
+        # When
+        res = parser.parse(text, ["snips/datetime"], 0)
+
+        # Then
+        self.assertEqual(1, len(res))
+        self.assertEqual(date, res[0]["value"])
+        self.assertDictEqual({"start": 2, "end": 16}, res[0]["range"])
+
+    def test_should_report_boundary_adjustments(self):
+        # Given
+        parser = BuiltinEntityParser.build("ja", boundary_alignment="expand")
+        date = "\u4e8c \u5343 \u5341\u4e09 \u5e74\u4e8c \u6708\u5341 \u65e5"
+        text = "%s\u306e \u5929\u6c17" % date
+
+        # When
+        res = parser.parse_with_diagnostics(text, ["snips/datetime"], 0)
+
+        # Then
+        self.assertEqual(1, len(res["entities"]))
+        self.assertEqual(date + "\u306e", res["entities"][0]["value"])
+        expected_adjustments = [
+            {
+                "entity_kind": "snips/datetime",
+                "matched_range": {"start": 0, "end": 14},
+                "range": {"start": 0, "end": 15}
+            }
+        ]
+        self.assertListEqual(expected_adjustments,
+                             res["diagnostics"]["boundary_adjustments"])
+
     def test_should_parse_in_all_languages(self):
         # Given
         all_languages = get_all_languages()
@@ -145,12 +393,77 @@ class TestBuiltinEntityParser(unittest.TestCase):
 
         self.assertListEqual(expected_result, res)
 
+    def test_should_remove_replace_and_reset_gazetteer_values(self):
+        # Given
+        gazetteer_parser_path = ROOT_DIR / "data" / "tests" / \
+                                "builtin_gazetteer_parser"
+        parser = BuiltinEntityParser.build(
+            "en", gazetteer_entity_parser_path=gazetteer_parser_path)
+        entity_name = "snips/musicArtist"
+
+        def parse_artists(text):
+            return [ent["entity"]["value"] for ent in
+                    parser.parse(text, [entity_name], 0)]
+
+        parser.extend_gazetteer_entity(entity_name, [
+            {"raw_value": "old band", "resolved_value": "Old Band"},
+            {"raw_value": "jazz band", "resolved_value": "Jazz Band"},
+        ])
+
+        # When
+        parser.remove_gazetteer_values(entity_name, ["old band"])
+        after_removal = parse_artists("play old band then jazz band")
+        parser.replace_gazetteer_values(
+            entity_name, [{"raw_value": "new band",
+                           "resolved_value": "New Band"}])
+        after_replacement = parse_artists("play jazz band then new band")
+        parser.reset_gazetteer_entity(entity_name)
+        after_reset = parse_artists("play new band then the rolling stones")
+
+        # Then
+        self.assertListEqual(["Jazz Band"], after_removal)
+        self.assertListEqual(["New Band"], after_replacement)
+        self.assertListEqual(["The Rolling Stones"], after_reset)
+
+    def test_should_extend_gazetteer_entity_while_parsing(self):
+        # Given
+        gazetteer_parser_path = ROOT_DIR / "data" / "tests" / \
+                                "builtin_gazetteer_parser"
+        parser = BuiltinEntityParser.build(
+            "en", gazetteer_entity_parser_path=gazetteer_parser_path)
+        entity_name = "snips/musicArtist"
+        text = "I want to listen to my extended artist please"
+        results = []
+
+        def parse_text():
+            for _ in range(20):
+                results.append(parser.parse(text, [entity_name], 0))
+
+        threads = [Thread(target=parse_text) for _ in range(4)]
+
+        # When
+        for thread in threads:
+            thread.start()
+        parser.extend_gazetteer_entity(entity_name, [
+            {"raw_value": "my extended artist",
+             "resolved_value": "My Extended Artist"}
+        ])
+        for thread in threads:
+            thread.join()
+        res = parser.parse(text, [entity_name], 0)
+
+        # Then
+        self.assertEqual(80, len(results))
+        self.assertTrue(all(len(entities) <= 1 for entities in results))
+        self.assertListEqual(
+            ["My Extended Artist"], [ent["entity"]["value"] for ent in res])
+
     def test_should_fail_to_extend_non_extensible_parser(self):
         # Given
         parser = BuiltinEntityParser.build("en")
 
         # When / Then
-        with self.assertRaises(ValueError) as cm:
+        with self.assertRaises(GazetteerNotLoadedError) as cm:
             entity_values = [
                 {
                     "raw_value": "my first custom artist",
@@ -162,6 +475,16 @@ class TestBuiltinEntityParser(unittest.TestCase):
         self.assertTrue("No gazetteer parser found for entity 'MusicArtist'"
                         in str(cm.exception))
 
+    def test_should_raise_typed_errors(self):
+        # Given
+        parser = BuiltinEntityParser.build("en")
+
+        # When / Then
+        with self.assertRaises(UnknownEntityKindError):
+            parser.parse("Raise to sixty two", ["snips/unknownEntity"])
+        with self.assertRaises(PersistenceError):
+            BuiltinEntityParser.from_path("/path/to/nowhere")
+
     def test_should_persist_parser(self):
         # Given
         parser = BuiltinEntityParser.build("en")
@@ -189,6 +512,43 @@ class TestBuiltinEntityParser(unittest.TestCase):
         ]
         self.assertListEqual(expected_result, res)
 
+    def test_should_persist_parser_with_overwrite(self):
+        # Given
+        en_parser = BuiltinEntityParser.build("en")
+        fr_parser = BuiltinEntityParser.build("fr")
+
+        # When
+        with temp_dir() as tmpdir:
+            persisted_path = tmpdir / "persisted_builtin_parser"
+            en_parser.persist(persisted_path)
+            with self.assertRaises(PersistenceError):
+                fr_parser.persist(persisted_path)
+            fr_parser.persist(persisted_path, overwrite=True)
+            loaded_parser = BuiltinEntityParser.from_path(persisted_path)
+            persisted_files = [p.name for p in tmpdir.iterdir()]
+
+        # Then
+        self.assertListEqual(["persisted_builtin_parser"], persisted_files)
+        res = loaded_parser.parse("quatre", ["snips/number"])
+        self.assertEqual(4.0, res[0]["entity"]["value"])
+
+    def test_should_refuse_parser_with_incompatible_version(self):
+        # Given
+        parser = BuiltinEntityParser.build("en")
+
+        # When / Then
+        with temp_dir() as tmpdir:
+            persisted_path = tmpdir / "persisted_builtin_parser"
+            parser.persist(persisted_path)
+            metadata_path = persisted_path / "metadata.json"
+            with metadata_path.open(encoding="utf8") as f:
+                metadata = json.load(f)
+            metadata["format_version"] += 1
+            with metadata_path.open(mode="w", encoding="utf8") as f:
+                f.write(json.dumps(metadata))
+            with self.assertRaises(IncompatibleVersionError):
+                BuiltinEntityParser.from_path(persisted_path)
+
     def test_should_load_parser_from_path(self):
         # Given
         parser = BuiltinEntityParser.from_path(
@@ -240,6 +600,100 @@ class TestBuiltinEntityParser(unittest.TestCase):
         ]
         self.assertListEqual(expected_result, res)
 
+    def test_should_persist_parser_to_archive(self):
+        # Given
+        parser = BuiltinEntityParser.from_path(BUILTIN_PARSER_PATH)
+
+        # When
+        with temp_dir() as tmpdir:
+            archive_path = tmpdir / "builtin_parser.tar"
+            parser.persist_to_archive(archive_path)
+            is_file = archive_path.is_file()
+            with self.assertRaises(PersistenceError):
+                parser.persist_to_archive(archive_path)
+            loaded_parser = BuiltinEntityParser.from_archive(archive_path)
+        res = loaded_parser.parse("I want to listen to the stones", None)
+
+        # Then
+        expected_result = [
+            {
+                "value": "the stones",
+                "entity": {
+                    "kind": "MusicArtist",
+                    "value": "The Rolling Stones"
+                },
+                "alternatives": [],
+                "range": {"start": 20, "end": 30},
+                "entity_kind": "snips/musicArtist"
+            }
+        ]
+        self.assertTrue(is_file)
+        self.assertListEqual(expected_result, res)
+
+    def test_should_load_parser_from_bytes(self):
+        # Given
+        parser = BuiltinEntityParser.from_path(BUILTIN_PARSER_PATH)
+        with temp_dir() as tmpdir:
+            archive_path = tmpdir / "builtin_parser.tar"
+            parser.persist_to_archive(archive_path)
+            with archive_path.open("rb") as f:
+                parser_bytes = f.read()
+
+        # When
+        loaded_parser = BuiltinEntityParser.from_bytes(parser_bytes)
+        res = loaded_parser.parse("I want to listen to the stones", None)
+
+        # Then
+        expected_result = [
+            {
+                "value": "the stones",
+                "entity": {
+                    "kind": "MusicArtist",
+                    "value": "The Rolling Stones"
+                },
+                "alternatives": [],
+                "range": {"start": 20, "end": 30},
+                "entity_kind": "snips/musicArtist"
+            }
+        ]
+        self.assertListEqual(expected_result, res)
+
+    def test_should_raise_when_loading_parser_from_invalid_bytes(self):
+        with self.assertRaises(PersistenceError):
+            BuiltinEntityParser.from_bytes(b"not a parser archive")
+
+    def test_should_verify_persisted_parser(self):
+        # Given
+        parser = BuiltinEntityParser.from_path(BUILTIN_PARSER_PATH)
+
+        with temp_dir() as tmpdir:
+            persisted_path = tmpdir / "persisted_builtin_parser"
+            parser.persist(persisted_path)
+
+            # When
+            BuiltinEntityParser.verify(persisted_path)
+            with (persisted_path / "gazetteer_entity_parser" /
+                  "metadata.json").open("a") as f:
+                f.write(" ")
+
+            # Then
+            with self.assertRaises(IntegrityCheckError):
+                BuiltinEntityParser.verify(persisted_path)
+
+    def test_should_compute_stable_fingerprint(self):
+        # Given
+        parser = BuiltinEntityParser.from_path(BUILTIN_PARSER_PATH)
+
+        # When
+        fingerprint = parser.fingerprint()
+        other_fingerprint = parser.fingerprint()
+        en_fingerprint = BuiltinEntityParser.build("en").fingerprint()
+
+        # Then
+        self.assertEqual(64, len(fingerprint))
+        self.assertEqual(fingerprint, other_fingerprint)
+        self.assertNotEqual(fingerprint, en_fingerprint)
+
     def test_should_load_parser_with_gazetteer_entities_from_path(self):
         # Given
         parser = BuiltinEntityParser.from_path(BUILTIN_PARSER_PATH)
@@ -1,9 +1,14 @@
 from __future__ import unicode_literals
 
+import json
+import os
+import tarfile
 import unittest
 from builtins import str
 
-from snips_nlu_parsers import GazetteerEntityParser
+from snips_nlu_parsers import (
+    GazetteerEntityParser, GazetteerNotLoadedError, IntegrityCheckError,
+    InvalidGazetteerThresholdError, UnsafeLayoutError)
 from snips_nlu_parsers.tests.utils import ROOT_DIR
 from snips_nlu_parsers.utils import temp_dir
 
@@ -96,7 +101,8 @@ class TestGazetteerEntityParser(unittest.TestCase):
         parser = GazetteerEntityParser.build(parser_config)
 
         # When
-        res = parser.parse("I want to listen to the stones", None)
+        res = parser.parse("I want to listen to the stones", None,
+                           include_scores=True)
 
         # Then
         expected_result = [
@@ -104,6 +110,9 @@ class TestGazetteerEntityParser(unittest.TestCase):
                 "value": "the stones",
                 "resolved_value": "The Rolling Stones",
                 "alternative_resolved_values": [],
+                "score": 2 / 3.0,
+                "matched_tokens_ratio": 2 / 3.0,
+                "alternative_matches": [],
                 "range": {"start": 20, "end": 30},
                 "entity_identifier": "music_artist"
             }
@@ -111,6 +120,66 @@ class TestGazetteerEntityParser(unittest.TestCase):
 
         self.assertListEqual(expected_result, res)
 
+    def test_should_parse_with_offsets(self):
+        # Given
+        parser_config = self.get_test_parser_config()
+        parser = GazetteerEntityParser.build(parser_config)
+
+        # When
+        res = parser.parse("J'\u00e9coute \U0001F600 the stones",
+                           include_offsets=True)
+
+        # Then
+        expected_result = [
+            {
+                "value": "the stones",
+                "resolved_value": "The Rolling Stones",
+                "alternative_resolved_values": [],
+                "range": {"start": 11, "end": 21},
+                "byte_range": {"start": 15, "end": 25},
+                "utf16_range": {"start": 12, "end": 22},
+                "entity_identifier": "music_artist"
+            }
+        ]
+
+        self.assertListEqual(expected_result, res)
+
+    def test_should_parse_with_thresholds(self):
+        # Given
+        parser_config = self.get_test_parser_config()
+        parser = GazetteerEntityParser.build(parser_config)
+        partial_text = "I want to listen to the stones"
+        short_text = "I want to listen to stones"
+
+        # When
+        strict_res = parser.parse(partial_text,
+                                  thresholds={"music_artist": 0.7})
+        default_res = parser.parse(short_text)
+        loose_res = parser.parse(short_text,
+                                 thresholds={"music_artist": 0.3},
+                                 include_offsets=True)
+
+        # Then
+        self.assertListEqual([], strict_res)
+        self.assertListEqual([], default_res)
+        self.assertEqual(1, len(loose_res))
+        self.assertEqual("stones", loose_res[0]["value"])
+        self.assertEqual("The Rolling Stones",
+                         loose_res[0]["resolved_value"])
+        self.assertDictEqual({"start": 20, "end": 26},
+                             loose_res[0]["byte_range"])
+
+    def test_should_reject_invalid_thresholds(self):
+        # Given
+        parser_config = self.get_test_parser_config()
+        parser = GazetteerEntityParser.build(parser_config)
+        text = "I want to listen to the stones"
+
+        # When / Then
+        for threshold in [-0.1, 1.5, float("nan")]:
+            with self.assertRaises(InvalidGazetteerThresholdError):
+                parser.parse(text, thresholds={"music_artist": threshold})
+
     def test_should_parse_from_built_parser_with_scope(self):
         # Given
         parser_config = self.get_test_parser_config()
@@ -156,7 +225,8 @@ class TestGazetteerEntityParser(unittest.TestCase):
 
         # When
         text = "Play me the stones"
-        res = parser.parse(text, max_alternative_resolved_values=2)
+        res = parser.parse(text, max_alternative_resolved_values=2,
+                           include_scores=True)
 
         # Then
         expected_artist_result = [
@@ -167,6 +237,20 @@ class TestGazetteerEntityParser(unittest.TestCase):
                     "The Crying Stones",
                     "The Flying Stones"
                 ],
+                "score": 2 / 3.0,
+                "matched_tokens_ratio": 2 / 3.0,
+                "alternative_matches": [
+                    {
+                        "resolved_value": "The Crying Stones",
+                        "score": 2 / 3.0,
+                        "matched_tokens_ratio": 2 / 3.0
+                    },
+                    {
+                        "resolved_value": "The Flying Stones",
+                        "score": 2 / 3.0,
+                        "matched_tokens_ratio": 2 / 3.0
+                    }
+                ],
                 "range": {"start": 8, "end": 18},
                 "entity_identifier": "music_artist"
             }
@@ -183,6 +267,35 @@ class TestGazetteerEntityParser(unittest.TestCase):
 
         self.assertListEqual(expected_artist_result, res)
 
+    def test_should_parse_with_extended_entity(self):
+        # Given
+        parser_config = self.get_test_parser_config()
+        parser = GazetteerEntityParser.build(parser_config)
+        entity_values = [
+            {
+                "raw_value": "my favorite band",
+                "resolved_value": "My Favorite Band"
+            }
+        ]
+
+        # When
+        parser.extend_gazetteer_entity("music_artist", entity_values)
+        res = parser.parse("I want to listen to my favorite band")
+
+        # Then
+        expected_result = [
+            {
+                "value": "my favorite band",
+                "resolved_value": "My Favorite Band",
+                "alternative_resolved_values": [],
+                "range": {"start": 20, "end": 36},
+                "entity_identifier": "music_artist"
+            }
+        ]
+        self.assertListEqual(expected_result, res)
+        with self.assertRaises(GazetteerNotLoadedError):
+            parser.extend_gazetteer_entity("music_album", entity_values)
+
     def test_should_persist_parser(self):
         # Given
         parser_config = self.get_test_parser_config()
@@ -219,6 +332,112 @@ class TestGazetteerEntityParser(unittest.TestCase):
         self.assertEqual(expected_license_content, license_content)
         self.assertListEqual(expected_result, res)
 
+    def test_should_persist_parser_to_archive(self):
+        # Given
+        parser_config = self.get_test_parser_config()
+        parser = GazetteerEntityParser.build(parser_config)
+
+        # When
+        with temp_dir() as tmpdir:
+            archive_path = tmpdir / "gazetteer_parser.tar"
+            parser.persist_to_archive(archive_path)
+            with tarfile.open(str(archive_path)) as archive:
+                archived_files = [os.path.normpath(name)
+                                  for name in archive.getnames()]
+            loaded_parser = GazetteerEntityParser.from_archive(archive_path)
+
+        res = loaded_parser.parse("I want to listen to the stones", None)
+
+        # Then
+        expected_result = [
+            {
+                "value": "the stones",
+                "resolved_value": "The Rolling Stones",
+                "alternative_resolved_values": [],
+                "range": {"start": 20, "end": 30},
+                "entity_identifier": "music_artist"
+            }
+        ]
+        self.assertIn(os.path.join("parser_1", "LICENSE"), archived_files)
+        self.assertListEqual(expected_result, res)
+
+    def test_should_verify_persisted_parser(self):
+        # Given
+        parser_config = self.get_test_parser_config()
+        parser = GazetteerEntityParser.build(parser_config)
+
+        with temp_dir() as tmpdir:
+            persisted_path = tmpdir / "persisted_gazetteer_parser"
+            parser.persist(persisted_path)
+
+            # When
+            GazetteerEntityParser.verify(persisted_path)
+            (persisted_path / "parser_1" / "LICENSE").unlink()
+
+            # Then
+            with self.assertRaises(IntegrityCheckError):
+                GazetteerEntityParser.verify(persisted_path)
+
+    def test_should_not_follow_unconfined_paths_in_untrusted_mode(self):
+        # Given
+        parser_config = self.get_test_parser_config()
+        parser = GazetteerEntityParser.build(parser_config)
+
+        with temp_dir() as tmpdir:
+            persisted_path = tmpdir / "persisted_gazetteer_parser"
+            parser.persist(persisted_path)
+            metadata_path = persisted_path / "metadata.json"
+            with metadata_path.open(encoding="utf8") as f:
+                metadata = json.load(f)
+            metadata["parsers_metadata"][0]["entity_parser"] = \
+                "../persisted_gazetteer_parser/parser_1"
+            with metadata_path.open(mode="w", encoding="utf8") as f:
+                f.write(str(json.dumps(metadata)))
+
+            # When / Then
+            GazetteerEntityParser.from_path(persisted_path)
+            with self.assertRaises(UnsafeLayoutError):
+                GazetteerEntityParser.from_path(persisted_path, untrusted=True)
+
+    def test_should_limit_file_sizes_in_untrusted_mode(self):
+        # Given
+        loading_limits = {"max_file_size": 10}
+
+        # When / Then
+        GazetteerEntityParser.from_path(CUSTOM_PARSER_PATH, untrusted=True)
+        with self.assertRaises(UnsafeLayoutError):
+            GazetteerEntityParser.from_path(
+                CUSTOM_PARSER_PATH, untrusted=True,
+                loading_limits=loading_limits)
+
+    def test_should_check_archive_entries_in_untrusted_mode(self):
+        # Given
+        parser = GazetteerEntityParser.from_path(CUSTOM_PARSER_PATH)
+        loading_limits = {"max_file_size": 10}
+
+        with temp_dir() as tmpdir:
+            archive_path = tmpdir / "gazetteer_parser.tar"
+            parser.persist_to_archive(archive_path)
+            with archive_path.open("rb") as f:
+                parser_bytes = f.read()
+            linked_archive_path = tmpdir / "linked_gazetteer_parser.tar"
+            with tarfile.open(str(linked_archive_path), "w") as archive:
+                link = tarfile.TarInfo("metadata.json")
+                link.type = tarfile.SYMTYPE
+                link.linkname = "/etc/passwd"
+                archive.addfile(link)
+
+            # When / Then
+            GazetteerEntityParser.from_archive(archive_path, untrusted=True)
+            GazetteerEntityParser.from_bytes(parser_bytes, untrusted=True)
+            with self.assertRaises(UnsafeLayoutError):
+                GazetteerEntityParser.from_archive(linked_archive_path,
+                                                   untrusted=True)
+            with self.assertRaises(UnsafeLayoutError):
+                GazetteerEntityParser.from_bytes(
+                    parser_bytes, untrusted=True,
+                    loading_limits=loading_limits)
+
     def test_should_load_parser_from_path(self):
         # Given
         parser = GazetteerEntityParser.from_path(CUSTOM_PARSER_PATH)
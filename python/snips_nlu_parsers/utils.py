@@ -1,10 +1,15 @@
+import json
 import shutil
 from _ctypes import POINTER, Structure, byref
+from builtins import bytes
 from contextlib import contextmanager
 from ctypes import c_char_p, c_int32, cdll, string_at
 from pathlib import Path
 from tempfile import mkdtemp
 
+from snips_nlu_parsers.errors import (
+    ERRORS_BY_CODE, InvalidGazetteerThresholdError, SnipsNluParsersError)
+
 PACKAGE_PATH = Path(__file__).absolute().parent
 
 dylib_dir = PACKAGE_PATH / "dylib"
@@ -44,11 +49,31 @@ def temp_dir():
         shutil.rmtree(tmp_dir)
 
 
+def loading_mode_json(untrusted, loading_limits):
+    if untrusted:
+        loading_mode = {"untrusted": loading_limits or dict()}
+    else:
+        loading_mode = "trusted"
+    return bytes(json.dumps(loading_mode), encoding="utf8")
+
+
+def check_gazetteer_thresholds(thresholds):
+    # NaN and infinite thresholds cannot be sent as JSON, so thresholds are
+    # checked before reaching the parser, which applies the same check
+    for entity, threshold in thresholds.items():
+        if not 0.0 <= threshold <= 1.0:
+            raise InvalidGazetteerThresholdError(
+                "Invalid threshold %s for gazetteer entity '%s', expected a "
+                "number between 0 and 1" % (threshold, entity))
+
+
 def check_ffi_error(exit_code, error_context_msg):
     if exit_code != 0:
+        error_code = lib.snips_nlu_parsers_get_last_error_code()
+        error_cls = ERRORS_BY_CODE.get(error_code, SnipsNluParsersError)
         with string_pointer(c_char_p()) as ptr:
             if lib.snips_nlu_parsers_get_last_error(byref(ptr)) == 0:
                 ffi_error_message = string_at(ptr).decode("utf8")
             else:
                 ffi_error_message = "see stderr"
-        raise ValueError("%s: %s" % (error_context_msg, ffi_error_message))
+        raise error_cls("%s: %s" % (error_context_msg, ffi_error_message))
@@ -1,31 +1,97 @@
+use crate::conflict_resolution::ConflictResolution;
+use crate::context::ExtractionContext;
 use crate::conversion::*;
-use crate::errors::*;
-use crate::gazetteer_parser::GazetteerParser;
+use crate::errors::{ErrorKind, Result};
+use crate::gazetteer_parser::{check_thresholds, GazetteerParser};
+use crate::normalization::NormalizedSentence;
+use crate::offsets::{add_offsets, WithOffsets};
 use crate::parsable::ParsableLanguage;
-use crate::utils::{get_ranges_mapping, NON_SPACE_REGEX, NON_SPACE_SEPARATED_LANGUAGES};
-use failure::{format_err, ResultExt};
+use crate::persistence::{
+    compute_checksums, compute_fingerprint, confine_path, load_archive, persist_archive,
+    persist_with_staging, read_archive, verify_checksums, write_archive, write_metadata, Checksums,
+    LoadingMode, PersistMode, PersistenceVersions,
+};
+use crate::tokenization::{
+    whitespace_tokenize, BoundaryAdjustment, BoundaryAlignment, JoinedSentence, Tokenization,
+    Tokenizer,
+};
+use chrono::Local;
+use chrono_tz::Tz;
+use failure::ResultExt;
 pub use gazetteer_entity_parser::EntityValue;
-use itertools::Itertools;
-use rustling_ontology::{build_parser, OutputKind, Parser as RustlingParser, ResolverContext};
+use lazy_static::lazy_static;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use rustling_ontology::output::Output;
+use rustling_ontology::{
+    build_raw_parser, CandidateTagger, OutputKind, RawParser as RustlingParser, ResolverContext,
+};
 use serde::{Deserialize, Serialize};
 use snips_nlu_ontology::*;
 use snips_nlu_utils::string::{convert_to_byte_range, convert_to_char_index};
+use std::collections::HashMap;
 use std::fs;
+use std::io::{Read, Write};
 use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
 
 pub struct BuiltinEntityParser {
     gazetteer_parser: Option<GazetteerParser<BuiltinGazetteerEntityKind>>,
-    rustling_parser: RustlingParser,
+    grammar_parser: Option<Arc<dyn GrammarParser>>,
     language: Language,
     rustling_entity_kinds: Vec<BuiltinEntityKind>,
+    entity_kinds: Option<Vec<BuiltinEntityKind>>,
+    conflict_resolution: ConflictResolution,
+    grammar_error_mode: GrammarErrorMode,
+    tokenization: Option<Tokenization>,
+    tokenizer: Option<Arc<dyn Tokenizer>>,
+    boundary_alignment: BoundaryAlignment,
+}
+
+/// Behavior of the parser when rustling fails to parse a sentence
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GrammarErrorMode {
+    /// The extraction fails with an `ErrorKind::GrammarParsing` error
+    Strict,
+    /// No grammar entity is returned and the error is reported in the `ExtractionDiagnostics`
+    Lenient,
+}
+
+impl Default for GrammarErrorMode {
+    fn default() -> Self {
+        GrammarErrorMode::Lenient
+    }
+}
+
+/// Non-fatal issues which occurred during an extraction
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ExtractionDiagnostics {
+    pub grammar_errors: Vec<String>,
+    #[serde(default)]
+    pub boundary_adjustments: Vec<BoundaryAdjustment>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct BuiltinEntityParserLoader {
     language: Language,
     gazetteer_parser_path: Option<PathBuf>,
+    #[serde(default)]
+    entity_kinds: Option<Vec<BuiltinEntityKind>>,
+    #[serde(default)]
+    conflict_resolution: ConflictResolution,
+    #[serde(default)]
+    grammar_error_mode: GrammarErrorMode,
+    #[serde(default)]
+    loading_mode: LoadingMode,
+    #[serde(default)]
+    tokenization: Option<Tokenization>,
+    #[serde(skip)]
+    tokenizer: Option<Arc<dyn Tokenizer>>,
+    #[serde(default)]
+    boundary_alignment: BoundaryAlignment,
 }
 
 impl BuiltinEntityParserLoader {
@@ -33,6 +99,13 @@ impl BuiltinEntityParserLoader {
         BuiltinEntityParserLoader {
             language,
             gazetteer_parser_path: None,
+            entity_kinds: None,
+            conflict_resolution: ConflictResolution::default(),
+            grammar_error_mode: GrammarErrorMode::default(),
+            loading_mode: LoadingMode::default(),
+            tokenization: None,
+            tokenizer: None,
+            boundary_alignment: BoundaryAlignment::default(),
         }
     }
 
@@ -41,30 +114,244 @@ impl BuiltinEntityParserLoader {
         self
     }
 
+    /// Restricts the parser to the provided entity kinds: the gazetteer parsers are only loaded
+    /// for these kinds, the grammar is not loaded at all when none of them is a grammar kind, and
+    /// extracting any other kind results in an error
+    ///
+    /// rustling-ontology only builds the grammar of all the entity kinds of a language, so
+    /// allowing a single grammar kind does not reduce the memory used by the grammar. The grammar
+    /// is shared by the parsers of the language which are alive at the same time, and freed with
+    /// the last of them.
+    pub fn entity_kinds(&mut self, entity_kinds: Vec<BuiltinEntityKind>) -> &mut Self {
+        self.entity_kinds = Some(entity_kinds);
+        self
+    }
+
+    pub fn conflict_resolution(&mut self, conflict_resolution: ConflictResolution) -> &mut Self {
+        self.conflict_resolution = conflict_resolution;
+        self
+    }
+
+    pub fn grammar_error_mode(&mut self, grammar_error_mode: GrammarErrorMode) -> &mut Self {
+        self.grammar_error_mode = grammar_error_mode;
+        self
+    }
+
+    /// Sets the `LoadingMode` applied to the gazetteer parser
+    pub fn loading_mode(&mut self, loading_mode: LoadingMode) -> &mut Self {
+        self.loading_mode = loading_mode;
+        self
+    }
+
+    /// Overrides the `Tokenization` used for the language, see
+    /// `Tokenization::default_for_language`
+    pub fn tokenization(&mut self, tokenization: Tokenization) -> &mut Self {
+        self.tokenization = Some(tokenization);
+        self
+    }
+
+    /// Uses a custom `Tokenizer`, which takes precedence over the `Tokenization`
+    ///
+    /// Custom tokenizers are not persisted: parsers loaded from disk use the persisted
+    /// `Tokenization` instead.
+    pub fn tokenizer(&mut self, tokenizer: Arc<dyn Tokenizer>) -> &mut Self {
+        self.tokenizer = Some(tokenizer);
+        self
+    }
+
+    /// Sets how entities which do not start or end on token boundaries are handled when the
+    /// sentence is tokenized, adjustments being reported in the `ExtractionDiagnostics`
+    pub fn boundary_alignment(&mut self, boundary_alignment: BoundaryAlignment) -> &mut Self {
+        self.boundary_alignment = boundary_alignment;
+        self
+    }
+
     pub fn load(&self) -> Result<BuiltinEntityParser> {
         let supported_entity_kinds = self.language.supported_entity_kinds();
-        let ordered_entity_kinds = OutputKind::all()
+        let ordered_entity_kinds: Vec<BuiltinEntityKind> = OutputKind::all()
             .iter()
             .map(|output_kind| output_kind.ontology_into())
             .filter(|builtin_entity_kind| supported_entity_kinds.contains(&builtin_entity_kind))
+            .filter(|builtin_entity_kind| self.is_allowed(builtin_entity_kind))
             .collect();
-        let rustling_parser = build_parser(self.language.ontology_into()).map_err(|_| {
-            format_err!(
-                "Cannot create Rustling Parser for language {:?}",
-                self.language
-            )
-        })?;
+        let grammar_parser = if ordered_entity_kinds.is_empty() {
+            None
+        } else {
+            let rustling_parser: Arc<dyn GrammarParser> = get_rustling_parser(self.language)?;
+            Some(rustling_parser)
+        };
+        let gazetteer_entity_kinds: Option<Vec<BuiltinGazetteerEntityKind>> =
+            self.entity_kinds.as_ref().map(|kinds| {
+                kinds
+                    .iter()
+                    .flat_map(|kind| kind.try_into_gazetteer_kind().ok())
+                    .collect()
+            });
         let gazetteer_parser = match &self.gazetteer_parser_path {
-            Some(parser_path) => Some(GazetteerParser::from_path(parser_path)?),
+            Some(parser_path) => Some(GazetteerParser::load_from_path(
+                parser_path,
+                gazetteer_entity_kinds.as_ref().map(|kinds| &**kinds),
+                &self.loading_mode,
+            )?),
             None => None,
         };
         Ok(BuiltinEntityParser {
             gazetteer_parser,
-            rustling_parser,
+            grammar_parser,
             language: self.language,
             rustling_entity_kinds: ordered_entity_kinds,
+            entity_kinds: self.entity_kinds.clone(),
+            conflict_resolution: self.conflict_resolution.clone(),
+            grammar_error_mode: self.grammar_error_mode,
+            tokenization: self.tokenization,
+            tokenizer: self.tokenizer.clone().or_else(|| {
+                self.tokenization
+                    .unwrap_or_else(|| Tokenization::default_for_language(self.language))
+                    .tokenizer()
+            }),
+            boundary_alignment: self.boundary_alignment,
         })
     }
+
+    fn is_allowed(&self, entity_kind: &BuiltinEntityKind) -> bool {
+        self.entity_kinds
+            .as_ref()
+            .map(|kinds| kinds.contains(entity_kind))
+            .unwrap_or(true)
+    }
+}
+
+lazy_static! {
+    /// Rustling parsers in use, indexed by language, which are freed once the last parser using
+    /// them is dropped
+    static ref RUSTLING_PARSERS: Mutex<HashMap<Language, Weak<RustlingParser>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Returns the rustling parser of the language, which is shared with the other parsers of the
+/// language which are alive, or built otherwise
+///
+/// Rustling only builds the full grammar of a language, so the same parser is used whatever the
+/// allowed entity kinds. The grammar is built without holding the lock of the cache, so that
+/// parsers of other languages can be loaded meanwhile, and parsers of the same language loaded
+/// concurrently may each build the grammar, only one of them being kept.
+fn get_rustling_parser(language: Language) -> Result<Arc<RustlingParser>> {
+    if let Some(rustling_parser) = lock_rustling_parsers()
+        .get(&language)
+        .and_then(|rustling_parser| rustling_parser.upgrade())
+    {
+        return Ok(rustling_parser);
+    }
+    let rustling_parser = Arc::new(build_raw_parser(language.ontology_into()).map_err(|_| {
+        ErrorKind::ParserBuild(format!(
+            "Cannot create Rustling Parser for language {:?}",
+            language
+        ))
+    })?);
+    let mut rustling_parsers = lock_rustling_parsers();
+    if let Some(cached_parser) = rustling_parsers
+        .get(&language)
+        .and_then(|rustling_parser| rustling_parser.upgrade())
+    {
+        // Another parser of the language was loaded in the meantime
+        return Ok(cached_parser);
+    }
+    rustling_parsers.retain(|_, rustling_parser| rustling_parser.upgrade().is_some());
+    rustling_parsers.insert(language, Arc::downgrade(&rustling_parser));
+    Ok(rustling_parser)
+}
+
+fn lock_rustling_parsers() -> MutexGuard<'static, HashMap<Language, Weak<RustlingParser>>> {
+    RUSTLING_PARSERS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Maximum number of thread pools kept for batch extractions, each of them holding its threads
+const MAX_THREAD_POOLS: usize = 4;
+
+lazy_static! {
+    /// Thread pools used for batch extractions, indexed by number of threads, least recently used
+    /// first
+    static ref THREAD_POOLS: Mutex<Vec<(usize, Arc<ThreadPool>)>> = Mutex::new(vec![]);
+}
+
+/// Returns a pool of `num_threads` threads, which is only built the first time it is needed
+fn get_thread_pool(num_threads: usize) -> Result<Arc<ThreadPool>> {
+    let mut thread_pools = THREAD_POOLS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(index) = thread_pools
+        .iter()
+        .position(|(pool_num_threads, _)| *pool_num_threads == num_threads)
+    {
+        let thread_pool = thread_pools.remove(index);
+        let pool = thread_pool.1.clone();
+        thread_pools.push(thread_pool);
+        return Ok(pool);
+    }
+    let pool = Arc::new(
+        ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .map_err(|error| ErrorKind::ThreadPool(error.to_string()))?,
+    );
+    if thread_pools.len() >= MAX_THREAD_POOLS {
+        // The threads of the evicted pool stop once the batches still using it are done
+        thread_pools.remove(0);
+    }
+    thread_pools.push((num_threads, pool.clone()));
+    Ok(pool)
+}
+
+/// Parser of grammar entities, which lets tests replace rustling with a parser which fails
+trait GrammarParser: Send + Sync {
+    /// Returns the candidate readings of the spans of the sentence, or the message of the error
+    /// which prevented parsing it
+    fn parse_candidates(
+        &self,
+        sentence: &str,
+        output_kinds: &[OutputKind],
+        context: &ResolverContext,
+        resolve_all_candidates: bool,
+    ) -> std::result::Result<Vec<GrammarCandidate>, String>;
+}
+
+/// Reading of a span of the sentence, tagged when it is the best reading of this span
+struct GrammarCandidate {
+    value: Option<Output>,
+    byte_range: (usize, usize),
+    char_range: (usize, usize),
+    is_12_hour_clock_time: bool,
+    tagged: bool,
+}
+
+impl GrammarParser for RustlingParser {
+    fn parse_candidates(
+        &self,
+        sentence: &str,
+        output_kinds: &[OutputKind],
+        context: &ResolverContext,
+        resolve_all_candidates: bool,
+    ) -> std::result::Result<Vec<GrammarCandidate>, String> {
+        let tagger = CandidateTagger {
+            output_kind_filter: output_kinds,
+            context,
+            resolve_all_candidates,
+        };
+        Ok(self
+            .candidates(sentence, &tagger)
+            .map_err(|error| error.to_string())?
+            .into_iter()
+            .map(|candidate| GrammarCandidate {
+                is_12_hour_clock_time: rustling::is_12_hour_clock_time(&candidate.node.value),
+                byte_range: (candidate.match_.byte_range.0, candidate.match_.byte_range.1),
+                char_range: (candidate.match_.char_range.0, candidate.match_.char_range.1),
+                value: candidate.match_.value,
+                tagged: candidate.tagged,
+            })
+            .collect())
+    }
 }
 
 impl BuiltinEntityParser {
@@ -74,18 +361,129 @@ impl BuiltinEntityParser {
         filter_entity_kinds: Option<&[BuiltinEntityKind]>,
         max_alternative_resolved_values: usize,
     ) -> Result<Vec<BuiltinEntity>> {
-        if NON_SPACE_SEPARATED_LANGUAGES.contains(&self.language) {
-            self._extract_entities_for_non_space_separated(
+        self.extract_entities_with_context(
+            sentence,
+            filter_entity_kinds,
+            max_alternative_resolved_values,
+            &ExtractionContext::default(),
+        )
+    }
+
+    pub fn extract_entities_with_context(
+        &self,
+        sentence: &str,
+        filter_entity_kinds: Option<&[BuiltinEntityKind]>,
+        max_alternative_resolved_values: usize,
+        context: &ExtractionContext,
+    ) -> Result<Vec<BuiltinEntity>> {
+        self.extract_entities_with_diagnostics(
+            sentence,
+            filter_entity_kinds,
+            max_alternative_resolved_values,
+            context,
+        )
+        .map(|(entities, _)| entities)
+    }
+
+    /// Extracts entities along with their ranges expressed in bytes and in UTF-16 code units
+    pub fn extract_entities_with_offsets(
+        &self,
+        sentence: &str,
+        filter_entity_kinds: Option<&[BuiltinEntityKind]>,
+        max_alternative_resolved_values: usize,
+        context: &ExtractionContext,
+    ) -> Result<Vec<WithOffsets<BuiltinEntity>>> {
+        let entities = self.extract_entities_with_context(
+            sentence,
+            filter_entity_kinds,
+            max_alternative_resolved_values,
+            context,
+        )?;
+        Ok(add_offsets(sentence, entities))
+    }
+
+    /// Extracts entities from each of the provided sentences
+    ///
+    /// Sentences are processed sequentially on the calling thread when `num_threads` is 1, on the
+    /// global rayon pool, which has one thread per CPU by default, when it is 0, and in parallel on
+    /// a pool of `num_threads` threads otherwise. Such pools are shared by all the batches of the
+    /// process, and only the `MAX_THREAD_POOLS` most recently used ones are kept. The batch fails
+    /// as soon as one of the sentences fails.
+    pub fn extract_entities_batch(
+        &self,
+        sentences: &[&str],
+        filter_entity_kinds: Option<&[BuiltinEntityKind]>,
+        max_alternative_resolved_values: usize,
+        context: &ExtractionContext,
+        num_threads: usize,
+    ) -> Result<Vec<Vec<BuiltinEntity>>> {
+        let extract = |sentence: &&str| {
+            self.extract_entities_with_context(
                 sentence,
                 filter_entity_kinds,
                 max_alternative_resolved_values,
+                context,
             )
+        };
+        match num_threads {
+            0 => sentences.par_iter().map(extract).collect(),
+            1 => sentences.iter().map(extract).collect(),
+            num_threads => get_thread_pool(num_threads)?
+                .install(|| sentences.par_iter().map(extract).collect()),
+        }
+    }
+
+    pub fn extract_entities_with_diagnostics(
+        &self,
+        sentence: &str,
+        filter_entity_kinds: Option<&[BuiltinEntityKind]>,
+        max_alternative_resolved_values: usize,
+        context: &ExtractionContext,
+    ) -> Result<(Vec<BuiltinEntity>, ExtractionDiagnostics)> {
+        self.check_entity_kinds_are_loaded(filter_entity_kinds)?;
+        let mut diagnostics = ExtractionDiagnostics::default();
+        let entities = if let Some(tokenizer) = &self.tokenizer {
+            self._extract_entities_from_tokens(
+                &**tokenizer,
+                sentence,
+                filter_entity_kinds,
+                max_alternative_resolved_values,
+                context,
+                &mut diagnostics,
+            )?
         } else {
             self._extract_entities(
                 sentence,
                 filter_entity_kinds,
                 max_alternative_resolved_values,
-            )
+                context,
+                &mut diagnostics,
+            )?
+        };
+        Ok((entities, diagnostics))
+    }
+
+    /// Returns the byte ranges of the tokens of `sentence`, as split by the tokenizer of the
+    /// parser, or on whitespace when the parser has none
+    pub(crate) fn tokenize(&self, sentence: &str) -> Vec<Range<usize>> {
+        match &self.tokenizer {
+            Some(tokenizer) => tokenizer.tokenize(sentence),
+            None => whitespace_tokenize(sentence),
+        }
+    }
+
+    fn check_entity_kinds_are_loaded(
+        &self,
+        filter_entity_kinds: Option<&[BuiltinEntityKind]>,
+    ) -> Result<()> {
+        match (&self.entity_kinds, filter_entity_kinds) {
+            (Some(loaded_kinds), Some(requested_kinds)) => requested_kinds
+                .iter()
+                .find(|kind| !loaded_kinds.contains(kind))
+                .map_or(Ok(()), |kind| {
+                    Err(ErrorKind::EntityKindNotLoaded(kind.identifier().to_string()).into())
+                }),
+            _ => Ok(()),
         }
     }
 
@@ -94,8 +492,11 @@ impl BuiltinEntityParser {
         sentence: &str,
         filter_entity_kinds: Option<&[BuiltinEntityKind]>,
         max_alternative_resolved_values: usize,
+        context: &ExtractionContext,
+        diagnostics: &mut ExtractionDiagnostics,
     ) -> Result<Vec<BuiltinEntity>> {
-        let context = ResolverContext::default();
+        check_thresholds(&context.gazetteer_thresholds)?;
+        let rustling_context = context.rustling_context();
         let rustling_output_kinds = self
             .rustling_entity_kinds
             .iter()
@@ -109,13 +510,14 @@ impl BuiltinEntityParser {
         let rustling_entities = if rustling_output_kinds.is_empty() {
             vec![]
         } else {
-            self.rustling_parser
-                .parse_with_kind_order(&sentence.to_lowercase(), &context, &rustling_output_kinds)
-                .unwrap_or_else(|_| vec![])
-                .into_iter()
-                .map(|parser_match| rustling::convert_to_builtin(sentence, parser_match))
-                .sorted_by(|a, b| Ord::cmp(&a.range.start, &b.range.start))
-                .collect()
+            let entities = self._extract_rustling_entities(
+                sentence,
+                &rustling_output_kinds,
+                &rustling_context,
+                context.timezone,
+                max_alternative_resolved_values,
+            );
+            self._recover_from_grammar_error(entities, diagnostics)?
         };
 
         let mut gazetteer_entities = match &self.gazetteer_parser {
@@ -127,10 +529,11 @@ impl BuiltinEntityParser {
                             .flat_map(|kind| kind.try_into_gazetteer_kind().ok())
                             .collect()
                     });
-                gazetteer_parser.extract_builtin_entities(
+                gazetteer_parser.extract_builtin_entities_with_thresholds(
                     sentence,
                     gazetteer_entity_kinds.as_ref().map(|kinds| &**kinds),
                     max_alternative_resolved_values,
+                    &context.gazetteer_thresholds,
                 )?
             }
             None => vec![],
@@ -138,71 +541,163 @@ impl BuiltinEntityParser {
 
         let mut entities = rustling_entities;
         entities.append(&mut gazetteer_entities);
-        Ok(entities)
+        Ok(self.conflict_resolution.resolve(entities))
     }
 
-    pub fn _extract_entities_for_non_space_separated(
+    /// Extracts grammar entities in a single rustling pass
+    ///
+    /// Rustling tags the best reading of each span according to the order of output kinds. When
+    /// alternatives are requested, the other candidates found on the same span are resolved as
+    /// well and returned as alternatives, after the other half of the day for times which can be
+    /// read on a 12-hour clock, such as "at 5".
+    fn _extract_rustling_entities(
         &self,
         sentence: &str,
-        filter_entity_kinds: Option<&[BuiltinEntityKind]>,
+        output_kinds: &[OutputKind],
+        rustling_context: &ResolverContext,
+        timezone: Option<Tz>,
         max_alternative_resolved_values: usize,
     ) -> Result<Vec<BuiltinEntity>> {
-        let original_tokens_bytes_ranges: Vec<Range<usize>> = NON_SPACE_REGEX
-            .find_iter(sentence)
-            .map(|m| m.start()..m.end())
-            .collect();
+        let grammar_parser = match &self.grammar_parser {
+            Some(grammar_parser) => grammar_parser,
+            None => return Ok(vec![]),
+        };
+        let normalized_sentence = NormalizedSentence::lowercase(sentence);
+        let (tagged_candidates, competing_candidates): (Vec<_>, Vec<_>) = grammar_parser
+            .parse_candidates(
+                normalized_sentence.normalized(),
+                output_kinds,
+                rustling_context,
+                max_alternative_resolved_values > 0,
+            )
+            .map_err(|message| ErrorKind::GrammarParsing {
+                sentence: sentence.to_string(),
+                message,
+            })?
+            .into_iter()
+            .partition(|candidate| candidate.tagged);
+        let to_timezone = |slot_value: SlotValue| match timezone {
+            Some(timezone) => rustling::convert_to_timezone(slot_value, timezone),
+            None => slot_value,
+        };
+        let other_half_of_day = |slot_value: &SlotValue| match timezone {
+            Some(timezone) => rustling::other_half_of_day(slot_value, &timezone),
+            None => rustling::other_half_of_day(slot_value, &Local),
+        };
 
-        let joined_sentence = original_tokens_bytes_ranges
+        Ok(tagged_candidates
             .iter()
-            .map(|r| &sentence[r.clone()])
-            .join("");
+            .filter_map(|candidate| {
+                let value = candidate.value.clone()?;
+                let entity = rustling::convert_to_builtin(
+                    normalized_sentence.normalized(),
+                    candidate.byte_range,
+                    candidate.char_range,
+                    value,
+                );
+                let entity_value = to_timezone(entity.entity);
+                let mut alternatives = vec![];
+                if max_alternative_resolved_values > 0 {
+                    if candidate.is_12_hour_clock_time {
+                        alternatives.extend(other_half_of_day(&entity_value));
+                    }
+                    let competing_values = competing_candidates
+                        .iter()
+                        .filter(|competing| competing.char_range == candidate.char_range)
+                        .filter_map(|competing| competing.value.clone())
+                        .filter(|competing_value| output_kinds.contains(&competing_value.kind()))
+                        .map(|competing_value| to_timezone(competing_value.ontology_into()));
+                    for competing_value in competing_values {
+                        if competing_value != entity_value
+                            && !alternatives.contains(&competing_value)
+                        {
+                            alternatives.push(competing_value);
+                        }
+                    }
+                    alternatives.truncate(max_alternative_resolved_values);
+                }
+                let range = normalized_sentence.original_char_range(&entity.range);
+                Some(BuiltinEntity {
+                    value: normalized_sentence.original_substring(&range).to_string(),
+                    range,
+                    entity: entity_value,
+                    alternatives,
+                    entity_kind: entity.entity_kind,
+                })
+            })
+            .collect())
+    }
 
-        if original_tokens_bytes_ranges.is_empty() {
-            return Ok(vec![]);
+    fn _recover_from_grammar_error(
+        &self,
+        result: Result<Vec<BuiltinEntity>>,
+        diagnostics: &mut ExtractionDiagnostics,
+    ) -> Result<Vec<BuiltinEntity>> {
+        match (result, self.grammar_error_mode) {
+            (Ok(entities), _) => Ok(entities),
+            (Err(error), GrammarErrorMode::Strict) => Err(error),
+            (Err(error), GrammarErrorMode::Lenient) => {
+                diagnostics.grammar_errors.push(error.to_string());
+                Ok(vec![])
+            }
         }
+    }
 
-        let ranges_mapping = get_ranges_mapping(&original_tokens_bytes_ranges);
+    /// Extracts entities from the sentence made of the joined tokens, and aligns them on token
+    /// boundaries according to the `BoundaryAlignment`
+    fn _extract_entities_from_tokens(
+        &self,
+        tokenizer: &dyn Tokenizer,
+        sentence: &str,
+        filter_entity_kinds: Option<&[BuiltinEntityKind]>,
+        max_alternative_resolved_values: usize,
+        context: &ExtractionContext,
+        diagnostics: &mut ExtractionDiagnostics,
+    ) -> Result<Vec<BuiltinEntity>> {
+        let joined_sentence = JoinedSentence::new(sentence, tokenizer);
+        if joined_sentence.is_empty() {
+            return Ok(vec![]);
+        }
 
-        Ok(self
-            ._extract_entities(
-                &*joined_sentence,
-                filter_entity_kinds,
-                max_alternative_resolved_values,
-            )?
-            .into_iter()
-            .filter_map(|ent| {
-                let byte_range = convert_to_byte_range(&*joined_sentence, &ent.range);
-                let start = byte_range.start;
-                let end = byte_range.end;
-                // Check if match range correspond to original tokens otherwise skip the entity
-                if (start == 0 as usize || ranges_mapping.contains_key(&start))
-                    && (ranges_mapping.contains_key(&end))
-                {
-                    let start_token_index = if start == 0 as usize {
-                        0 as usize
-                    } else {
-                        ranges_mapping[&start] + 1
-                    };
-                    let end_token_index = ranges_mapping[&end];
-
-                    let original_start = original_tokens_bytes_ranges[start_token_index].start;
-                    let original_end = original_tokens_bytes_ranges[end_token_index].end;
-                    let value = sentence[original_start..original_end].to_string();
-
-                    let original_ent = BuiltinEntity {
-                        value,
-                        range: convert_to_char_index(&sentence, original_start)
-                            ..convert_to_char_index(&sentence, original_end),
-                        entity: ent.entity,
-                        alternatives: ent.alternatives,
-                        entity_kind: ent.entity_kind,
-                    };
-                    Some(original_ent)
-                } else {
-                    None
-                }
-            })
-            .collect())
+        let char_range = |byte_range: &Range<usize>| {
+            convert_to_char_index(&sentence, byte_range.start)
+                ..convert_to_char_index(&sentence, byte_range.end)
+        };
+        let mut entities = vec![];
+        for ent in self._extract_entities(
+            joined_sentence.joined(),
+            filter_entity_kinds,
+            max_alternative_resolved_values,
+            context,
+            diagnostics,
+        )? {
+            let byte_range = convert_to_byte_range(joined_sentence.joined(), &ent.range);
+            let (original_range, adjusted) = match joined_sentence
+                .aligned_original_byte_range(&byte_range, self.boundary_alignment)
+            {
+                Some(aligned_range) => aligned_range,
+                None => continue,
+            };
+            let range = char_range(&original_range);
+            if adjusted {
+                diagnostics.boundary_adjustments.push(BoundaryAdjustment {
+                    entity_kind: ent.entity_kind,
+                    matched_range: char_range(&joined_sentence.original_byte_range(&byte_range)),
+                    range: range.clone(),
+                });
+            }
+            entities.push(BuiltinEntity {
+                value: joined_sentence
+                    .original_substring(&original_range)
+                    .to_string(),
+                range,
+                entity: ent.entity,
+                alternatives: ent.alternatives,
+                entity_kind: ent.entity_kind,
+            });
+        }
+        // Adjusted entities may overlap other entities
+        Ok(self.conflict_resolution.resolve(entities))
     }
 }
 
@@ -212,82 +707,268 @@ impl BuiltinEntityParser {
         entity_kind: BuiltinGazetteerEntityKind,
         entity_values: impl Iterator<Item = EntityValue>,
     ) -> Result<()> {
+        self.get_gazetteer_parser_mut(entity_kind)?
+            .extend_gazetteer_entity(entity_kind, entity_values)
+    }
+
+    pub fn remove_gazetteer_values(
+        &mut self,
+        entity_kind: BuiltinGazetteerEntityKind,
+        raw_values: &[String],
+    ) -> Result<()> {
+        self.get_gazetteer_parser_mut(entity_kind)?
+            .remove_gazetteer_values(entity_kind, raw_values)
+    }
+
+    pub fn replace_gazetteer_values(
+        &mut self,
+        entity_kind: BuiltinGazetteerEntityKind,
+        entity_values: impl Iterator<Item = EntityValue>,
+    ) -> Result<()> {
+        self.get_gazetteer_parser_mut(entity_kind)?
+            .replace_gazetteer_values(entity_kind, entity_values)
+    }
+
+    pub fn reset_gazetteer_entity(
+        &mut self,
+        entity_kind: BuiltinGazetteerEntityKind,
+    ) -> Result<()> {
+        self.get_gazetteer_parser_mut(entity_kind)?
+            .reset_gazetteer_entity(entity_kind)
+    }
+
+    fn get_gazetteer_parser_mut(
+        &mut self,
+        entity_kind: BuiltinGazetteerEntityKind,
+    ) -> Result<&mut GazetteerParser<BuiltinGazetteerEntityKind>> {
         self.gazetteer_parser
             .as_mut()
-            .map(|gazetteer_parser| {
-                gazetteer_parser.extend_gazetteer_entity(entity_kind, entity_values)
-            })
-            .transpose()?
-            .ok_or_else(|| format_err!("No gazetteer parser found for entity '{:?}'", entity_kind))
+            .ok_or_else(|| ErrorKind::GazetteerNotLoaded(format!("{:?}", entity_kind)).into())
     }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct BuiltinParserMetadata {
+    #[serde(flatten)]
+    pub versions: PersistenceVersions,
     pub language: String,
     pub gazetteer_parser: Option<String>,
+    #[serde(default)]
+    pub entity_kinds: Option<Vec<BuiltinEntityKind>>,
+    #[serde(default)]
+    pub conflict_resolution: ConflictResolution,
+    #[serde(default)]
+    pub grammar_error_mode: GrammarErrorMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokenization: Option<Tokenization>,
+    #[serde(default)]
+    pub boundary_alignment: BoundaryAlignment,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksums: Option<Checksums>,
 }
 
 impl BuiltinEntityParser {
+    /// Persists the parser in a new directory at `path`
     pub fn persist<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.persist_with_mode(path, PersistMode::CreateNew)
+    }
+
+    /// Persists the parser at `path`, the parser being written in a temporary directory first and
+    /// then moved into place, so that `path` never contains a partially written parser
+    ///
+    /// On Linux, overwriting swaps the previous parser and the new one atomically. Elsewhere, the
+    /// previous parser is moved aside before the new one takes its place, and if the process is
+    /// interrupted in between, `path` is missing until the next overwrite of `path`, which
+    /// restores the previous parser first. Overwrites of the same `path` wait for each other by
+    /// locking a `.{name}.lock` file created next to `path`.
+    pub fn persist_with_mode<P: AsRef<Path>>(&self, path: P, mode: PersistMode) -> Result<()> {
+        persist_with_staging(path, mode, |staging_path| self.write_files(staging_path))
+    }
+
+    /// Persists the parser in a single tar archive file at `path`, which must not exist
+    pub fn persist_to_archive<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        persist_archive(path, |staging_path| self.write_files(staging_path))
+    }
+
+    /// Writes the parser into `writer`, using the same format as `persist_to_archive`
+    pub fn persist_to_writer<W: Write>(&self, writer: W) -> Result<()> {
+        write_archive(writer, |staging_path| self.write_files(staging_path))
+    }
+
+    fn write_files<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         fs::create_dir(path.as_ref()).with_context(|_| {
-            format!(
+            ErrorKind::Persistence(format!(
                 "Cannot create builtin entity parser directory at path: {:?}",
                 path.as_ref()
-            )
+            ))
         })?;
         let gazetteer_parser_directory = if let Some(ref gazetteer_parser) = self.gazetteer_parser {
             let gazetteer_parser_path = path.as_ref().join("gazetteer_entity_parser");
-            gazetteer_parser.persist(gazetteer_parser_path)?;
+            gazetteer_parser.write_files(gazetteer_parser_path)?;
             Some("gazetteer_entity_parser".to_string())
         } else {
             None
         };
-        let gazetteer_parser_metadata = BuiltinParserMetadata {
+        let parser_metadata = BuiltinParserMetadata {
+            versions: PersistenceVersions::current(),
             language: self.language.to_string(),
             gazetteer_parser: gazetteer_parser_directory,
+            entity_kinds: self.entity_kinds.clone().map(|mut entity_kinds| {
+                entity_kinds.sort_by_key(|entity_kind| entity_kind.identifier());
+                entity_kinds.dedup();
+                entity_kinds
+            }),
+            conflict_resolution: self.conflict_resolution.clone(),
+            grammar_error_mode: self.grammar_error_mode,
+            tokenization: self.tokenization,
+            boundary_alignment: self.boundary_alignment,
+            checksums: Some(compute_checksums(path.as_ref())?),
         };
-        let metadata_path = path.as_ref().join("metadata.json");
-        let metadata_file = fs::File::create(&metadata_path).with_context(|_| {
-            format!("Cannot create metadata file at path: {:?}", metadata_path)
-        })?;
-        serde_json::to_writer_pretty(metadata_file, &gazetteer_parser_metadata)
-            .with_context(|_| "Cannot serialize builtin parser metadata")?;
-        Ok(())
+        write_metadata(path.as_ref(), &parser_metadata)
+    }
+
+    /// Computes a SHA-256 fingerprint of the persisted content of the parser
+    ///
+    /// Two parsers which would be persisted into identical files have the same fingerprint.
+    pub fn fingerprint(&self) -> Result<String> {
+        compute_fingerprint(|staging_path| self.write_files(staging_path))
+    }
+
+    /// Loads a parser persisted with `persist_to_archive`, applying the provided `LoadingMode` to
+    /// the content of the archive
+    pub fn from_archive<P: AsRef<Path>>(path: P, loading_mode: &LoadingMode) -> Result<Self> {
+        load_archive(path, loading_mode, |path| {
+            Self::from_path_with_loading_mode(path, loading_mode)
+        })
+    }
+
+    /// Loads a parser from a reader, using the format produced by `persist_to_writer`
+    ///
+    /// The content is unpacked in a temporary directory which is removed once the parser is
+    /// loaded. In `LoadingMode::Untrusted`, the entries of the archive are checked before being
+    /// unpacked.
+    pub fn from_reader<R: Read>(reader: R, loading_mode: &LoadingMode) -> Result<Self> {
+        read_archive(reader, loading_mode, |path| {
+            Self::from_path_with_loading_mode(path, loading_mode)
+        })
+    }
+
+    /// Loads a parser from an in-memory buffer, using the format produced by `persist_to_writer`
+    pub fn from_bytes(bytes: &[u8], loading_mode: &LoadingMode) -> Result<Self> {
+        Self::from_reader(bytes, loading_mode)
     }
 
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let metadata_path = path.as_ref().join("metadata.json");
-        let metadata_file = fs::File::open(&metadata_path).with_context(|_| {
-            format!(
-                "Cannot open builtin parser metadata file at path: {:?}",
-                metadata_path
-            )
-        })?;
-        let metadata: BuiltinParserMetadata = serde_json::from_reader(metadata_file)
-            .with_context(|_| "Cannot deserialize builtin parser metadata")?;
-        let language = Language::from_str(&metadata.language)?;
+        Self::from_path_with_loading_mode(path, &LoadingMode::Trusted)
+    }
+
+    /// Loads the parser located at `path`, applying the provided `LoadingMode` to the parser
+    /// directory and to its gazetteer parser
+    pub fn from_path_with_loading_mode<P: AsRef<Path>>(
+        path: P,
+        loading_mode: &LoadingMode,
+    ) -> Result<Self> {
+        loading_mode.check_parser_directory(path.as_ref())?;
+        let metadata = Self::load_metadata(path.as_ref())?;
+        metadata.versions.check_compatibility()?;
+        let language = Language::from_str(&metadata.language)
+            .map_err(|_| ErrorKind::UnknownLanguage(metadata.language.clone()))?;
         let mut parser_loader = BuiltinEntityParserLoader::new(language);
+        parser_loader.conflict_resolution(metadata.conflict_resolution);
+        parser_loader.grammar_error_mode(metadata.grammar_error_mode);
+        if let Some(tokenization) = metadata.tokenization {
+            parser_loader.tokenization(tokenization);
+        }
+        parser_loader.boundary_alignment(metadata.boundary_alignment);
+        if let Some(entity_kinds) = metadata.entity_kinds {
+            parser_loader.entity_kinds(entity_kinds);
+        }
+        parser_loader.loading_mode(*loading_mode);
         if let Some(gazetteer_parser_dir) = metadata.gazetteer_parser {
-            let gazetteer_parser_path = path.as_ref().join(&gazetteer_parser_dir);
+            let gazetteer_parser_path =
+                loading_mode.resolve_path(path.as_ref(), &gazetteer_parser_dir)?;
             parser_loader.use_gazetter_parser(gazetteer_parser_path);
         }
         parser_loader.load()
     }
+
+    /// Checks the integrity of the parser persisted at `path` without loading it
+    ///
+    /// The metadata is checked for compatibility, and the persisted files are checked against the
+    /// checksums recorded in it. Parsers persisted before checksums were recorded only get their
+    /// metadata checked.
+    pub fn verify<P: AsRef<Path>>(path: P) -> Result<()> {
+        let metadata = Self::load_metadata(path.as_ref())?;
+        metadata.versions.check_compatibility()?;
+        Language::from_str(&metadata.language)
+            .map_err(|_| ErrorKind::UnknownLanguage(metadata.language.clone()))?;
+        if let Some(checksums) = metadata.checksums {
+            verify_checksums(path.as_ref(), &checksums)?;
+        }
+        if let Some(gazetteer_parser_dir) = metadata.gazetteer_parser {
+            GazetteerParser::<String>::verify(confine_path(path.as_ref(), &gazetteer_parser_dir)?)?;
+        }
+        Ok(())
+    }
+
+    fn load_metadata(path: &Path) -> Result<BuiltinParserMetadata> {
+        let metadata_path = path.join("metadata.json");
+        let metadata_file = fs::File::open(&metadata_path).with_context(|_| {
+            ErrorKind::Persistence(format!(
+                "Cannot open builtin parser metadata file at path: {:?}",
+                metadata_path
+            ))
+        })?;
+        Ok(serde_json::from_reader(metadata_file).with_context(|_| {
+            ErrorKind::MetadataDeserialization(
+                "Cannot deserialize builtin parser metadata".to_string(),
+            )
+        })?)
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use chrono::{TimeZone, Utc};
+    use itertools::Itertools;
     use snips_nlu_ontology::language::Language;
     use snips_nlu_ontology::IntoBuiltinEntityKind;
     use snips_nlu_ontology::SlotValue::InstantTime;
     use tempfile::tempdir;
 
     use crate::parsable::ParsableEntityKind;
+    use crate::persistence::LoadingLimits;
     use crate::test_utils::test_path;
 
     use super::*;
 
+    /// Grammar parser failing on every sentence, as rustling does not fail on any known input
+    struct FailingGrammarParser(String);
+
+    impl GrammarParser for FailingGrammarParser {
+        fn parse_candidates(
+            &self,
+            _sentence: &str,
+            _output_kinds: &[OutputKind],
+            _context: &ResolverContext,
+            _resolve_all_candidates: bool,
+        ) -> std::result::Result<Vec<GrammarCandidate>, String> {
+            Err(self.0.clone())
+        }
+    }
+
+    fn load_failing_parser(
+        grammar_error_mode: GrammarErrorMode,
+        message: &str,
+    ) -> BuiltinEntityParser {
+        let mut parser = BuiltinEntityParserLoader::new(Language::EN)
+            .grammar_error_mode(grammar_error_mode)
+            .load()
+            .unwrap();
+        parser.grammar_parser = Some(Arc::new(FailingGrammarParser(message.to_string())));
+        parser
+    }
+
     #[test]
     fn test_should_parse_grammar_entities() {
         let parser = BuiltinEntityParserLoader::new(Language::EN).load().unwrap();
@@ -399,43 +1080,332 @@ mod test {
     }
 
     #[test]
-    fn test_should_parser_builtin_entities_with_empty_scope() {
+    fn test_should_resolve_datetimes_with_context() {
+        // Given
         let parser = BuiltinEntityParserLoader::new(Language::EN).load().unwrap();
+        let context = ExtractionContext::new()
+            .reference_datetime(Utc.ymd(2019, 10, 18).and_hms(10, 0, 0))
+            .timezone(Tz::Asia__Tokyo);
+
+        // When
         let entities = parser
-            .extract_entities("tomorrow morning", Some(&[]), 0)
+            .extract_entities_with_context(
+                "Book me a restaurant for tomorrow at 8pm",
+                Some(&[BuiltinEntityKind::Datetime]),
+                0,
+                &context,
+            )
             .unwrap();
-        assert_eq!(Vec::<BuiltinEntity>::new(), entities);
+
+        // Then
+        let expected_value = InstantTimeValue {
+            value: "2019-10-19 20:00:00 +09:00".to_string(),
+            grain: Grain::Hour,
+            precision: Precision::Exact,
+        };
+        assert_eq!(1, entities.len());
+        assert_eq!(InstantTime(expected_value), entities[0].entity);
     }
 
     #[test]
-    fn test_should_parse_gazetteer_entities() {
+    fn test_should_parse_grammar_entities_with_alternatives() {
         // Given
-        let language = Language::FR;
-        let parser = BuiltinEntityParserLoader::new(language)
-            .use_gazetter_parser(test_path().join("builtin_gazetteer_parser"))
-            .load()
-            .unwrap();
+        let parser = BuiltinEntityParserLoader::new(Language::EN).load().unwrap();
+        let context = ExtractionContext::new()
+            .reference_datetime(Utc.ymd(2019, 10, 18).and_hms(10, 0, 0))
+            .timezone(Tz::UTC);
 
         // When
-        let above_threshold_entity = parser
-            .extract_entities("Je voudrais écouter the stones s'il vous plaît", None, 5)
+        let entities = parser
+            .extract_entities_with_context(
+                "Call me at 5",
+                Some(&[BuiltinEntityKind::Time]),
+                5,
+                &context,
+            )
             .unwrap();
-        let below_threshold_entity = parser
-            .extract_entities("Je voudrais écouter les stones", None, 5)
+        let entities_without_alternatives = parser
+            .extract_entities_with_context(
+                "Call me at 5",
+                Some(&[BuiltinEntityKind::Time]),
+                0,
+                &context,
+            )
             .unwrap();
 
         // Then
+        let instant_time = |value: &str| {
+            SlotValue::InstantTime(InstantTimeValue {
+                value: value.to_string(),
+                grain: Grain::Hour,
+                precision: Precision::Exact,
+            })
+        };
         let expected_entity = BuiltinEntity {
-            value: "the stones".to_string(),
-            range: 20..30,
-            entity: SlotValue::MusicArtist(StringValue {
-                value: "The Rolling Stones".to_string(),
-            }),
+            value: "at 5".to_string(),
+            range: 8..12,
+            entity: instant_time("2019-10-18 17:00:00 +00:00"),
+            alternatives: vec![instant_time("2019-10-19 05:00:00 +00:00")],
+            entity_kind: BuiltinEntityKind::Time,
+        };
+        let expected_entity_without_alternatives = BuiltinEntity {
             alternatives: vec![],
-            entity_kind: BuiltinEntityKind::MusicArtist,
+            ..expected_entity.clone()
         };
-        assert_eq!(vec![expected_entity], above_threshold_entity);
-        assert_eq!(Vec::<BuiltinEntity>::new(), below_threshold_entity);
+        assert_eq!(vec![expected_entity], entities);
+        assert_eq!(
+            vec![expected_entity_without_alternatives],
+            entities_without_alternatives
+        );
+    }
+
+    #[test]
+    fn test_should_return_readings_of_other_kinds_as_alternatives() {
+        // Given
+        let parser = BuiltinEntityParserLoader::new(Language::EN).load().unwrap();
+        let entity_kinds = &[BuiltinEntityKind::Number, BuiltinEntityKind::Temperature];
+
+        // When
+        let entities = parser
+            .extract_entities("Raise to sixty two", Some(entity_kinds), 5)
+            .unwrap();
+
+        // Then
+        let number_value = SlotValue::Number(NumberValue { value: 62. });
+        let temperature_value = SlotValue::Temperature(TemperatureValue {
+            value: 62.,
+            unit: None,
+        });
+        assert_eq!(1, entities.len());
+        assert_eq!(9..18, entities[0].range);
+        assert_eq!(1, entities[0].alternatives.len());
+        let readings = vec![&entities[0].entity, &entities[0].alternatives[0]];
+        assert!(readings.contains(&&number_value));
+        assert!(readings.contains(&&temperature_value));
+    }
+
+    #[test]
+    fn test_should_handle_grammar_errors_according_to_mode() {
+        // Given
+        let strict_parser = BuiltinEntityParserLoader::new(Language::EN)
+            .grammar_error_mode(GrammarErrorMode::Strict)
+            .load()
+            .unwrap();
+        let lenient_parser = BuiltinEntityParserLoader::new(Language::EN).load().unwrap();
+        let grammar_error = || -> Result<Vec<BuiltinEntity>> {
+            Err(ErrorKind::GrammarParsing {
+                sentence: "hello".to_string(),
+                message: "some rustling error".to_string(),
+            }
+            .into())
+        };
+
+        // When
+        let mut strict_diagnostics = ExtractionDiagnostics::default();
+        let strict_result =
+            strict_parser._recover_from_grammar_error(grammar_error(), &mut strict_diagnostics);
+        let mut lenient_diagnostics = ExtractionDiagnostics::default();
+        let lenient_result =
+            lenient_parser._recover_from_grammar_error(grammar_error(), &mut lenient_diagnostics);
+
+        // Then
+        match strict_result.unwrap_err().kind() {
+            ErrorKind::GrammarParsing { .. } => (),
+            kind => panic!("Unexpected error kind: {:?}", kind),
+        }
+        assert!(strict_diagnostics.grammar_errors.is_empty());
+        assert_eq!(Vec::<BuiltinEntity>::new(), lenient_result.unwrap());
+        assert_eq!(
+            vec!["Cannot parse grammar entities in 'hello': some rustling error".to_string()],
+            lenient_diagnostics.grammar_errors
+        );
+    }
+
+    #[test]
+    fn test_should_return_grammar_errors_according_to_mode() {
+        // Given
+        let strict_parser = load_failing_parser(GrammarErrorMode::Strict, "rustling error");
+        let lenient_parser = load_failing_parser(GrammarErrorMode::Lenient, "rustling error");
+
+        // When
+        let strict_result = strict_parser.extract_entities("in three days", None, 0);
+        let lenient_result = lenient_parser.extract_entities_with_diagnostics(
+            "in three days",
+            None,
+            0,
+            &ExtractionContext::default(),
+        );
+
+        // Then
+        match strict_result.unwrap_err().kind() {
+            ErrorKind::GrammarParsing { sentence, message } => {
+                assert_eq!("in three days", sentence.as_str());
+                assert_eq!("rustling error", message.as_str());
+            }
+            kind => panic!("Unexpected error kind: {:?}", kind),
+        }
+        let (lenient_entities, lenient_diagnostics) = lenient_result.unwrap();
+        assert_eq!(Vec::<BuiltinEntity>::new(), lenient_entities);
+        assert_eq!(
+            vec!["Cannot parse grammar entities in 'in three days': rustling error".to_string()],
+            lenient_diagnostics.grammar_errors
+        );
+    }
+
+    #[test]
+    fn test_should_persist_grammar_error_mode() {
+        // Given
+        let parser = BuiltinEntityParserLoader::new(Language::EN)
+            .grammar_error_mode(GrammarErrorMode::Strict)
+            .load()
+            .unwrap();
+        let temp_dir = tempdir().unwrap();
+        let parser_dir = temp_dir.path().join("builtin_entity_parser");
+
+        // When
+        parser.persist(&parser_dir).unwrap();
+        let loaded_parser = BuiltinEntityParser::from_path(&parser_dir).unwrap();
+
+        // Then
+        assert_eq!(GrammarErrorMode::Strict, loaded_parser.grammar_error_mode);
+    }
+
+    #[test]
+    fn test_should_parser_builtin_entities_with_empty_scope() {
+        let parser = BuiltinEntityParserLoader::new(Language::EN).load().unwrap();
+        let entities = parser
+            .extract_entities("tomorrow morning", Some(&[]), 0)
+            .unwrap();
+        assert_eq!(Vec::<BuiltinEntity>::new(), entities);
+    }
+
+    #[test]
+    fn test_should_parse_gazetteer_entities() {
+        // Given
+        let language = Language::FR;
+        let parser = BuiltinEntityParserLoader::new(language)
+            .use_gazetter_parser(test_path().join("builtin_gazetteer_parser"))
+            .load()
+            .unwrap();
+
+        // When
+        let above_threshold_entity = parser
+            .extract_entities("Je voudrais écouter the stones s'il vous plaît", None, 5)
+            .unwrap();
+        let below_threshold_entity = parser
+            .extract_entities("Je voudrais écouter les stones", None, 5)
+            .unwrap();
+
+        // Then
+        let expected_entity = BuiltinEntity {
+            value: "the stones".to_string(),
+            range: 20..30,
+            entity: SlotValue::MusicArtist(StringValue {
+                value: "The Rolling Stones".to_string(),
+            }),
+            alternatives: vec![],
+            entity_kind: BuiltinEntityKind::MusicArtist,
+        };
+        assert_eq!(vec![expected_entity], above_threshold_entity);
+        assert_eq!(Vec::<BuiltinEntity>::new(), below_threshold_entity);
+    }
+
+    #[test]
+    fn test_should_apply_gazetteer_thresholds_with_context() {
+        // Given
+        let language = Language::FR;
+        let parser = BuiltinEntityParserLoader::new(language)
+            .use_gazetter_parser(test_path().join("builtin_gazetteer_parser"))
+            .load()
+            .unwrap();
+        let partial_sentence = "Je voudrais écouter the stones s'il vous plaît";
+        let short_sentence = "Je voudrais écouter stones s'il vous plaît";
+        let strict_context = ExtractionContext::new().gazetteer_threshold("snips/musicArtist", 0.7);
+        let loose_context = ExtractionContext::new().gazetteer_threshold("snips/musicArtist", 0.3);
+        let default_context = ExtractionContext::default();
+
+        // When
+        let strict_entities = parser
+            .extract_entities_with_context(partial_sentence, None, 5, &strict_context)
+            .unwrap();
+        let partial_entities = parser
+            .extract_entities_with_context(partial_sentence, None, 5, &default_context)
+            .unwrap();
+        let loose_entities = parser
+            .extract_entities_with_context(short_sentence, None, 5, &loose_context)
+            .unwrap();
+        let short_entities = parser
+            .extract_entities_with_context(short_sentence, None, 5, &default_context)
+            .unwrap();
+
+        // Then
+        assert_eq!(Vec::<BuiltinEntity>::new(), strict_entities);
+        assert_eq!(1, partial_entities.len());
+        assert_eq!("the stones", partial_entities[0].value);
+        assert_eq!(1, loose_entities.len());
+        assert_eq!("stones", loose_entities[0].value);
+        assert_eq!(
+            SlotValue::MusicArtist(StringValue {
+                value: "The Rolling Stones".to_string()
+            }),
+            loose_entities[0].entity
+        );
+        assert_eq!(Vec::<BuiltinEntity>::new(), short_entities);
+    }
+
+    #[test]
+    fn test_should_parse_input_whose_lowercase_form_changes_length() {
+        // Given
+        let language = Language::EN;
+        let parser = BuiltinEntityParserLoader::new(language).load().unwrap();
+
+        // When
+        let entities = parser
+            .extract_entities(
+                "İZMİR: I need THREE apples",
+                Some(&[BuiltinEntityKind::Number]),
+                5,
+            )
+            .unwrap();
+
+        // Then
+        let expected_entity = BuiltinEntity {
+            value: "THREE".to_string(),
+            range: 14..19,
+            entity: SlotValue::Number(NumberValue { value: 3.0 }),
+            alternatives: vec![],
+            entity_kind: BuiltinEntityKind::Number,
+        };
+        assert_eq!(vec![expected_entity], entities);
+    }
+
+    #[test]
+    fn test_should_parse_entities_with_offsets() {
+        // Given
+        let language = Language::FR;
+        let parser = BuiltinEntityParserLoader::new(language)
+            .use_gazetter_parser(test_path().join("builtin_gazetteer_parser"))
+            .load()
+            .unwrap();
+        let sentence = "😀 Je voudrais écouter deux fois the stones s'il vous plaît";
+
+        // When
+        let entities = parser
+            .extract_entities_with_offsets(sentence, None, 5, &ExtractionContext::default())
+            .unwrap();
+
+        // Then
+        let utf16_sentence = sentence.encode_utf16().collect::<Vec<_>>();
+        assert!(entities
+            .iter()
+            .any(|entity| entity.entity.entity_kind == BuiltinEntityKind::MusicArtist));
+        for entity in entities {
+            assert_eq!(entity.entity.value, &sentence[entity.byte_range]);
+            assert_eq!(
+                entity.entity.value,
+                String::from_utf16(&utf16_sentence[entity.utf16_range]).unwrap()
+            );
+        }
     }
 
     #[test]
@@ -541,6 +1511,210 @@ mod test {
         assert_eq!(vec![expected_entity], parsed_entity);
     }
 
+    #[test]
+    fn test_should_resolve_conflicts_between_grammar_and_gazetteer_entities() {
+        // Given
+        let language = Language::EN;
+        let mut parser = BuiltinEntityParserLoader::new(language)
+            .use_gazetter_parser(test_path().join("builtin_gazetteer_parser"))
+            .conflict_resolution(ConflictResolution::LongestMatch)
+            .load()
+            .unwrap();
+        parser
+            .extend_gazetteer_entity(
+                BuiltinGazetteerEntityKind::MusicTrack,
+                vec![EntityValue {
+                    raw_value: "one nine nine nine".to_string(),
+                    resolved_value: "1999".to_string(),
+                }]
+                .into_iter(),
+            )
+            .unwrap();
+
+        // When
+        let entities = parser
+            .extract_entities(
+                "play one nine nine nine by prince",
+                Some(&[BuiltinEntityKind::Number, BuiltinEntityKind::MusicTrack]),
+                0,
+            )
+            .unwrap();
+
+        // Then
+        let expected_entity = BuiltinEntity {
+            value: "one nine nine nine".to_string(),
+            range: 5..23,
+            entity: SlotValue::MusicTrack(StringValue {
+                value: "1999".to_string(),
+            }),
+            alternatives: vec![],
+            entity_kind: BuiltinEntityKind::MusicTrack,
+        };
+        assert_eq!(vec![expected_entity], entities);
+    }
+
+    #[test]
+    fn test_should_extract_entities_in_batch() {
+        // Given
+        let parser = BuiltinEntityParserLoader::new(Language::EN).load().unwrap();
+        let sentences = vec![
+            "Book me a restaurant for two people tomorrow",
+            "Raise to sixty two degrees celsius",
+            "hello world",
+        ];
+        let context = ExtractionContext::default();
+
+        // When
+        let sequential_entities = parser
+            .extract_entities_batch(&sentences, None, 0, &context, 1)
+            .unwrap();
+        let parallel_entities = parser
+            .extract_entities_batch(&sentences, None, 0, &context, 2)
+            .unwrap();
+        let per_cpu_entities = parser
+            .extract_entities_batch(&sentences, None, 0, &context, 0)
+            .unwrap();
+
+        // Then
+        let expected_entities = sentences
+            .iter()
+            .map(|sentence| parser.extract_entities(sentence, None, 0).unwrap())
+            .collect_vec();
+        assert_eq!(3, expected_entities.len());
+        assert_eq!(expected_entities, sequential_entities);
+        assert_eq!(expected_entities, parallel_entities);
+        assert_eq!(expected_entities, per_cpu_entities);
+    }
+
+    #[test]
+    fn test_should_reuse_thread_pools_of_batch_extractions() {
+        // When
+        let pool = get_thread_pool(3).unwrap();
+        let same_pool = get_thread_pool(3).unwrap();
+        let other_pools = (4..4 + MAX_THREAD_POOLS)
+            .map(|num_threads| get_thread_pool(num_threads).unwrap())
+            .collect_vec();
+
+        // Then
+        assert!(Arc::ptr_eq(&pool, &same_pool));
+        assert_eq!(3, pool.current_num_threads());
+        assert!(other_pools
+            .iter()
+            .all(|other_pool| !Arc::ptr_eq(&pool, other_pool)));
+        assert!(THREAD_POOLS.lock().unwrap().len() <= MAX_THREAD_POOLS);
+    }
+
+    #[test]
+    fn test_should_return_grammar_errors_according_to_mode_in_batch() {
+        // Given
+        let strict_parser = load_failing_parser(GrammarErrorMode::Strict, "rustling error");
+        let lenient_parser = load_failing_parser(GrammarErrorMode::Lenient, "rustling error");
+        let sentences = vec!["in three days", "tomorrow at 5pm"];
+        let context = ExtractionContext::default();
+
+        // When
+        let strict_results = vec![
+            strict_parser.extract_entities_batch(&sentences, None, 0, &context, 1),
+            strict_parser.extract_entities_batch(&sentences, None, 0, &context, 2),
+        ];
+        let lenient_results = vec![
+            lenient_parser.extract_entities_batch(&sentences, None, 0, &context, 1),
+            lenient_parser.extract_entities_batch(&sentences, None, 0, &context, 2),
+        ];
+
+        // Then
+        for strict_result in strict_results {
+            match strict_result.unwrap_err().kind() {
+                ErrorKind::GrammarParsing { message, .. } => {
+                    assert_eq!("rustling error", message.as_str())
+                }
+                kind => panic!("Unexpected error kind: {:?}", kind),
+            }
+        }
+        for lenient_result in lenient_results {
+            assert_eq!(
+                vec![Vec::<BuiltinEntity>::new(), vec![]],
+                lenient_result.unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_should_only_load_allowed_entity_kinds() {
+        // Given
+        let parser = BuiltinEntityParserLoader::new(Language::EN)
+            .use_gazetter_parser(test_path().join("builtin_gazetteer_parser"))
+            .entity_kinds(vec![
+                BuiltinEntityKind::Number,
+                BuiltinEntityKind::MusicArtist,
+            ])
+            .load()
+            .unwrap();
+
+        // When
+        let entity_kinds = parser
+            .extract_entities("Play the rolling stones for two hours tomorrow", None, 0)
+            .unwrap()
+            .into_iter()
+            .map(|entity| entity.entity_kind)
+            .collect_vec();
+        let unloaded_kind_error = parser
+            .extract_entities(
+                "Play the rolling stones",
+                Some(&[BuiltinEntityKind::Date]),
+                0,
+            )
+            .unwrap_err();
+
+        // Then
+        assert_eq!(
+            vec![BuiltinEntityKind::MusicArtist, BuiltinEntityKind::Number],
+            entity_kinds
+        );
+        assert_eq!(
+            &ErrorKind::EntityKindNotLoaded("snips/date".to_string()),
+            unloaded_kind_error.kind()
+        );
+    }
+
+    #[test]
+    fn test_should_share_rustling_grammar_between_parsers_alive() {
+        // Given
+        // No other test loads parsers in Italian, so that they cannot keep its grammar alive
+        let grammar_address = |parser: &BuiltinEntityParser| {
+            &**parser.grammar_parser.as_ref().unwrap() as *const dyn GrammarParser as *const u8
+        };
+        let is_grammar_cached = || {
+            lock_rustling_parsers()
+                .get(&Language::IT)
+                .and_then(|rustling_parser| rustling_parser.upgrade())
+                .is_some()
+        };
+
+        // When
+        let restricted_parser = BuiltinEntityParserLoader::new(Language::IT)
+            .entity_kinds(vec![BuiltinEntityKind::Number])
+            .load()
+            .unwrap();
+        let full_parser = BuiltinEntityParserLoader::new(Language::IT).load().unwrap();
+        let gazetteer_only_parser = BuiltinEntityParserLoader::new(Language::EN)
+            .use_gazetter_parser(test_path().join("builtin_gazetteer_parser"))
+            .entity_kinds(vec![BuiltinEntityKind::MusicArtist])
+            .load()
+            .unwrap();
+        let is_shared = grammar_address(&restricted_parser) == grammar_address(&full_parser);
+        let is_cached_while_used = is_grammar_cached();
+        drop(restricted_parser);
+        drop(full_parser);
+        let is_cached_after_drop = is_grammar_cached();
+
+        // Then
+        assert!(is_shared);
+        assert!(gazetteer_only_parser.grammar_parser.is_none());
+        assert!(is_cached_while_used);
+        assert!(!is_cached_after_drop);
+    }
+
     #[test]
     fn test_should_parse_builtin_entities_for_non_space_separated_languages() {
         let parser = BuiltinEntityParserLoader::new(Language::JA).load().unwrap();
@@ -590,6 +1764,128 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_should_align_misaligned_entities_on_token_boundaries() {
+        // Given
+        let expanding_parser = BuiltinEntityParserLoader::new(Language::JA)
+            .boundary_alignment(BoundaryAlignment::Expand)
+            .load()
+            .unwrap();
+        let shrinking_parser = BuiltinEntityParserLoader::new(Language::JA)
+            .boundary_alignment(BoundaryAlignment::Shrink)
+            .load()
+            .unwrap();
+        let sentence = "二 千 十三 年二 月十 日の カリフォルニア州の天気予報は？";
+        let entity_kinds = [BuiltinEntityKind::Datetime];
+
+        // When
+        let (expanded_entities, expanding_diagnostics) = expanding_parser
+            .extract_entities_with_diagnostics(
+                sentence,
+                Some(&entity_kinds),
+                0,
+                &ExtractionContext::default(),
+            )
+            .unwrap();
+        let (shrunk_entities, shrinking_diagnostics) = shrinking_parser
+            .extract_entities_with_diagnostics(
+                sentence,
+                Some(&entity_kinds),
+                0,
+                &ExtractionContext::default(),
+            )
+            .unwrap();
+
+        // Then
+        assert_eq!(1, expanded_entities.len());
+        assert_eq!("二 千 十三 年二 月十 日の", expanded_entities[0].value);
+        assert_eq!(0..15, expanded_entities[0].range);
+        let expected_adjustment = BoundaryAdjustment {
+            entity_kind: BuiltinEntityKind::Datetime,
+            matched_range: 0..14,
+            range: 0..15,
+        };
+        assert_eq!(
+            vec![expected_adjustment],
+            expanding_diagnostics.boundary_adjustments
+        );
+        assert_eq!(1, shrunk_entities.len());
+        assert_eq!("二 千 十三 年二 月十", shrunk_entities[0].value);
+        assert_eq!(0..12, shrunk_entities[0].range);
+        assert_eq!(1, shrinking_diagnostics.boundary_adjustments.len());
+    }
+
+    #[test]
+    fn test_should_parse_with_configured_tokenization() {
+        // Given
+        let parser = BuiltinEntityParserLoader::new(Language::JA)
+            .tokenization(Tokenization::CjkMixed)
+            .load()
+            .unwrap();
+        let temp_dir = tempdir().unwrap();
+        let parser_dir = temp_dir.path().join("builtin_entity_parser");
+
+        // When
+        let parsed_entities = parser
+            .extract_entities(
+                " の カリフォル  二 千 十三 年二 月十 日  ニア州の天気予報は？",
+                Some(&[BuiltinEntityKind::Datetime]),
+                0,
+            )
+            .unwrap();
+        parser.persist(&parser_dir).unwrap();
+        let metadata: BuiltinParserMetadata =
+            serde_json::from_reader(fs::File::open(parser_dir.join("metadata.json")).unwrap())
+                .unwrap();
+
+        // Then
+        assert_eq!(1, parsed_entities.len());
+        assert_eq!("二 千 十三 年二 月十 日", parsed_entities[0].value);
+        assert_eq!(10..24, parsed_entities[0].range);
+        assert_eq!(Some(Tokenization::CjkMixed), metadata.tokenization);
+    }
+
+    #[test]
+    fn test_should_parse_with_custom_tokenizer() {
+        // Given
+        #[derive(Debug)]
+        struct HyphenTokenizer;
+
+        impl Tokenizer for HyphenTokenizer {
+            fn tokenize(&self, sentence: &str) -> Vec<Range<usize>> {
+                let mut tokens = vec![];
+                let mut start = 0;
+                for (index, c) in sentence.char_indices() {
+                    if c == '-' {
+                        tokens.push(start..index);
+                        start = index + 1;
+                    }
+                }
+                tokens.push(start..sentence.len());
+                tokens
+            }
+
+            fn separator(&self, _previous_token: &str, _next_token: &str) -> &'static str {
+                " "
+            }
+        }
+
+        let parser = BuiltinEntityParserLoader::new(Language::EN)
+            .tokenizer(Arc::new(HyphenTokenizer))
+            .load()
+            .unwrap();
+
+        // When
+        let entities = parser
+            .extract_entities("buy-three-apples", Some(&[BuiltinEntityKind::Number]), 0)
+            .unwrap();
+
+        // Then
+        assert_eq!(1, entities.len());
+        assert_eq!("three", entities[0].value);
+        assert_eq!(4..9, entities[0].range);
+    }
+
     #[test]
     fn test_entity_examples_should_be_parsed() {
         for language in Language::all() {
@@ -637,6 +1933,30 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_should_refuse_parser_persisted_with_incompatible_version() {
+        // Given
+        let parser = BuiltinEntityParserLoader::new(Language::EN).load().unwrap();
+        let temp_dir = tempdir().unwrap();
+        let parser_dir = temp_dir.path().join("builtin_entity_parser");
+        parser.persist(&parser_dir).unwrap();
+        let metadata_path = parser_dir.join("metadata.json");
+        let mut metadata: BuiltinParserMetadata =
+            serde_json::from_reader(fs::File::open(&metadata_path).unwrap()).unwrap();
+        assert_eq!(PersistenceVersions::current(), metadata.versions);
+        metadata.versions.snips_nlu_ontology_version = Some("1.0.0".to_string());
+        serde_json::to_writer(fs::File::create(&metadata_path).unwrap(), &metadata).unwrap();
+
+        // When
+        let loading_result = BuiltinEntityParser::from_path(&parser_dir);
+
+        // Then
+        match loading_result.map(|_| ()).unwrap_err().kind() {
+            ErrorKind::IncompatibleVersion(_) => (),
+            kind => panic!("Unexpected error kind: {:?}", kind),
+        }
+    }
+
     #[test]
     fn test_should_load_parser_from_path() {
         // Given
@@ -680,6 +2000,148 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_should_persist_parser_with_gazetteer_entities_to_archive() {
+        // Given
+        let parser = BuiltinEntityParserLoader::new(Language::FR)
+            .use_gazetter_parser(test_path().join("builtin_gazetteer_parser"))
+            .load()
+            .unwrap();
+
+        let temp_dir = tempdir().unwrap();
+        let archive_path = temp_dir.path().join("builtin_entity_parser.tar");
+
+        // When
+        parser.persist_to_archive(&archive_path).unwrap();
+        let loaded_parser =
+            BuiltinEntityParser::from_archive(&archive_path, &LoadingMode::Trusted).unwrap();
+
+        // Then
+        assert!(archive_path.is_file());
+        assert_eq!(parser.language, loaded_parser.language);
+        assert_eq!(parser.gazetteer_parser, loaded_parser.gazetteer_parser);
+        assert_eq!(
+            parser.rustling_entity_kinds,
+            loaded_parser.rustling_entity_kinds
+        );
+    }
+
+    #[test]
+    fn test_should_load_parser_with_gazetteer_entities_from_reader() {
+        // Given
+        let parser = BuiltinEntityParserLoader::new(Language::FR)
+            .use_gazetter_parser(test_path().join("builtin_gazetteer_parser"))
+            .load()
+            .unwrap();
+        let mut parser_bytes = vec![];
+        parser.persist_to_writer(&mut parser_bytes).unwrap();
+
+        // When
+        let loaded_parser =
+            BuiltinEntityParser::from_reader(&*parser_bytes, &LoadingMode::Trusted).unwrap();
+
+        // Then
+        assert_eq!(parser.language, loaded_parser.language);
+        assert_eq!(parser.gazetteer_parser, loaded_parser.gazetteer_parser);
+        assert_eq!(
+            parser.rustling_entity_kinds,
+            loaded_parser.rustling_entity_kinds
+        );
+    }
+
+    #[test]
+    fn test_should_verify_persisted_parser() {
+        // Given
+        let parser = BuiltinEntityParserLoader::new(Language::FR)
+            .use_gazetter_parser(test_path().join("builtin_gazetteer_parser"))
+            .load()
+            .unwrap();
+        let temp_dir = tempdir().unwrap();
+        let parser_dir = temp_dir.path().join("builtin_entity_parser");
+        parser.persist(&parser_dir).unwrap();
+        let entity_parser_file = fs::read_dir(parser_dir.join("gazetteer_entity_parser/parser_1"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.is_file())
+            .unwrap();
+
+        // When
+        let valid_result = BuiltinEntityParser::verify(&parser_dir);
+        let legacy_result = BuiltinEntityParser::verify(test_path().join("builtin_entity_parser"));
+        let content = fs::read(&entity_parser_file).unwrap();
+        fs::write(&entity_parser_file, &content[..content.len() / 2]).unwrap();
+        let truncated_result = BuiltinEntityParser::verify(&parser_dir);
+
+        // Then
+        assert!(valid_result.is_ok());
+        assert!(legacy_result.is_ok());
+        match truncated_result.unwrap_err().kind() {
+            ErrorKind::IntegrityCheck(_) => (),
+            kind => panic!("Unexpected error kind: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_should_load_parser_in_untrusted_mode() {
+        // Given
+        let parser_path = test_path().join("builtin_entity_parser");
+        let untrusted_mode = LoadingMode::Untrusted(LoadingLimits::default());
+        let small_files_mode = LoadingMode::Untrusted(LoadingLimits {
+            max_file_size: 10,
+            ..LoadingLimits::default()
+        });
+
+        // When
+        let parser =
+            BuiltinEntityParser::from_path_with_loading_mode(&parser_path, &untrusted_mode)
+                .unwrap();
+        let small_files_result =
+            BuiltinEntityParser::from_path_with_loading_mode(&parser_path, &small_files_mode);
+
+        // Then
+        let expected_parser = BuiltinEntityParser::from_path(&parser_path).unwrap();
+        assert_eq!(expected_parser.gazetteer_parser, parser.gazetteer_parser);
+        match small_files_result.unwrap_err().kind() {
+            ErrorKind::UnsafeLayout(_) => (),
+            kind => panic!("Unexpected error kind: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_should_persist_normalized_metadata() {
+        // Given
+        let parser = BuiltinEntityParserLoader::new(Language::EN)
+            .entity_kinds(vec![
+                BuiltinEntityKind::Number,
+                BuiltinEntityKind::Duration,
+                BuiltinEntityKind::Number,
+            ])
+            .load()
+            .unwrap();
+        let other_parser = BuiltinEntityParserLoader::new(Language::EN)
+            .entity_kinds(vec![BuiltinEntityKind::Duration, BuiltinEntityKind::Number])
+            .load()
+            .unwrap();
+        let temp_dir = tempdir().unwrap();
+        let parser_dir = temp_dir.path().join("builtin_entity_parser");
+
+        // When
+        parser.persist(&parser_dir).unwrap();
+        let metadata: BuiltinParserMetadata =
+            serde_json::from_reader(fs::File::open(parser_dir.join("metadata.json")).unwrap())
+                .unwrap();
+
+        // Then
+        assert_eq!(
+            Some(vec![BuiltinEntityKind::Duration, BuiltinEntityKind::Number]),
+            metadata.entity_kinds
+        );
+        assert_eq!(
+            parser.fingerprint().unwrap(),
+            other_parser.fingerprint().unwrap()
+        );
+    }
+
     #[test]
     fn test_should_load_parser_with_gazetteer_entities_from_path() {
         // Given
@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+use snips_nlu_ontology::{BuiltinEntity, BuiltinEntityKind};
+use std::cmp::Ordering;
+
+/// Strategy used to resolve overlapping entities coming from the grammar (rustling) parser and
+/// the gazetteer parser
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictResolution {
+    /// Keep all entities, even when they overlap
+    KeepAll,
+    /// Keep the longest entity among overlapping ones
+    LongestMatch,
+    /// Keep the entity whose kind comes first in the provided list, kinds which are not listed
+    /// having the lowest priority
+    Priority(Vec<BuiltinEntityKind>),
+    /// Keep grammar entities over gazetteer entities
+    GrammarFirst,
+    /// Keep gazetteer entities over grammar entities
+    GazetteerFirst,
+}
+
+impl Default for ConflictResolution {
+    fn default() -> Self {
+        ConflictResolution::KeepAll
+    }
+}
+
+impl ConflictResolution {
+    /// Resolves conflicts between overlapping entities and returns the remaining entities sorted
+    /// by position
+    pub fn resolve(&self, entities: Vec<BuiltinEntity>) -> Vec<BuiltinEntity> {
+        let mut entities = match self {
+            ConflictResolution::KeepAll => entities,
+            _ => self.remove_overlaps(entities),
+        };
+        entities.sort_by(|a, b| {
+            a.range
+                .start
+                .cmp(&b.range.start)
+                .then(a.range.end.cmp(&b.range.end))
+        });
+        entities
+    }
+
+    fn remove_overlaps(&self, mut entities: Vec<BuiltinEntity>) -> Vec<BuiltinEntity> {
+        // The sort is stable, so that ties are resolved by keeping the first entity
+        entities.sort_by(|a, b| self.compare_priority(a, b));
+        entities
+            .into_iter()
+            .fold(vec![], |mut kept_entities, entity| {
+                if !kept_entities.iter().any(|kept| overlap(kept, &entity)) {
+                    kept_entities.push(entity);
+                }
+                kept_entities
+            })
+    }
+
+    /// Entities with the highest priority come first
+    fn compare_priority(&self, a: &BuiltinEntity, b: &BuiltinEntity) -> Ordering {
+        let by_length = length(b).cmp(&length(a));
+        match self {
+            ConflictResolution::KeepAll | ConflictResolution::LongestMatch => by_length,
+            ConflictResolution::Priority(kinds) => {
+                let rank = |entity: &BuiltinEntity| {
+                    kinds
+                        .iter()
+                        .position(|kind| *kind == entity.entity_kind)
+                        .unwrap_or(kinds.len())
+                };
+                rank(a).cmp(&rank(b)).then(by_length)
+            }
+            ConflictResolution::GrammarFirst => is_gazetteer_entity(a)
+                .cmp(&is_gazetteer_entity(b))
+                .then(by_length),
+            ConflictResolution::GazetteerFirst => is_gazetteer_entity(b)
+                .cmp(&is_gazetteer_entity(a))
+                .then(by_length),
+        }
+    }
+}
+
+fn length(entity: &BuiltinEntity) -> usize {
+    entity.range.end - entity.range.start
+}
+
+fn overlap(a: &BuiltinEntity, b: &BuiltinEntity) -> bool {
+    a.range.start < b.range.end && b.range.start < a.range.end
+}
+
+fn is_gazetteer_entity(entity: &BuiltinEntity) -> bool {
+    entity.entity_kind.try_into_gazetteer_kind().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use snips_nlu_ontology::{NumberValue, SlotValue, StringValue};
+
+    fn number_entity() -> BuiltinEntity {
+        BuiltinEntity {
+            value: "1999".to_string(),
+            range: 5..9,
+            entity: SlotValue::Number(NumberValue { value: 1999. }),
+            alternatives: vec![],
+            entity_kind: BuiltinEntityKind::Number,
+        }
+    }
+
+    fn album_entity() -> BuiltinEntity {
+        BuiltinEntity {
+            value: "1999".to_string(),
+            range: 5..9,
+            entity: SlotValue::MusicAlbum(StringValue {
+                value: "1999".to_string(),
+            }),
+            alternatives: vec![],
+            entity_kind: BuiltinEntityKind::MusicAlbum,
+        }
+    }
+
+    fn track_entity() -> BuiltinEntity {
+        BuiltinEntity {
+            value: "1999 by prince".to_string(),
+            range: 5..19,
+            entity: SlotValue::MusicTrack(StringValue {
+                value: "1999".to_string(),
+            }),
+            alternatives: vec![],
+            entity_kind: BuiltinEntityKind::MusicTrack,
+        }
+    }
+
+    fn artist_entity() -> BuiltinEntity {
+        BuiltinEntity {
+            value: "prince".to_string(),
+            range: 13..19,
+            entity: SlotValue::MusicArtist(StringValue {
+                value: "Prince".to_string(),
+            }),
+            alternatives: vec![],
+            entity_kind: BuiltinEntityKind::MusicArtist,
+        }
+    }
+
+    #[test]
+    fn test_should_keep_all_entities_sorted_by_position() {
+        // Given
+        let entities = vec![artist_entity(), number_entity(), album_entity()];
+
+        // When
+        let resolved_entities = ConflictResolution::KeepAll.resolve(entities);
+
+        // Then
+        let expected_entities = vec![number_entity(), album_entity(), artist_entity()];
+        assert_eq!(expected_entities, resolved_entities);
+    }
+
+    #[test]
+    fn test_should_keep_longest_match() {
+        // Given
+        let entities = vec![number_entity(), track_entity(), artist_entity()];
+
+        // When
+        let resolved_entities = ConflictResolution::LongestMatch.resolve(entities);
+
+        // Then
+        assert_eq!(vec![track_entity()], resolved_entities);
+    }
+
+    #[test]
+    fn test_should_resolve_conflicts_with_priorities() {
+        // Given
+        let entities = vec![number_entity(), track_entity(), artist_entity()];
+        let priorities = vec![BuiltinEntityKind::Number, BuiltinEntityKind::MusicArtist];
+
+        // When
+        let resolved_entities = ConflictResolution::Priority(priorities).resolve(entities);
+
+        // Then
+        assert_eq!(vec![number_entity(), artist_entity()], resolved_entities);
+    }
+
+    #[test]
+    fn test_should_resolve_conflicts_between_grammar_and_gazetteer_entities() {
+        // Given
+        let entities = vec![number_entity(), album_entity()];
+
+        // When
+        let grammar_first = ConflictResolution::GrammarFirst.resolve(entities.clone());
+        let gazetteer_first = ConflictResolution::GazetteerFirst.resolve(entities);
+
+        // Then
+        assert_eq!(vec![number_entity()], grammar_first);
+        assert_eq!(vec![album_entity()], gazetteer_first);
+    }
+
+    #[test]
+    fn test_should_deserialize_conflict_resolution() {
+        // Given
+        let json = r#"{"priority": ["snips/musicAlbum", "snips/number"]}"#;
+
+        // When
+        let conflict_resolution: ConflictResolution = serde_json::from_str(json).unwrap();
+
+        // Then
+        let expected_conflict_resolution = ConflictResolution::Priority(vec![
+            BuiltinEntityKind::MusicAlbum,
+            BuiltinEntityKind::Number,
+        ]);
+        assert_eq!(expected_conflict_resolution, conflict_resolution);
+    }
+}
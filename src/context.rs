@@ -0,0 +1,157 @@
+use chrono::{DateTime, Duration, Local, LocalResult, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use rustling_ontology::{Grain, Interval, Moment, ResolverContext};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Per-call context used when extracting entities
+///
+/// The reference datetime and timezone are used to resolve relative time expressions such as
+/// "tomorrow at 8pm". When they are not provided, the current time and the local timezone of the
+/// machine are used.
+///
+/// The gazetteer thresholds override, for this call only, the minimum ratio of the tokens of a
+/// gazetteer value which must be matched, indexed by entity identifier. They can be lower or
+/// higher than the threshold the entity parser was built with, but must be between 0 and 1, which
+/// is checked when extracting entities.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct ExtractionContext {
+    pub reference_datetime: Option<DateTime<Utc>>,
+    pub timezone: Option<Tz>,
+    pub gazetteer_thresholds: BTreeMap<String, f64>,
+}
+
+impl ExtractionContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reference_datetime(mut self, reference_datetime: DateTime<Utc>) -> Self {
+        self.reference_datetime = Some(reference_datetime);
+        self
+    }
+
+    pub fn timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = Some(timezone);
+        self
+    }
+
+    pub fn gazetteer_threshold(mut self, entity_identifier: &str, threshold: f64) -> Self {
+        self.gazetteer_thresholds
+            .insert(entity_identifier.to_string(), threshold);
+        self
+    }
+
+    pub(crate) fn rustling_context(&self) -> ResolverContext {
+        if self.reference_datetime.is_none() && self.timezone.is_none() {
+            return ResolverContext::default();
+        }
+        let reference = Interval::starting_at(Moment(self.local_reference()), Grain::Second);
+        ResolverContext::for_reference(reference)
+    }
+
+    fn local_reference(&self) -> DateTime<Local> {
+        self.host_reference(&Local)
+    }
+
+    /// Rustling resolves datetimes in the timezone of the host, so the reference datetime is
+    /// expressed with the wall clock of the requested timezone and then interpreted in the host
+    /// timezone. The resolved values are translated back with
+    /// `conversion::rustling::convert_to_timezone`, which keeps their wall clock time.
+    ///
+    /// Rustling adds durations such as "in 3 hours" to the instant of the reference, so the
+    /// resolved wall clock time is off by the length of a DST transition of the host or of the
+    /// requested timezone occurring in between. Wall clock times such as "tomorrow at 8pm" do not
+    /// depend on the host timezone.
+    fn host_reference<Z: TimeZone>(&self, host_timezone: &Z) -> DateTime<Z> {
+        let reference = self.reference_datetime.unwrap_or_else(Utc::now);
+        match self.timezone {
+            Some(timezone) => {
+                let wall_clock = reference.with_timezone(&timezone).naive_local();
+                from_wall_clock(host_timezone, &wall_clock)
+            }
+            None => reference.with_timezone(host_timezone),
+        }
+    }
+}
+
+/// Interprets a wall clock time in `timezone`, including around DST transitions
+///
+/// A wall clock time which occurs twice, when clocks are set back, is mapped to its earliest
+/// occurrence. A wall clock time which does not exist, when clocks are set forward, is shifted
+/// forward by the length of the transition, e.g. 02:30 becomes 03:30 when clocks jump from 02:00
+/// to 03:00.
+pub(crate) fn from_wall_clock<Z: TimeZone>(
+    timezone: &Z,
+    wall_clock: &NaiveDateTime,
+) -> DateTime<Z> {
+    match timezone.from_local_datetime(wall_clock) {
+        LocalResult::Single(datetime) => datetime,
+        LocalResult::Ambiguous(earliest, _) => earliest,
+        LocalResult::None => {
+            // The offset in effect before the transition is used, which moves the wall clock
+            // time past the gap
+            let offset_before_gap = timezone
+                .offset_from_utc_datetime(&(*wall_clock - Duration::days(1)))
+                .fix();
+            let utc =
+                *wall_clock - Duration::seconds(i64::from(offset_before_gap.local_minus_utc()));
+            timezone.from_utc_datetime(&utc)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::NaiveDate;
+    use chrono_tz::Tz;
+
+    #[test]
+    fn test_should_interpret_wall_clock_around_dst_transitions() {
+        // Given
+        let timezone = Tz::Europe__Paris;
+        let skipped_wall_clock = NaiveDate::from_ymd(2019, 3, 31).and_hms(2, 30, 0);
+        let repeated_wall_clock = NaiveDate::from_ymd(2019, 10, 27).and_hms(2, 30, 0);
+        let regular_wall_clock = NaiveDate::from_ymd(2019, 10, 27).and_hms(12, 0, 0);
+
+        // When
+        let skipped_datetime = from_wall_clock(&timezone, &skipped_wall_clock);
+        let repeated_datetime = from_wall_clock(&timezone, &repeated_wall_clock);
+        let regular_datetime = from_wall_clock(&timezone, &regular_wall_clock);
+
+        // Then
+        assert_eq!("2019-03-31T03:30:00+02:00", skipped_datetime.to_rfc3339());
+        assert_eq!("2019-10-27T02:30:00+02:00", repeated_datetime.to_rfc3339());
+        assert_eq!("2019-10-27T12:00:00+01:00", regular_datetime.to_rfc3339());
+    }
+
+    #[test]
+    fn test_should_express_reference_with_the_wall_clock_of_the_timezone_in_any_host_timezone() {
+        // Given
+        // New York switched to daylight saving time on 2019-03-10, and Paris on 2019-03-31
+        let host_timezone = Tz::America__New_York;
+        let context = |reference_datetime: DateTime<Utc>| {
+            ExtractionContext::new()
+                .reference_datetime(reference_datetime)
+                .timezone(Tz::Europe__Paris)
+        };
+        let regular_context = context(Utc.ymd(2019, 3, 10).and_hms(7, 30, 0));
+        let skipped_in_host_context = context(Utc.ymd(2019, 3, 10).and_hms(1, 30, 0));
+        let utc_host_context = context(Utc.ymd(2019, 3, 31).and_hms(0, 30, 0));
+
+        // When
+        let regular_reference = regular_context.host_reference(&host_timezone);
+        let skipped_in_host_reference = skipped_in_host_context.host_reference(&host_timezone);
+        let utc_host_reference = utc_host_context.host_reference(&Utc);
+
+        // Then
+        assert_eq!("2019-03-10T08:30:00-04:00", regular_reference.to_rfc3339());
+        assert_eq!(
+            "2019-03-10T03:30:00-04:00",
+            skipped_in_host_reference.to_rfc3339()
+        );
+        assert_eq!("2019-03-31T01:30:00+00:00", utc_host_reference.to_rfc3339());
+    }
+}
@@ -1,7 +1,11 @@
+use crate::context::from_wall_clock;
 use crate::conversion::*;
-use crate::errors::Result;
-use failure::format_err;
-use rustling_ontology::dimension::Precision as RustlingPrecision;
+use crate::errors::{ErrorKind, Result};
+use chrono::{DateTime, Duration, TimeZone};
+use chrono_tz::Tz;
+use rustling_ontology::dimension::{
+    Dimension, Form, Precision as RustlingPrecision, TimeOfDayForm,
+};
 use rustling_ontology::output::{
     AmountOfMoneyOutput, DatetimeIntervalKind, DatetimeIntervalOutput, DatetimeOutput,
     DurationOutput, FloatOutput, IntegerOutput, OrdinalOutput, Output, OutputKind,
@@ -9,8 +13,8 @@ use rustling_ontology::output::{
 };
 use rustling_ontology::Grain as RustlingGrain;
 use rustling_ontology::Lang as RustlingLanguage;
-use rustling_ontology::ParserMatch;
 use snips_nlu_ontology::*;
+use std::fmt::Display;
 
 impl OntologyFrom<IntegerOutput> for NumberValue {
     fn ontology_from(rustling_output: IntegerOutput) -> Self {
@@ -174,13 +178,90 @@ impl OntologyFrom<Output> for SlotValue {
     }
 }
 
-pub fn convert_to_builtin(input: &str, parser_match: ParserMatch<Output>) -> BuiltinEntity {
+pub fn convert_to_builtin(
+    input: &str,
+    byte_range: (usize, usize),
+    char_range: (usize, usize),
+    value: Output,
+) -> BuiltinEntity {
     BuiltinEntity {
-        value: input[parser_match.byte_range.0..parser_match.byte_range.1].into(),
-        range: parser_match.char_range.0..parser_match.char_range.1,
-        entity: parser_match.value.clone().ontology_into(),
+        value: input[byte_range.0..byte_range.1].into(),
+        range: char_range.0..char_range.1,
+        entity_kind: BuiltinEntityKind::ontology_from(&value),
+        entity: value.ontology_into(),
         alternatives: vec![],
-        entity_kind: BuiltinEntityKind::ontology_from(&parser_match.value),
+    }
+}
+
+/// Whether a rustling node is a time of day expressed on a 12-hour clock without specifying the
+/// half of the day, such as "at 5"
+pub fn is_12_hour_clock_time(dimension: &Dimension) -> bool {
+    match dimension {
+        Dimension::Datetime(datetime_value) => match datetime_value.form {
+            Form::TimeOfDay(TimeOfDayForm::Hour { is_12_clock, .. })
+            | Form::TimeOfDay(TimeOfDayForm::HourMinute { is_12_clock, .. })
+            | Form::TimeOfDay(TimeOfDayForm::HourMinuteSecond { is_12_clock, .. }) => is_12_clock,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Returns the reading of a 12-hour clock time in the other half of the day, in `timezone`
+///
+/// Rustling resolves such times to their next occurrence, so the other reading is the one whose
+/// wall clock time is 12 hours later, e.g. 5am on the next day for "at 5" resolved as 5pm. The
+/// hours are added to the wall clock time in `timezone` rather than to the instant, so that the
+/// other reading keeps the same time of day on days with a DST transition.
+pub fn other_half_of_day<Z>(slot_value: &SlotValue, timezone: &Z) -> Option<SlotValue>
+where
+    Z: TimeZone,
+    Z::Offset: Display,
+{
+    match slot_value {
+        SlotValue::InstantTime(value) => DateTime::parse_from_str(&value.value, MOMENT_FORMAT)
+            .ok()
+            .map(|datetime| {
+                let wall_clock = datetime.naive_local() + Duration::hours(12);
+                SlotValue::InstantTime(InstantTimeValue {
+                    value: from_wall_clock(timezone, &wall_clock)
+                        .format(MOMENT_FORMAT)
+                        .to_string(),
+                    ..value.clone()
+                })
+            }),
+        _ => None,
+    }
+}
+
+const MOMENT_FORMAT: &str = "%Y-%m-%d %H:%M:%S %:z";
+
+/// Expresses the resolved datetimes of a rustling entity in the provided timezone, keeping the
+/// same wall clock time
+///
+/// Wall clock times which are repeated or skipped in the timezone because of a DST transition are
+/// mapped as described in `context::from_wall_clock`.
+pub fn convert_to_timezone(slot_value: SlotValue, timezone: Tz) -> SlotValue {
+    match slot_value {
+        SlotValue::InstantTime(value) => SlotValue::InstantTime(InstantTimeValue {
+            value: moment_to_timezone(value.value, timezone),
+            ..value
+        }),
+        SlotValue::TimeInterval(value) => SlotValue::TimeInterval(TimeIntervalValue {
+            from: value.from.map(|from| moment_to_timezone(from, timezone)),
+            to: value.to.map(|to| moment_to_timezone(to, timezone)),
+        }),
+        _ => slot_value,
+    }
+}
+
+fn moment_to_timezone(moment: String, timezone: Tz) -> String {
+    // Moments are formatted by rustling-ontology with `MOMENT_FORMAT`, so they always parse
+    match DateTime::parse_from_str(&moment, MOMENT_FORMAT) {
+        Ok(datetime) => from_wall_clock(&timezone, &datetime.naive_local())
+            .format(MOMENT_FORMAT)
+            .to_string(),
+        Err(_) => moment,
     }
 }
 
@@ -222,7 +303,7 @@ impl<'a> TryOntologyFrom<&'a BuiltinEntityKind> for OutputKind {
             BuiltinEntityKind::DatePeriod => Ok(OutputKind::DatePeriod),
             BuiltinEntityKind::TimePeriod => Ok(OutputKind::TimePeriod),
             BuiltinEntityKind::Percentage => Ok(OutputKind::Percentage),
-            _ => Err(format_err!("Cannot convert {:?} into rustling type", v)),
+            _ => Err(ErrorKind::UnknownEntityKind(format!("{:?}", v)).into()),
         }
     }
 }
@@ -242,3 +323,95 @@ impl OntologyFrom<Language> for RustlingLanguage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_to_timezone() {
+        // Given
+        let slot_value = SlotValue::TimeInterval(TimeIntervalValue {
+            from: Some("2019-10-19 20:00:00 +02:00".to_string()),
+            to: None,
+        });
+
+        // When
+        let converted_value = convert_to_timezone(slot_value, Tz::Asia__Tokyo);
+
+        // Then
+        let expected_value = SlotValue::TimeInterval(TimeIntervalValue {
+            from: Some("2019-10-19 20:00:00 +09:00".to_string()),
+            to: None,
+        });
+        assert_eq!(expected_value, converted_value);
+    }
+
+    #[test]
+    fn test_other_half_of_day() {
+        // Given
+        let slot_value = SlotValue::InstantTime(InstantTimeValue {
+            value: "2019-10-18 17:00:00 +02:00".to_string(),
+            grain: Grain::Hour,
+            precision: Precision::Exact,
+        });
+
+        // When
+        let other_value = other_half_of_day(&slot_value, &Tz::Europe__Paris);
+
+        // Then
+        let expected_value = SlotValue::InstantTime(InstantTimeValue {
+            value: "2019-10-19 05:00:00 +02:00".to_string(),
+            grain: Grain::Hour,
+            precision: Precision::Exact,
+        });
+        assert_eq!(Some(expected_value), other_value);
+    }
+
+    #[test]
+    fn test_other_half_of_day_should_keep_time_of_day_across_dst_transitions() {
+        // Given
+        let instant_time = |value: &str| {
+            SlotValue::InstantTime(InstantTimeValue {
+                value: value.to_string(),
+                grain: Grain::Hour,
+                precision: Precision::Exact,
+            })
+        };
+        let before_spring_forward = instant_time("2019-03-30 17:00:00 +01:00");
+        let before_fall_back = instant_time("2019-10-26 17:00:00 +02:00");
+
+        // When
+        let after_spring_forward = other_half_of_day(&before_spring_forward, &Tz::Europe__Paris);
+        let after_fall_back = other_half_of_day(&before_fall_back, &Tz::Europe__Paris);
+
+        // Then
+        assert_eq!(
+            Some(instant_time("2019-03-31 05:00:00 +02:00")),
+            after_spring_forward
+        );
+        assert_eq!(
+            Some(instant_time("2019-10-27 05:00:00 +01:00")),
+            after_fall_back
+        );
+    }
+
+    #[test]
+    fn test_convert_to_timezone_around_dst_transitions() {
+        // Given
+        let slot_value = SlotValue::TimeInterval(TimeIntervalValue {
+            from: Some("2019-03-31 02:30:00 +00:00".to_string()),
+            to: Some("2019-10-27 02:30:00 +00:00".to_string()),
+        });
+
+        // When
+        let converted_value = convert_to_timezone(slot_value, Tz::Europe__Paris);
+
+        // Then
+        let expected_value = SlotValue::TimeInterval(TimeIntervalValue {
+            from: Some("2019-03-31 03:30:00 +02:00".to_string()),
+            to: Some("2019-10-27 02:30:00 +02:00".to_string()),
+        });
+        assert_eq!(expected_value, converted_value);
+    }
+}
@@ -1 +1,89 @@
-pub type Result<T> = failure::Fallible<T>;
+use failure::{Backtrace, Context, Fail};
+use std::fmt;
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub struct Error {
+    inner: Context<ErrorKind>,
+}
+
+#[derive(Debug, Clone, PartialEq, Fail)]
+pub enum ErrorKind {
+    #[fail(display = "{}", _0)]
+    Persistence(String),
+    #[fail(display = "{}", _0)]
+    MetadataDeserialization(String),
+    #[fail(display = "Incompatible persisted parser: {}", _0)]
+    IncompatibleVersion(String),
+    #[fail(display = "Integrity check failed: {}", _0)]
+    IntegrityCheck(String),
+    #[fail(display = "Unsafe parser layout: {}", _0)]
+    UnsafeLayout(String),
+    #[fail(display = "Unknown language '{}'", _0)]
+    UnknownLanguage(String),
+    #[fail(display = "Unknown entity kind '{}'", _0)]
+    UnknownEntityKind(String),
+    #[fail(display = "No gazetteer parser found for entity '{}'", _0)]
+    GazetteerNotLoaded(String),
+    #[fail(display = "Entity kind '{}' was not loaded by the parser", _0)]
+    EntityKindNotLoaded(String),
+    #[fail(
+        display = "Invalid threshold {} for gazetteer entity '{}', expected a number between 0 and 1",
+        threshold, entity_identifier
+    )]
+    InvalidGazetteerThreshold {
+        entity_identifier: String,
+        threshold: f64,
+    },
+    #[fail(display = "{}", _0)]
+    ParserBuild(String),
+    #[fail(display = "Cannot build thread pool: {}", _0)]
+    ThreadPool(String),
+    #[fail(
+        display = "Cannot parse grammar entities in '{}': {}",
+        sentence, message
+    )]
+    GrammarParsing { sentence: String, message: String },
+    #[fail(
+        display = "Cannot parse gazetteer entities in '{}': {}",
+        sentence, message
+    )]
+    GazetteerParsing { sentence: String, message: String },
+}
+
+impl Error {
+    pub fn kind(&self) -> &ErrorKind {
+        self.inner.get_context()
+    }
+}
+
+impl Fail for Error {
+    fn cause(&self) -> Option<&dyn Fail> {
+        self.inner.cause()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.inner.backtrace()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error {
+            inner: Context::new(kind),
+        }
+    }
+}
+
+impl From<Context<ErrorKind>> for Error {
+    fn from(inner: Context<ErrorKind>) -> Self {
+        Error { inner }
+    }
+}
@@ -1,18 +1,28 @@
 use crate::conversion::gazetteer_entities::convert_to_slot_value;
 use crate::errors::*;
-use failure::{format_err, ResultExt};
+use crate::normalization::NormalizedSentence;
+use crate::offsets::{add_offsets, WithOffsets};
+use crate::persistence::{
+    compute_checksums, compute_fingerprint, confine_path, copy_directory, load_archive,
+    persist_archive, persist_with_staging, read_archive, verify_checksums, write_archive,
+    write_metadata, Checksums, LoadingMode, PersistMode, PersistenceVersions,
+};
+use failure::ResultExt;
 pub use gazetteer_entity_parser::{
     EntityValue, Parser as EntityParser, ParserBuilder as EntityParserBuilder,
 };
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use snips_nlu_ontology::{BuiltinEntity, BuiltinGazetteerEntityKind, IntoBuiltinEntityKind};
-use snips_nlu_utils::string::substring_with_char_range;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::fs;
 use std::fs::File;
+use std::io::{Read, Write};
 use std::ops::Range;
 use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tempfile::{tempdir, TempDir};
 
 pub trait EntityIdentifier:
     Clone + Debug + PartialEq + Serialize + DeserializeOwned + Sized
@@ -34,6 +44,7 @@ impl EntityIdentifier for String {
 impl EntityIdentifier for BuiltinGazetteerEntityKind {
     fn try_from_identifier(identifier: String) -> Result<Self> {
         BuiltinGazetteerEntityKind::from_identifier(&identifier)
+            .map_err(|_| ErrorKind::UnknownEntityKind(identifier).into())
     }
 
     fn into_identifier(self) -> String {
@@ -49,32 +60,270 @@ where
     entity_parsers: Vec<GazetteerEntityParser<T>>,
 }
 
-impl GazetteerParser<BuiltinGazetteerEntityKind> {
+impl<T> GazetteerParser<T>
+where
+    T: EntityIdentifier,
+{
+    fn new(entity_parsers: Vec<GazetteerEntityParser<T>>) -> Self {
+        Self { entity_parsers }
+    }
+
+    /// Adds the provided values to the gazetteer of the entity, with a higher priority than the
+    /// existing values
+    ///
+    /// The first injection in the gazetteer of an entity dumps a copy of its whole gazetteer in a
+    /// temporary directory, which is kept until the parser is dropped, so that the injected values
+    /// can be removed afterwards. This copy takes as long to write, and as much disk space, as
+    /// persisting the entity parser.
     pub fn extend_gazetteer_entity(
         &mut self,
-        entity_kind: BuiltinGazetteerEntityKind,
+        entity_identifier: T,
         entity_values: impl Iterator<Item = EntityValue>,
     ) -> Result<()> {
+        self.get_entity_parser_mut(entity_identifier)?
+            .inject_values(entity_values.collect())
+    }
+
+    /// Removes the values which were injected in the gazetteer of the entity and whose raw value
+    /// is one of the provided raw values
+    pub fn remove_gazetteer_values(
+        &mut self,
+        entity_identifier: T,
+        raw_values: &[String],
+    ) -> Result<()> {
+        let entity_parser = self.get_entity_parser_mut(entity_identifier)?;
+        let injected_values = entity_parser
+            .injected_values
+            .drain(..)
+            .filter(|entity_value| !raw_values.contains(&entity_value.raw_value))
+            .collect();
+        entity_parser.reset_to_baseline(injected_values)
+    }
+
+    /// Replaces all the values which were injected in the gazetteer of the entity with the
+    /// provided values
+    pub fn replace_gazetteer_values(
+        &mut self,
+        entity_identifier: T,
+        entity_values: impl Iterator<Item = EntityValue>,
+    ) -> Result<()> {
+        self.get_entity_parser_mut(entity_identifier)?
+            .reset_to_baseline(entity_values.collect())
+    }
+
+    /// Removes all the values which were injected in the gazetteer of the entity, so that it goes
+    /// back to the state in which it was built
+    ///
+    /// Injected values are persisted separately from the gazetteer they were injected in, so they
+    /// can still be removed after the parser is persisted and loaded back.
+    pub fn reset_gazetteer_entity(&mut self, entity_identifier: T) -> Result<()> {
+        self.get_entity_parser_mut(entity_identifier)?
+            .reset_to_baseline(vec![])
+    }
+
+    fn get_entity_parser_mut(
+        &mut self,
+        entity_identifier: T,
+    ) -> Result<&mut GazetteerEntityParser<T>> {
         self.entity_parsers
             .iter_mut()
-            .find(|entity_parser| entity_parser.entity_identifier == entity_kind)
-            .map(|entity_parser| entity_parser.parser.prepend_values(entity_values.collect()))
+            .find(|entity_parser| entity_parser.entity_identifier == entity_identifier)
             .ok_or_else(|| {
-                format_err!(
-                    "Cannot find gazetteer parser for entity '{:?}'",
-                    entity_kind
-                )
+                ErrorKind::GazetteerNotLoaded(entity_identifier.into_identifier()).into()
             })
     }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(Debug)]
 struct GazetteerEntityParser<T>
 where
     T: EntityIdentifier,
 {
     entity_identifier: T,
     parser: EntityParser,
+    /// Values injected at runtime, ordered by decreasing priority
+    injected_values: Vec<EntityValue>,
+    /// Copy of the parser before any value was injected, dumped on disk on the first injection
+    baseline: Option<TempDir>,
+    /// Copies of the parser configured with the thresholds requested at extraction time, least
+    /// recently used first, which are dropped whenever the values of the gazetteer change
+    threshold_parsers: Mutex<Vec<(f64, Arc<EntityParser>)>>,
+}
+
+/// Maximum number of copies of an entity parser configured with a threshold override which are
+/// kept, as each of them holds a full copy of the gazetteer
+const MAX_THRESHOLD_PARSERS: usize = 4;
+
+impl<T> PartialEq for GazetteerEntityParser<T>
+where
+    T: EntityIdentifier,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.entity_identifier == other.entity_identifier
+            && self.parser == other.parser
+            && self.injected_values == other.injected_values
+    }
+}
+
+impl<T> GazetteerEntityParser<T>
+where
+    T: EntityIdentifier,
+{
+    fn new(entity_identifier: T, parser: EntityParser) -> Self {
+        Self {
+            entity_identifier,
+            parser,
+            injected_values: vec![],
+            baseline: None,
+            threshold_parsers: Mutex::new(vec![]),
+        }
+    }
+
+    fn inject_values(&mut self, mut entity_values: Vec<EntityValue>) -> Result<()> {
+        if self.baseline.is_none() {
+            self.baseline = Some(self.dump_baseline()?);
+        }
+        self.clear_threshold_parsers();
+        self.parser.prepend_values(entity_values.clone());
+        entity_values.append(&mut self.injected_values);
+        self.injected_values = entity_values;
+        Ok(())
+    }
+
+    fn reset_to_baseline(&mut self, injected_values: Vec<EntityValue>) -> Result<()> {
+        if let Some(baseline) = &self.baseline {
+            self.parser =
+                EntityParser::from_folder(baseline.path().join("parser")).with_context(|_| {
+                    ErrorKind::Persistence(format!(
+                        "Cannot restore baseline gazetteer for entity '{}'",
+                        self.entity_identifier.clone().into_identifier()
+                    ))
+                })?;
+            self.injected_values = vec![];
+            self.clear_threshold_parsers();
+        }
+        if injected_values.is_empty() {
+            Ok(())
+        } else {
+            self.inject_values(injected_values)
+        }
+    }
+
+    /// Returns a copy of the parser which matches gazetteer values as soon as the provided ratio
+    /// of their tokens is found
+    ///
+    /// The copy is built the first time this threshold is requested, without holding the lock of
+    /// the cache so that extractions with other thresholds are not blocked meanwhile. Only the
+    /// `MAX_THRESHOLD_PARSERS` most recently used copies are kept.
+    fn parser_with_threshold(&self, threshold: f64) -> Result<Arc<EntityParser>> {
+        if let Some(parser) = self.cached_parser_with_threshold(threshold) {
+            return Ok(parser);
+        }
+        let parser = Arc::new(self.build_parser_with_threshold(threshold)?);
+        let mut threshold_parsers = self.lock_threshold_parsers();
+        if let Some((_, cached_parser)) = threshold_parsers
+            .iter()
+            .find(|(parser_threshold, _)| *parser_threshold == threshold)
+        {
+            // Another extraction built the same copy in the meantime
+            return Ok(cached_parser.clone());
+        }
+        if threshold_parsers.len() >= MAX_THRESHOLD_PARSERS {
+            threshold_parsers.remove(0);
+        }
+        threshold_parsers.push((threshold, parser.clone()));
+        Ok(parser)
+    }
+
+    fn cached_parser_with_threshold(&self, threshold: f64) -> Option<Arc<EntityParser>> {
+        let mut threshold_parsers = self.lock_threshold_parsers();
+        let index = threshold_parsers
+            .iter()
+            .position(|(parser_threshold, _)| *parser_threshold == threshold)?;
+        let threshold_parser = threshold_parsers.remove(index);
+        let parser = threshold_parser.1.clone();
+        threshold_parsers.push(threshold_parser);
+        Some(parser)
+    }
+
+    fn lock_threshold_parsers(&self) -> MutexGuard<Vec<(f64, Arc<EntityParser>)>> {
+        self.threshold_parsers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The gazetteer entity parser has no way to change its threshold, so the parser is dumped,
+    /// and loaded back once the threshold recorded in its metadata has been replaced
+    ///
+    /// This metadata belongs to the gazetteer entity parser: if its format no longer contains a
+    /// numeric threshold, an error is returned rather than a copy using the original threshold.
+    fn build_parser_with_threshold(&self, threshold: f64) -> Result<EntityParser> {
+        let entity_identifier = self.entity_identifier.clone().into_identifier();
+        let threshold_error = || {
+            ErrorKind::ParserBuild(format!(
+                "Cannot configure threshold {} for entity '{}'",
+                threshold, entity_identifier
+            ))
+        };
+        let parser_dir = tempdir().with_context(|_| threshold_error())?;
+        let parser_path = parser_dir.path().join("parser");
+        self.parser
+            .dump(&parser_path)
+            .with_context(|_| threshold_error())?;
+        let metadata_path = parser_path.join("metadata.json");
+        let metadata_file = File::open(&metadata_path).with_context(|_| threshold_error())?;
+        let mut metadata: serde_json::Value =
+            serde_json::from_reader(metadata_file).with_context(|_| threshold_error())?;
+        match metadata.get_mut("threshold") {
+            Some(parser_threshold) if parser_threshold.is_number() => {
+                *parser_threshold = threshold.into()
+            }
+            _ => return Err(threshold_error().into()),
+        }
+        let metadata_file = File::create(&metadata_path).with_context(|_| threshold_error())?;
+        serde_json::to_writer(metadata_file, &metadata).with_context(|_| threshold_error())?;
+        Ok(EntityParser::from_folder(parser_path).with_context(|_| threshold_error())?)
+    }
+
+    fn clear_threshold_parsers(&mut self) {
+        self.threshold_parsers
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clear();
+    }
+
+    /// Dumps the parser as it was before any value was injected, the injected values being
+    /// persisted in the metadata of the gazetteer parser
+    fn dump_without_injected_values(&self, path: &Path) -> Result<()> {
+        match &self.baseline {
+            Some(baseline) => copy_directory(&baseline.path().join("parser"), path),
+            None => Ok(self.parser.dump(path).with_context(|_| {
+                ErrorKind::Persistence(format!(
+                    "Cannot dump entity parser for entity '{}'",
+                    self.entity_identifier.clone().into_identifier()
+                ))
+            })?),
+        }
+    }
+
+    fn dump_baseline(&self) -> Result<TempDir> {
+        let entity_identifier = self.entity_identifier.clone().into_identifier();
+        let baseline = tempdir().with_context(|_| {
+            ErrorKind::Persistence(format!(
+                "Cannot create baseline directory for entity '{}'",
+                entity_identifier
+            ))
+        })?;
+        self.parser
+            .dump(baseline.path().join("parser"))
+            .with_context(|_| {
+                ErrorKind::Persistence(format!(
+                    "Cannot dump baseline gazetteer for entity '{}'",
+                    entity_identifier
+                ))
+            })?;
+        Ok(baseline)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -98,7 +347,7 @@ impl GazetteerParserBuilder {
             .into_iter()
             .map(|parser_builder| parser_builder.build())
             .collect::<Result<_>>()?;
-        Ok(GazetteerParser { entity_parsers })
+        Ok(GazetteerParser::new(entity_parsers))
     }
 }
 
@@ -107,10 +356,12 @@ impl GazetteerEntityParserBuilder {
     where
         T: EntityIdentifier,
     {
-        Ok(GazetteerEntityParser {
-            entity_identifier: T::try_from_identifier(self.entity_identifier)?,
-            parser: self.entity_parser.build()?,
-        })
+        Ok(GazetteerEntityParser::new(
+            T::try_from_identifier(self.entity_identifier)?,
+            self.entity_parser.build().with_context(|_| {
+                ErrorKind::ParserBuild("Cannot build gazetteer entity parser".to_string())
+            })?,
+        ))
     }
 }
 
@@ -124,18 +375,182 @@ where
     pub alternative_resolved_values: Vec<String>,
     pub range: Range<usize>,
     pub entity_identifier: T,
+    /// Scores of the match, only computed when requested, e.g. with
+    /// `GazetteerParser::extract_entities_with_scores`
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub scores: Option<GazetteerMatchScores>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GazetteerMatchScores {
+    /// Jaccard similarity between the tokens of the matched value and the tokens of the
+    /// gazetteer value it was resolved from, see `MatchScore`
+    pub score: f64,
+    /// Ratio of the tokens of the gazetteer value which were found in the matched value, which is
+    /// the ratio the gazetteer entity parser compares to its threshold
+    pub matched_tokens_ratio: f64,
+    /// Scores of the alternative resolved values, in the same order as
+    /// `alternative_resolved_values`
+    pub alternative_matches: Vec<GazetteerAlternativeMatch>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GazetteerAlternativeMatch {
+    pub resolved_value: String,
+    pub score: f64,
+    pub matched_tokens_ratio: f64,
+}
+
+/// Scores of a matched value against a gazetteer value
+///
+/// The `matched_tokens_ratio` is the ratio the gazetteer entity parser compares to its threshold.
+/// The parser splits values on whitespace, and only matches consecutive tokens of the sentence
+/// which are found in the gazetteer value, in the same order, so the number of tokens it found is
+/// the number of tokens of the matched value.
+///
+/// The `score` is the Jaccard similarity between the tokens of both values, split on any
+/// non-alphanumeric character so that punctuation is ignored, and deduplicated. It also accounts
+/// for the tokens of the gazetteer value which were not found, and is meant to rank matches
+/// rather than to be compared with the threshold of the parser.
+struct MatchScore {
+    score: f64,
+    matched_tokens_ratio: f64,
+}
+
+impl MatchScore {
+    fn compute(matched_value: &str, raw_value: &str) -> Self {
+        let matched_tokens = score_tokens(matched_value);
+        let raw_tokens = score_tokens(raw_value);
+        let nb_common_tokens = raw_tokens
+            .iter()
+            .filter(|token| matched_tokens.contains(token))
+            .count();
+        let nb_tokens = matched_tokens.len() + raw_tokens.len() - nb_common_tokens;
+        let nb_matched_tokens = matched_value.split_whitespace().count();
+        let nb_raw_tokens = raw_value.split_whitespace().count();
+        Self {
+            score: ratio(nb_common_tokens, nb_tokens),
+            matched_tokens_ratio: ratio(nb_matched_tokens, nb_raw_tokens).min(1.0),
+        }
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+fn score_tokens(value: &str) -> Vec<String> {
+    let mut tokens = value
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+        .collect::<Vec<_>>();
+    tokens.sort();
+    tokens.dedup();
+    tokens
 }
 
 impl<T> GazetteerParser<T>
 where
     T: EntityIdentifier,
 {
+    /// Extracts entities, grouped by entity and sorted by entity identifier, whatever the order in
+    /// which the entity parsers were provided
     pub fn extract_entities(
         &self,
         sentence: &str,
         filter_entities: Option<&[T]>,
         max_alternative_resolved_values: usize,
     ) -> Result<Vec<GazetteerEntityMatch<T>>> {
+        self.extract_entities_with_thresholds(
+            sentence,
+            filter_entities,
+            max_alternative_resolved_values,
+            &BTreeMap::new(),
+        )
+    }
+
+    /// Extracts entities, the entity parsers of the entities listed in `thresholds` being run
+    /// with the provided threshold instead of the one they were built with, without scores
+    ///
+    /// The thresholds are indexed by entity identifier, and are the minimum ratio of the tokens of
+    /// a gazetteer value which must be found for it to match. They can be lower or higher than the
+    /// threshold the entity parser was built with: when the best value of a match does not reach
+    /// a higher threshold, the best value which does is returned instead. Thresholds which are
+    /// not between 0 and 1 are rejected with `ErrorKind::InvalidGazetteerThreshold`.
+    ///
+    /// The entity parser is copied for each distinct threshold, the first time it is requested,
+    /// which involves dumping and reloading its gazetteer. A few copies are kept for the next
+    /// calls, so that the cost is only paid once when a small set of thresholds is used.
+    pub fn extract_entities_with_thresholds(
+        &self,
+        sentence: &str,
+        filter_entities: Option<&[T]>,
+        max_alternative_resolved_values: usize,
+        thresholds: &BTreeMap<String, f64>,
+    ) -> Result<Vec<GazetteerEntityMatch<T>>> {
+        self.extract_gazetteer_entities(
+            sentence,
+            filter_entities,
+            max_alternative_resolved_values,
+            thresholds,
+            false,
+        )
+    }
+
+    /// Extracts entities like `extract_entities_with_thresholds`, along with the scores of each
+    /// match and of its alternatives
+    pub fn extract_entities_with_scores(
+        &self,
+        sentence: &str,
+        filter_entities: Option<&[T]>,
+        max_alternative_resolved_values: usize,
+        thresholds: &BTreeMap<String, f64>,
+    ) -> Result<Vec<GazetteerEntityMatch<T>>> {
+        self.extract_gazetteer_entities(
+            sentence,
+            filter_entities,
+            max_alternative_resolved_values,
+            thresholds,
+            true,
+        )
+    }
+
+    /// Extracts entities along with their ranges expressed in bytes and in UTF-16 code units,
+    /// applying `thresholds` as in `extract_entities_with_thresholds`, and including scores as in
+    /// `extract_entities_with_scores` when `include_scores` is true
+    pub fn extract_entities_with_offsets(
+        &self,
+        sentence: &str,
+        filter_entities: Option<&[T]>,
+        max_alternative_resolved_values: usize,
+        thresholds: &BTreeMap<String, f64>,
+        include_scores: bool,
+    ) -> Result<Vec<WithOffsets<GazetteerEntityMatch<T>>>> {
+        let entities = self.extract_gazetteer_entities(
+            sentence,
+            filter_entities,
+            max_alternative_resolved_values,
+            thresholds,
+            include_scores,
+        )?;
+        Ok(add_offsets(sentence, entities))
+    }
+
+    fn extract_gazetteer_entities(
+        &self,
+        sentence: &str,
+        filter_entities: Option<&[T]>,
+        max_alternative_resolved_values: usize,
+        thresholds: &BTreeMap<String, f64>,
+        include_scores: bool,
+    ) -> Result<Vec<GazetteerEntityMatch<T>>> {
+        check_thresholds(thresholds)?;
+        let normalized_sentence = NormalizedSentence::lowercase(sentence);
         Ok(self
             .entity_parsers
             .iter()
@@ -145,20 +560,64 @@ where
                     .unwrap_or(true)
             })
             .map(|parser| {
-                Ok(parser
-                    .parser
-                    .run(&sentence.to_lowercase(), max_alternative_resolved_values)?
+                let threshold_parser = thresholds
+                    .get(&parser.entity_identifier.clone().into_identifier())
+                    .map(|threshold| parser.parser_with_threshold(*threshold))
+                    .transpose()?;
+                let entity_parser = threshold_parser
+                    .as_ref()
+                    .map(|threshold_parser| &**threshold_parser)
+                    .unwrap_or(&parser.parser);
+                Ok(entity_parser
+                    .run(
+                        normalized_sentence.normalized(),
+                        max_alternative_resolved_values,
+                    )
+                    .map_err(|error| ErrorKind::GazetteerParsing {
+                        sentence: sentence.to_string(),
+                        message: error.to_string(),
+                    })?
                     .into_iter()
-                    .map(|parsed_value| GazetteerEntityMatch {
-                        value: substring_with_char_range(sentence.to_string(), &parsed_value.range),
-                        range: parsed_value.range,
-                        resolved_value: parsed_value.resolved_value.resolved,
-                        alternative_resolved_values: parsed_value
-                            .alternatives
-                            .into_iter()
-                            .map(|v| v.resolved)
-                            .collect(),
-                        entity_identifier: parser.entity_identifier.clone(),
+                    .map(|parsed_value| {
+                        let range = normalized_sentence.original_char_range(&parsed_value.range);
+                        let value = normalized_sentence.original_substring(&range).to_string();
+                        let scores = if include_scores {
+                            let match_score =
+                                MatchScore::compute(&value, &parsed_value.resolved_value.raw_value);
+                            let alternative_matches = parsed_value
+                                .alternatives
+                                .iter()
+                                .map(|v| {
+                                    let alternative_score =
+                                        MatchScore::compute(&value, &v.raw_value);
+                                    GazetteerAlternativeMatch {
+                                        resolved_value: v.resolved.clone(),
+                                        score: alternative_score.score,
+                                        matched_tokens_ratio: alternative_score
+                                            .matched_tokens_ratio,
+                                    }
+                                })
+                                .collect();
+                            Some(GazetteerMatchScores {
+                                score: match_score.score,
+                                matched_tokens_ratio: match_score.matched_tokens_ratio,
+                                alternative_matches,
+                            })
+                        } else {
+                            None
+                        };
+                        GazetteerEntityMatch {
+                            value,
+                            range,
+                            resolved_value: parsed_value.resolved_value.resolved,
+                            alternative_resolved_values: parsed_value
+                                .alternatives
+                                .into_iter()
+                                .map(|v| v.resolved)
+                                .collect(),
+                            entity_identifier: parser.entity_identifier.clone(),
+                            scores,
+                        }
                     })
                     .collect::<Vec<_>>())
             })
@@ -169,15 +628,50 @@ where
     }
 }
 
+/// Checks that the threshold overrides are ratios, i.e. numbers between 0 and 1
+pub(crate) fn check_thresholds(thresholds: &BTreeMap<String, f64>) -> Result<()> {
+    match thresholds
+        .iter()
+        .find(|(_, threshold)| !(0.0..=1.0).contains(*threshold))
+    {
+        Some((entity_identifier, threshold)) => Err(ErrorKind::InvalidGazetteerThreshold {
+            entity_identifier: entity_identifier.clone(),
+            threshold: *threshold,
+        }
+        .into()),
+        None => Ok(()),
+    }
+}
+
 impl GazetteerParser<BuiltinGazetteerEntityKind> {
     pub fn extract_builtin_entities(
         &self,
         sentence: &str,
         filter_entities: Option<&[BuiltinGazetteerEntityKind]>,
         max_alternative_resolved_values: usize,
+    ) -> Result<Vec<BuiltinEntity>> {
+        self.extract_builtin_entities_with_thresholds(
+            sentence,
+            filter_entities,
+            max_alternative_resolved_values,
+            &BTreeMap::new(),
+        )
+    }
+
+    pub fn extract_builtin_entities_with_thresholds(
+        &self,
+        sentence: &str,
+        filter_entities: Option<&[BuiltinGazetteerEntityKind]>,
+        max_alternative_resolved_values: usize,
+        thresholds: &BTreeMap<String, f64>,
     ) -> Result<Vec<BuiltinEntity>> {
         Ok(self
-            .extract_entities(sentence, filter_entities, max_alternative_resolved_values)?
+            .extract_entities_with_thresholds(
+                sentence,
+                filter_entities,
+                max_alternative_resolved_values,
+                thresholds,
+            )?
             .into_iter()
             .map(|entity_match| {
                 let entity_identifier = entity_match.entity_identifier;
@@ -200,54 +694,92 @@ impl GazetteerParser<BuiltinGazetteerEntityKind> {
 
 #[derive(Serialize, Deserialize, Default)]
 pub struct GazetteerParserMetadata {
+    #[serde(flatten)]
+    pub versions: PersistenceVersions,
     pub parsers_metadata: Vec<EntityParserMetadata>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksums: Option<Checksums>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct EntityParserMetadata {
     pub entity_identifier: String,
     pub entity_parser: String,
+    /// Values injected in the gazetteer at runtime, ordered by decreasing priority, which are not
+    /// part of the persisted entity parser and are injected again when the parser is loaded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub injected_values: Vec<EntityValue>,
 }
 
 impl<T> GazetteerParser<T>
 where
     T: EntityIdentifier,
 {
+    /// Persists the parser in a new directory at `path`
     pub fn persist<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.persist_with_mode(path, PersistMode::CreateNew)
+    }
+
+    /// Persists the parser at `path`, the parser being written in a temporary directory first and
+    /// then moved into place, so that `path` never contains a partially written parser
+    ///
+    /// On Linux, overwriting swaps the previous parser and the new one atomically. Elsewhere, the
+    /// previous parser is moved aside before the new one takes its place, and if the process is
+    /// interrupted in between, `path` is missing until the next overwrite of `path`, which
+    /// restores the previous parser first. Overwrites of the same `path` wait for each other by
+    /// locking a `.{name}.lock` file created next to `path`.
+    pub fn persist_with_mode<P: AsRef<Path>>(&self, path: P, mode: PersistMode) -> Result<()> {
+        persist_with_staging(path, mode, |staging_path| self.write_files(staging_path))
+    }
+
+    /// Persists the parser in a single tar archive file at `path`, which must not exist
+    pub fn persist_to_archive<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        persist_archive(path, |staging_path| self.write_files(staging_path))
+    }
+
+    /// Writes the parser into `writer`, using the same format as `persist_to_archive`
+    pub fn persist_to_writer<W: Write>(&self, writer: W) -> Result<()> {
+        write_archive(writer, |staging_path| self.write_files(staging_path))
+    }
+
+    pub(crate) fn write_files<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         fs::create_dir(path.as_ref()).with_context(|_| {
-            format!(
+            ErrorKind::Persistence(format!(
                 "Cannot create gazetteer parser directory at path: {:?}",
                 path.as_ref()
-            )
+            ))
         })?;
-        let mut gazetteer_parser_metadata = GazetteerParserMetadata::default();
-        for (index, entity_parser) in self.entity_parsers.iter().enumerate() {
+        let mut gazetteer_parser_metadata = GazetteerParserMetadata {
+            versions: PersistenceVersions::current(),
+            parsers_metadata: vec![],
+            checksums: None,
+        };
+        // Entity parsers are persisted sorted by entity identifier, so that the persisted layout
+        // does not depend on the order in which they were provided
+        let mut entity_parsers = self.entity_parsers.iter().collect::<Vec<_>>();
+        entity_parsers
+            .sort_by_key(|entity_parser| entity_parser.entity_identifier.clone().into_identifier());
+        for (index, entity_parser) in entity_parsers.into_iter().enumerate() {
             let parser_directory = format!("parser_{}", index + 1);
             let parser_path = path.as_ref().join(&parser_directory);
-            let entity_identifier = entity_parser.entity_identifier.clone().into_identifier();
-            entity_parser.parser.dump(parser_path).with_context(|_| {
-                format!(
-                    "Cannot dump entity parser for entity '{}'",
-                    &entity_identifier
-                )
-            })?;
+            entity_parser.dump_without_injected_values(&parser_path)?;
             gazetteer_parser_metadata
                 .parsers_metadata
                 .push(EntityParserMetadata {
-                    entity_identifier,
+                    entity_identifier: entity_parser.entity_identifier.clone().into_identifier(),
                     entity_parser: parser_directory,
+                    injected_values: entity_parser.injected_values.clone(),
                 })
         }
-        let metadata_path = path.as_ref().join("metadata.json");
-        let metadata_file = File::create(&metadata_path).with_context(|_| {
-            format!(
-                "Cannot create metadata file for gazetteer parser at path: {:?}",
-                metadata_path
-            )
-        })?;
-        serde_json::to_writer_pretty(metadata_file, &gazetteer_parser_metadata)
-            .with_context(|_| "Cannot serialize gazetteer parser metadata")?;
-        Ok(())
+        gazetteer_parser_metadata.checksums = Some(compute_checksums(path.as_ref())?);
+        write_metadata(path.as_ref(), &gazetteer_parser_metadata)
+    }
+
+    /// Computes a SHA-256 fingerprint of the persisted content of the parser
+    ///
+    /// Two parsers which would be persisted into identical files have the same fingerprint.
+    pub fn fingerprint(&self) -> Result<String> {
+        compute_fingerprint(|staging_path| self.write_files(staging_path))
     }
 }
 
@@ -255,38 +787,138 @@ impl<T> GazetteerParser<T>
 where
     T: EntityIdentifier,
 {
+    /// Loads a parser persisted with `persist_to_archive`, applying the provided `LoadingMode` to
+    /// the content of the archive
+    pub fn from_archive<P: AsRef<Path>>(path: P, loading_mode: &LoadingMode) -> Result<Self> {
+        load_archive(path, loading_mode, |path| {
+            Self::from_path_with_loading_mode(path, loading_mode)
+        })
+    }
+
+    /// Loads a parser from a reader, using the format produced by `persist_to_writer`
+    ///
+    /// The content is unpacked in a temporary directory which is removed once the parser is
+    /// loaded. In `LoadingMode::Untrusted`, the entries of the archive are checked before being
+    /// unpacked.
+    pub fn from_reader<R: Read>(reader: R, loading_mode: &LoadingMode) -> Result<Self> {
+        read_archive(reader, loading_mode, |path| {
+            Self::from_path_with_loading_mode(path, loading_mode)
+        })
+    }
+
+    /// Loads a parser from an in-memory buffer, using the format produced by `persist_to_writer`
+    pub fn from_bytes(bytes: &[u8], loading_mode: &LoadingMode) -> Result<Self> {
+        Self::from_reader(bytes, loading_mode)
+    }
+
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let metadata_path = path.as_ref().join("metadata.json");
-        let metadata_file = File::open(&metadata_path).with_context(|_| {
-            format!(
-                "Cannot open metadata file for gazetteer parser at path: {:?}",
-                metadata_path
-            )
-        })?;
-        let metadata: GazetteerParserMetadata = serde_json::from_reader(metadata_file)
-            .with_context(|_| "Cannot deserialize gazetteer parser metadata")?;
+        Self::from_path_with_entities(path, None)
+    }
+
+    /// Loads the gazetteer parser located at `path`, restricted to the entity parsers of the
+    /// provided entities when `entity_identifiers` is not `None`
+    pub fn from_path_with_entities<P: AsRef<Path>>(
+        path: P,
+        entity_identifiers: Option<&[T]>,
+    ) -> Result<Self> {
+        Self::load_from_path(path, entity_identifiers, &LoadingMode::Trusted)
+    }
+
+    /// Loads the gazetteer parser located at `path`, applying the provided `LoadingMode`
+    pub fn from_path_with_loading_mode<P: AsRef<Path>>(
+        path: P,
+        loading_mode: &LoadingMode,
+    ) -> Result<Self> {
+        Self::load_from_path(path, None, loading_mode)
+    }
+
+    pub(crate) fn load_from_path<P: AsRef<Path>>(
+        path: P,
+        entity_identifiers: Option<&[T]>,
+        loading_mode: &LoadingMode,
+    ) -> Result<Self> {
+        loading_mode.check_parser_directory(path.as_ref())?;
+        let metadata = Self::load_metadata(path.as_ref())?;
+        metadata.versions.check_compatibility()?;
+        loading_mode.check_nb_entity_parsers(metadata.parsers_metadata.len())?;
         let entity_parsers = metadata
             .parsers_metadata
             .into_iter()
             .map(|entity_parser_metadata| {
-                let parser = EntityParser::from_folder(
-                    path.as_ref().join(&entity_parser_metadata.entity_parser),
-                )
-                .with_context(|_| {
-                    format!(
+                let entity_identifier =
+                    T::try_from_identifier(entity_parser_metadata.entity_identifier)?;
+                Ok((
+                    entity_identifier,
+                    entity_parser_metadata.entity_parser,
+                    entity_parser_metadata.injected_values,
+                ))
+            })
+            .filter(|entity_parser_metadata| match entity_parser_metadata {
+                Ok((entity_identifier, _, _)) => entity_identifiers
+                    .map(|identifiers| identifiers.contains(entity_identifier))
+                    .unwrap_or(true),
+                Err(_) => true,
+            })
+            .map(|entity_parser_metadata| {
+                let (entity_identifier, entity_parser_directory, injected_values) =
+                    entity_parser_metadata?;
+                let entity_parser_path =
+                    loading_mode.resolve_path(path.as_ref(), &entity_parser_directory)?;
+                let parser = EntityParser::from_folder(entity_parser_path).with_context(|_| {
+                    ErrorKind::Persistence(format!(
                         "Cannot create entity parser from path: {}",
-                        entity_parser_metadata.entity_parser
-                    )
+                        entity_parser_directory
+                    ))
                 })?;
-                Ok(GazetteerEntityParser {
-                    entity_identifier: T::try_from_identifier(
-                        entity_parser_metadata.entity_identifier,
-                    )?,
-                    parser,
-                })
+                let mut entity_parser = GazetteerEntityParser::new(entity_identifier, parser);
+                if !injected_values.is_empty() {
+                    entity_parser.inject_values(injected_values)?;
+                }
+                Ok(entity_parser)
             })
             .collect::<Result<_>>()?;
-        Ok(Self { entity_parsers })
+        Ok(Self::new(entity_parsers))
+    }
+
+    /// Checks the integrity of the parser persisted at `path` without loading it
+    ///
+    /// The metadata is checked for compatibility, and the persisted files are checked against the
+    /// checksums recorded in it. Parsers persisted before checksums were recorded only get their
+    /// metadata checked, along with the presence of the entity parser directories, which must be
+    /// confined to `path`.
+    pub fn verify<P: AsRef<Path>>(path: P) -> Result<()> {
+        let metadata = Self::load_metadata(path.as_ref())?;
+        metadata.versions.check_compatibility()?;
+        for entity_parser_metadata in metadata.parsers_metadata.iter() {
+            let entity_parser_path =
+                confine_path(path.as_ref(), &entity_parser_metadata.entity_parser)?;
+            if !entity_parser_path.is_dir() {
+                return Err(ErrorKind::IntegrityCheck(format!(
+                    "missing parser directory for entity '{}': {:?}",
+                    entity_parser_metadata.entity_identifier, entity_parser_path
+                ))
+                .into());
+            }
+        }
+        if let Some(checksums) = metadata.checksums {
+            verify_checksums(path.as_ref(), &checksums)?;
+        }
+        Ok(())
+    }
+
+    fn load_metadata(path: &Path) -> Result<GazetteerParserMetadata> {
+        let metadata_path = path.join("metadata.json");
+        let metadata_file = File::open(&metadata_path).with_context(|_| {
+            ErrorKind::Persistence(format!(
+                "Cannot open metadata file for gazetteer parser at path: {:?}",
+                metadata_path
+            ))
+        })?;
+        Ok(serde_json::from_reader(metadata_file).with_context(|_| {
+            ErrorKind::MetadataDeserialization(
+                "Cannot deserialize gazetteer parser metadata".to_string(),
+            )
+        })?)
     }
 }
 
@@ -298,6 +930,7 @@ mod test {
     };
     use tempfile::tempdir;
 
+    use crate::persistence::LoadingLimits;
     use crate::test_utils::test_path;
 
     use super::*;
@@ -382,7 +1015,8 @@ mod test {
 
         // When
         let input = "I want to listen to the track harder better faster please";
-        let result = gazetteer_parser.extract_entities(input, None, 5);
+        let result =
+            gazetteer_parser.extract_entities_with_scores(input, None, 5, &BTreeMap::new());
 
         // Then
         let expected_match = GazetteerEntityMatch {
@@ -391,10 +1025,49 @@ mod test {
             alternative_resolved_values: vec![],
             range: 30..50,
             entity_identifier: "music_track".to_string(),
+            scores: Some(GazetteerMatchScores {
+                score: 0.75,
+                matched_tokens_ratio: 0.75,
+                alternative_matches: vec![],
+            }),
+        };
+        assert_eq!(Some(vec![expected_match]), result.ok());
+    }
+
+    #[test]
+    fn test_should_parse_input_whose_lowercase_form_changes_length() {
+        // Given
+        let gazetteer_parser = get_test_custom_gazetteer_parser();
+
+        // When
+        let input = "İZMİR: play the track HARDER BETTER FASTER please";
+        let result = gazetteer_parser.extract_entities(input, None, 5);
+
+        // Then
+        let expected_match = GazetteerEntityMatch {
+            value: "HARDER BETTER FASTER".to_string(),
+            resolved_value: "Harder Better Faster Stronger".to_string(),
+            alternative_resolved_values: vec![],
+            range: 22..42,
+            entity_identifier: "music_track".to_string(),
+            scores: None,
         };
         assert_eq!(Some(vec![expected_match]), result.ok());
     }
 
+    #[test]
+    fn test_should_score_matches_by_tokens() {
+        // When
+        let partial_score = MatchScore::compute("The Stones!", "the rolling stones");
+        let extra_tokens_score = MatchScore::compute("rolling stones live", "rolling stones");
+
+        // Then
+        assert_eq!(2.0 / 3.0, partial_score.score);
+        assert_eq!(2.0 / 3.0, partial_score.matched_tokens_ratio);
+        assert_eq!(2.0 / 3.0, extra_tokens_score.score);
+        assert_eq!(1.0, extra_tokens_score.matched_tokens_ratio);
+    }
+
     #[test]
     fn test_should_not_parse_below_threshold() {
         // Given
@@ -408,6 +1081,201 @@ mod test {
         assert_eq!(Some(vec![]), result.ok());
     }
 
+    #[test]
+    fn test_should_parse_with_threshold_overrides() {
+        // Given
+        let gazetteer_parser = get_test_custom_gazetteer_parser();
+        let input = "I want to listen to the track harder better faster by the stones";
+        let mut strict_thresholds = BTreeMap::new();
+        strict_thresholds.insert("music_track".to_string(), 0.8);
+        let mut loose_thresholds = BTreeMap::new();
+        loose_thresholds.insert("music_track".to_string(), 0.75);
+
+        // When
+        let strict_result = gazetteer_parser
+            .extract_entities_with_thresholds(input, None, 5, &strict_thresholds)
+            .unwrap();
+        let loose_result = gazetteer_parser
+            .extract_entities_with_thresholds(input, None, 5, &loose_thresholds)
+            .unwrap();
+
+        // Then
+        let values = |matches: Vec<GazetteerEntityMatch<String>>| {
+            matches
+                .into_iter()
+                .map(|entity_match| entity_match.value)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["the stones"], values(strict_result));
+        assert_eq!(
+            vec!["the stones", "harder better faster"],
+            values(loose_result)
+        );
+    }
+
+    #[test]
+    fn test_should_parse_below_threshold_with_lower_threshold_override() {
+        // Given
+        let gazetteer_parser = get_test_custom_gazetteer_parser();
+        let input = "I want to listen to the track harder better please";
+        let mut loose_thresholds = BTreeMap::new();
+        loose_thresholds.insert("music_track".to_string(), 0.5);
+
+        // When
+        let default_result = gazetteer_parser.extract_entities(input, None, 5).unwrap();
+        let loose_result = gazetteer_parser
+            .extract_entities_with_scores(input, None, 5, &loose_thresholds)
+            .unwrap();
+
+        // Then
+        assert_eq!(Vec::<GazetteerEntityMatch<String>>::new(), default_result);
+        assert_eq!(1, loose_result.len());
+        assert_eq!("harder better", loose_result[0].value);
+        assert_eq!(
+            "Harder Better Faster Stronger",
+            loose_result[0].resolved_value
+        );
+        assert_eq!(
+            Some(0.5),
+            loose_result[0]
+                .scores
+                .as_ref()
+                .map(|scores| scores.matched_tokens_ratio)
+        );
+    }
+
+    #[test]
+    fn test_should_keep_values_reaching_threshold_override() {
+        // Given
+        let artist_entity_parser_builder = EntityParserBuilder::default()
+            .minimum_tokens_ratio(0.2)
+            .gazetteer(gazetteer!(
+                ("stones and roses and thorns", "Stones And Roses And Thorns"),
+                ("rolling stones", "Rolling Stones"),
+            ));
+        let gazetteer_parser = GazetteerParserBuilder {
+            entity_parsers: vec![GazetteerEntityParserBuilder {
+                entity_identifier: "music_artist".to_string(),
+                entity_parser: artist_entity_parser_builder,
+            }],
+        }
+        .build()
+        .unwrap();
+        let input = "I want to listen to stones";
+        let mut strict_thresholds = BTreeMap::new();
+        strict_thresholds.insert("music_artist".to_string(), 0.5);
+
+        // When
+        let default_result = gazetteer_parser.extract_entities(input, None, 5).unwrap();
+        let strict_result = gazetteer_parser
+            .extract_entities_with_scores(input, None, 5, &strict_thresholds)
+            .unwrap();
+
+        // Then
+        let mut default_values = default_result
+            .into_iter()
+            .flat_map(|entity_match| {
+                let mut values = vec![entity_match.resolved_value];
+                values.extend(entity_match.alternative_resolved_values);
+                values
+            })
+            .collect::<Vec<_>>();
+        default_values.sort();
+        assert_eq!(
+            vec!["Rolling Stones", "Stones And Roses And Thorns"],
+            default_values
+        );
+        assert_eq!(1, strict_result.len());
+        assert_eq!("stones", strict_result[0].value);
+        assert_eq!("Rolling Stones", strict_result[0].resolved_value);
+        assert_eq!(
+            Vec::<String>::new(),
+            strict_result[0].alternative_resolved_values
+        );
+        assert_eq!(
+            Some(0.5),
+            strict_result[0]
+                .scores
+                .as_ref()
+                .map(|scores| scores.matched_tokens_ratio)
+        );
+    }
+
+    #[test]
+    fn test_should_reject_invalid_thresholds() {
+        // Given
+        let gazetteer_parser = get_test_custom_gazetteer_parser();
+        let input = "I want to listen to the stones";
+
+        for &threshold in [-0.1, 1.5, ::std::f64::NAN].iter() {
+            let mut thresholds = BTreeMap::new();
+            thresholds.insert("music_artist".to_string(), threshold);
+
+            // When
+            let result =
+                gazetteer_parser.extract_entities_with_thresholds(input, None, 5, &thresholds);
+
+            // Then
+            match result.unwrap_err().kind() {
+                ErrorKind::InvalidGazetteerThreshold {
+                    entity_identifier, ..
+                } => assert_eq!("music_artist", entity_identifier),
+                kind => panic!("Unexpected error kind: {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn test_should_keep_a_bounded_number_of_threshold_parsers() {
+        // Given
+        let gazetteer_parser = get_test_custom_gazetteer_parser();
+        let input = "I want to listen to the stones";
+
+        // When
+        for index in 0..MAX_THRESHOLD_PARSERS + 2 {
+            let mut thresholds = BTreeMap::new();
+            thresholds.insert("music_artist".to_string(), 0.3 + 0.05 * index as f64);
+            gazetteer_parser
+                .extract_entities_with_thresholds(input, None, 5, &thresholds)
+                .unwrap();
+        }
+
+        // Then
+        let artist_parser = gazetteer_parser
+            .entity_parsers
+            .iter()
+            .find(|entity_parser| entity_parser.entity_identifier == "music_artist")
+            .unwrap();
+        let cached_thresholds = artist_parser
+            .lock_threshold_parsers()
+            .iter()
+            .map(|(threshold, _)| *threshold)
+            .collect::<Vec<_>>();
+        assert_eq!(MAX_THRESHOLD_PARSERS, cached_thresholds.len());
+        assert_eq!(
+            0.3 + 0.05 * (MAX_THRESHOLD_PARSERS + 1) as f64,
+            *cached_thresholds.last().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_should_apply_thresholds_when_extracting_with_offsets() {
+        // Given
+        let gazetteer_parser = get_test_custom_gazetteer_parser();
+        let input = "I want to listen to the track harder better please";
+        let mut loose_thresholds = BTreeMap::new();
+        loose_thresholds.insert("music_track".to_string(), 0.5);
+
+        // When
+        let result = gazetteer_parser
+            .extract_entities_with_offsets(input, None, 5, &loose_thresholds, false)
+            .unwrap();
+
+        // Then
+        assert_eq!(1, result.len());
+        assert_eq!("harder better", result[0].entity.value);
+    }
+
     #[test]
     fn test_should_parse_using_scope() {
         // Given
@@ -427,6 +1295,7 @@ mod test {
             alternative_resolved_values: vec![],
             range: 43..62,
             entity_identifier: "music_artist".to_string(),
+            scores: None,
         };
 
         let expected_track_match = GazetteerEntityMatch {
@@ -435,6 +1304,7 @@ mod test {
             alternative_resolved_values: vec![],
             range: 20..39,
             entity_identifier: "music_track".to_string(),
+            scores: None,
         };
         assert_eq!(Some(vec![expected_artist_match]), result_artist.ok());
         assert_eq!(Some(vec![expected_track_match]), result_track.ok());
@@ -454,7 +1324,8 @@ mod test {
 
         // When
         let input = "I want to listen to the stones";
-        let result = gazetteer_parser.extract_entities(input, None, 1);
+        let result =
+            gazetteer_parser.extract_entities_with_scores(input, None, 1, &BTreeMap::new());
 
         // Then
         let expected_match = GazetteerEntityMatch {
@@ -463,6 +1334,15 @@ mod test {
             alternative_resolved_values: vec!["The Crying Stones".to_string()],
             range: 20..30,
             entity_identifier: "music_artist".to_string(),
+            scores: Some(GazetteerMatchScores {
+                score: 2.0 / 3.0,
+                matched_tokens_ratio: 2.0 / 3.0,
+                alternative_matches: vec![GazetteerAlternativeMatch {
+                    resolved_value: "The Crying Stones".to_string(),
+                    score: 2.0 / 3.0,
+                    matched_tokens_ratio: 2.0 / 3.0,
+                }],
+            }),
         };
         assert_eq!(Some(vec![expected_match]), result.ok());
     }
@@ -489,6 +1369,122 @@ mod test {
         assert_eq!(Some(vec![expected_match]), result.ok());
     }
 
+    #[test]
+    fn test_should_extend_custom_gazetteer_entity() {
+        // Given
+        let mut gazetteer_parser = get_test_custom_gazetteer_parser();
+        let entity_values = vec![EntityValue {
+            raw_value: "my favorite band".to_string(),
+            resolved_value: "My Favorite Band".to_string(),
+        }];
+
+        // When
+        gazetteer_parser
+            .extend_gazetteer_entity("music_artist".to_string(), entity_values.into_iter())
+            .unwrap();
+        let result = gazetteer_parser.extract_entities("play my favorite band", None, 5);
+        let unknown_entity_error = gazetteer_parser
+            .extend_gazetteer_entity("music_album".to_string(), vec![].into_iter())
+            .unwrap_err();
+
+        // Then
+        let expected_match = GazetteerEntityMatch {
+            value: "my favorite band".to_string(),
+            resolved_value: "My Favorite Band".to_string(),
+            alternative_resolved_values: vec![],
+            range: 5..21,
+            entity_identifier: "music_artist".to_string(),
+            scores: None,
+        };
+        assert_eq!(Some(vec![expected_match]), result.ok());
+        assert_eq!(
+            &ErrorKind::GazetteerNotLoaded("music_album".to_string()),
+            unknown_entity_error.kind()
+        );
+    }
+
+    #[test]
+    fn test_should_remove_replace_and_reset_injected_values() {
+        // Given
+        let mut gazetteer_parser = get_test_custom_gazetteer_parser();
+        let entity_value = |raw_value: &str| EntityValue {
+            raw_value: raw_value.to_string(),
+            resolved_value: raw_value.to_uppercase(),
+        };
+        let artist = "music_artist".to_string();
+        let extract_artists = |parser: &GazetteerParser<String>, input: &str| {
+            parser
+                .extract_entities(input, None, 0)
+                .unwrap()
+                .into_iter()
+                .map(|entity_match| entity_match.resolved_value)
+                .collect::<Vec<_>>()
+        };
+        gazetteer_parser
+            .extend_gazetteer_entity(
+                artist.clone(),
+                vec![entity_value("old band"), entity_value("jazz band")].into_iter(),
+            )
+            .unwrap();
+
+        // When
+        gazetteer_parser
+            .remove_gazetteer_values(artist.clone(), &["old band".to_string()])
+            .unwrap();
+        let after_removal = extract_artists(&gazetteer_parser, "play old band then jazz band");
+        gazetteer_parser
+            .replace_gazetteer_values(artist.clone(), vec![entity_value("new band")].into_iter())
+            .unwrap();
+        let after_replacement = extract_artists(&gazetteer_parser, "play jazz band then new band");
+        gazetteer_parser.reset_gazetteer_entity(artist).unwrap();
+        let after_reset =
+            extract_artists(&gazetteer_parser, "play new band then the rolling stones");
+
+        // Then
+        assert_eq!(vec!["JAZZ BAND".to_string()], after_removal);
+        assert_eq!(vec!["NEW BAND".to_string()], after_replacement);
+        assert_eq!(vec!["The Rolling Stones".to_string()], after_reset);
+        assert_eq!(get_test_custom_gazetteer_parser(), gazetteer_parser);
+    }
+
+    #[test]
+    fn test_should_reset_injected_values_after_reloading_persisted_parser() {
+        // Given
+        let mut gazetteer_parser = get_test_custom_gazetteer_parser();
+        let artist = "music_artist".to_string();
+        gazetteer_parser
+            .extend_gazetteer_entity(
+                artist.clone(),
+                vec![EntityValue {
+                    raw_value: "my favorite band".to_string(),
+                    resolved_value: "My Favorite Band".to_string(),
+                }]
+                .into_iter(),
+            )
+            .unwrap();
+        let temp_dir = tempdir().unwrap();
+        let parser_dir = temp_dir.path().join("custom_gazetteer_parser");
+
+        // When
+        gazetteer_parser.persist(&parser_dir).unwrap();
+        let mut loaded_gazetteer_parser = GazetteerParser::from_path(&parser_dir).unwrap();
+        let before_reset = loaded_gazetteer_parser
+            .extract_entities("play my favorite band", None, 5)
+            .unwrap();
+        loaded_gazetteer_parser
+            .reset_gazetteer_entity(artist)
+            .unwrap();
+        let after_reset = loaded_gazetteer_parser
+            .extract_entities("play my favorite band", None, 5)
+            .unwrap();
+
+        // Then
+        assert_eq!(1, before_reset.len());
+        assert_eq!("My Favorite Band", before_reset[0].resolved_value);
+        assert!(after_reset.is_empty());
+        assert_eq!(get_test_custom_gazetteer_parser(), loaded_gazetteer_parser);
+    }
+
     #[test]
     fn test_should_persist_custom_gazetteer_parser() {
         // Given
@@ -506,6 +1502,376 @@ mod test {
         assert_eq!(gazetteer_parser, loaded_gazetteer_parser);
     }
 
+    #[test]
+    fn test_should_persist_custom_gazetteer_parser_to_archive() {
+        // Given
+        let gazetteer_parser = get_test_custom_gazetteer_parser();
+        let temp_dir = tempdir().unwrap();
+        let archive_path = temp_dir.path().join("custom_gazetteer_parser.tar");
+
+        // When
+        gazetteer_parser.persist_to_archive(&archive_path).unwrap();
+        let loaded_gazetteer_parser =
+            GazetteerParser::from_archive(&archive_path, &LoadingMode::Trusted).unwrap();
+
+        // Then
+        let archive_file = fs::File::open(&archive_path).unwrap();
+        let has_license = tar::Archive::new(archive_file)
+            .entries()
+            .unwrap()
+            .any(|entry| {
+                entry
+                    .unwrap()
+                    .path()
+                    .unwrap()
+                    .ends_with(Path::new("parser_1").join("LICENSE"))
+            });
+        assert!(has_license);
+        assert!(gazetteer_parser.persist_to_archive(&archive_path).is_err());
+        assert_eq!(gazetteer_parser, loaded_gazetteer_parser);
+    }
+
+    #[test]
+    fn test_should_load_custom_gazetteer_parser_from_bytes() {
+        // Given
+        let gazetteer_parser = get_test_custom_gazetteer_parser();
+        let mut parser_bytes = vec![];
+        gazetteer_parser
+            .persist_to_writer(&mut parser_bytes)
+            .unwrap();
+
+        // When
+        let loaded_gazetteer_parser =
+            GazetteerParser::from_bytes(&parser_bytes, &LoadingMode::Trusted).unwrap();
+        let untrusted_loading_mode = LoadingMode::Untrusted(LoadingLimits::default());
+        let untrusted_gazetteer_parser =
+            GazetteerParser::from_bytes(&parser_bytes, &untrusted_loading_mode).unwrap();
+
+        // Then
+        assert_eq!(gazetteer_parser, loaded_gazetteer_parser);
+        assert_eq!(gazetteer_parser, untrusted_gazetteer_parser);
+    }
+
+    #[test]
+    fn test_should_verify_persisted_custom_gazetteer_parser() {
+        // Given
+        let gazetteer_parser = get_test_custom_gazetteer_parser();
+        let temp_dir = tempdir().unwrap();
+        let parser_dir = temp_dir.path().join("custom_gazetteer_parser");
+        gazetteer_parser.persist(&parser_dir).unwrap();
+
+        // When
+        let valid_result = GazetteerParser::<String>::verify(&parser_dir);
+        let legacy_result =
+            GazetteerParser::<String>::verify(test_path().join("custom_gazetteer_parser"));
+        fs::remove_file(parser_dir.join("parser_1").join("LICENSE")).unwrap();
+        let missing_file_result = GazetteerParser::<String>::verify(&parser_dir);
+        fs::remove_dir_all(parser_dir.join("parser_1")).unwrap();
+        let missing_parser_result = GazetteerParser::<String>::verify(&parser_dir);
+
+        // Then
+        assert!(valid_result.is_ok());
+        assert!(legacy_result.is_ok());
+        assert_eq!(
+            &ErrorKind::IntegrityCheck(format!(
+                "file 'parser_1/LICENSE' is missing in {:?}",
+                parser_dir
+            )),
+            missing_file_result.unwrap_err().kind()
+        );
+        assert_eq!(
+            &ErrorKind::IntegrityCheck(format!(
+                "missing parser directory for entity 'music_artist': {:?}",
+                parser_dir.join("parser_1")
+            )),
+            missing_parser_result.unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn test_should_not_follow_unconfined_paths_in_untrusted_mode() {
+        // Given
+        let temp_dir = tempdir().unwrap();
+        let parser_dir = temp_dir.path().join("custom_gazetteer_parser");
+        get_test_custom_gazetteer_parser()
+            .persist(&parser_dir)
+            .unwrap();
+        let metadata_path = parser_dir.join("metadata.json");
+        let mut metadata: serde_json::Value =
+            serde_json::from_reader(File::open(&metadata_path).unwrap()).unwrap();
+        metadata["parsers_metadata"][0]["entity_parser"] =
+            "../custom_gazetteer_parser/parser_1".into();
+        serde_json::to_writer(File::create(&metadata_path).unwrap(), &metadata).unwrap();
+        let loading_mode = LoadingMode::Untrusted(LoadingLimits::default());
+
+        // When
+        let trusted_result = GazetteerParser::<String>::from_path(&parser_dir);
+        let untrusted_result =
+            GazetteerParser::<String>::from_path_with_loading_mode(&parser_dir, &loading_mode);
+
+        // Then
+        assert!(trusted_result.is_ok());
+        assert_eq!(
+            &ErrorKind::UnsafeLayout(
+                "path '../custom_gazetteer_parser/parser_1' referenced in the metadata is not \
+                 confined to the parser directory"
+                    .to_string()
+            ),
+            untrusted_result.unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn test_should_limit_entity_parsers_in_untrusted_mode() {
+        // Given
+        let path = test_path().join("custom_gazetteer_parser");
+        let loading_mode = LoadingMode::Untrusted(LoadingLimits {
+            max_entity_parsers: 1,
+            ..LoadingLimits::default()
+        });
+
+        // When
+        let result = GazetteerParser::<String>::from_path_with_loading_mode(path, &loading_mode);
+
+        // Then
+        assert_eq!(
+            &ErrorKind::UnsafeLayout(
+                "2 entity parsers found, the maximum allowed is 1".to_string()
+            ),
+            result.unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn test_should_persist_entity_parsers_sorted_by_identifier() {
+        // Given
+        let gazetteer_parser_builder = GazetteerParserBuilder {
+            entity_parsers: vec![
+                GazetteerEntityParserBuilder {
+                    entity_identifier: "music_track".to_string(),
+                    entity_parser: get_music_track_parser_builder(),
+                },
+                GazetteerEntityParserBuilder {
+                    entity_identifier: "music_artist".to_string(),
+                    entity_parser: get_music_artist_parser_builder().license_info(LicenseInfo {
+                        filename: "LICENSE".to_string(),
+                        content: "Some license content\nhere\n".to_string(),
+                    }),
+                },
+            ],
+        };
+
+        let gazetteer_parser = gazetteer_parser_builder.build::<String>().unwrap();
+        let temp_dir = tempdir().unwrap();
+        let parser_dir = temp_dir.path().join("gazetteer_parser");
+
+        // When
+        gazetteer_parser.persist(&parser_dir).unwrap();
+        let loaded_gazetteer_parser = GazetteerParser::from_path(&parser_dir).unwrap();
+
+        // Then
+        assert_ne!(get_test_custom_gazetteer_parser(), gazetteer_parser);
+        assert_eq!(get_test_custom_gazetteer_parser(), loaded_gazetteer_parser);
+        assert!(parser_dir.join("parser_1").join("LICENSE").exists());
+    }
+
+    #[test]
+    fn test_should_persist_identically_regardless_of_entity_parsers_order() {
+        // Given
+        let build_parser = |entity_identifiers: &[&str]| -> GazetteerParser<String> {
+            let entity_parsers = entity_identifiers
+                .iter()
+                .map(|entity_identifier| GazetteerEntityParserBuilder {
+                    entity_identifier: entity_identifier.to_string(),
+                    entity_parser: match *entity_identifier {
+                        "music_artist" => get_music_artist_parser_builder(),
+                        _ => get_music_track_parser_builder(),
+                    },
+                })
+                .collect();
+            GazetteerParserBuilder { entity_parsers }.build().unwrap()
+        };
+        let gazetteer_parser = build_parser(&["music_artist", "music_track"]);
+        let reordered_gazetteer_parser = build_parser(&["music_track", "music_artist"]);
+        let temp_dir = tempdir().unwrap();
+        let parser_dir = temp_dir.path().join("gazetteer_parser");
+        let reordered_parser_dir = temp_dir.path().join("reordered_gazetteer_parser");
+
+        // When
+        gazetteer_parser.persist(&parser_dir).unwrap();
+        reordered_gazetteer_parser
+            .persist(&reordered_parser_dir)
+            .unwrap();
+        let fingerprint = gazetteer_parser.fingerprint().unwrap();
+        let reordered_fingerprint = reordered_gazetteer_parser.fingerprint().unwrap();
+
+        // Then
+        assert_eq!(64, fingerprint.len());
+        assert_eq!(fingerprint, reordered_fingerprint);
+        assert_eq!(
+            fs::read(parser_dir.join("metadata.json")).unwrap(),
+            fs::read(reordered_parser_dir.join("metadata.json")).unwrap()
+        );
+        assert_eq!(
+            compute_checksums(&parser_dir).unwrap(),
+            compute_checksums(&reordered_parser_dir).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_should_change_fingerprint_when_extending_entity() {
+        // Given
+        let mut gazetteer_parser = get_test_custom_gazetteer_parser();
+        let entity_values = vec![EntityValue {
+            raw_value: "my favorite band".to_string(),
+            resolved_value: "My Favorite Band".to_string(),
+        }];
+
+        // When
+        let fingerprint = gazetteer_parser.fingerprint().unwrap();
+        gazetteer_parser
+            .extend_gazetteer_entity("music_artist".to_string(), entity_values.into_iter())
+            .unwrap();
+        let extended_fingerprint = gazetteer_parser.fingerprint().unwrap();
+
+        // Then
+        assert_ne!(fingerprint, extended_fingerprint);
+    }
+
+    #[test]
+    fn test_should_return_entities_in_the_order_of_the_entity_parsers() {
+        // Given
+        let gazetteer_parser = GazetteerParserBuilder {
+            entity_parsers: vec![
+                GazetteerEntityParserBuilder {
+                    entity_identifier: "music_track".to_string(),
+                    entity_parser: get_music_track_parser_builder(),
+                },
+                GazetteerEntityParserBuilder {
+                    entity_identifier: "music_artist".to_string(),
+                    entity_parser: get_music_artist_parser_builder(),
+                },
+            ],
+        }
+        .build::<String>()
+        .unwrap();
+
+        // When
+        let entity_identifiers = gazetteer_parser
+            .extract_entities("play what s my age again by blink one eight two", None, 0)
+            .unwrap()
+            .into_iter()
+            .map(|entity_match| entity_match.entity_identifier)
+            .collect::<Vec<_>>();
+
+        // Then
+        assert_eq!(
+            vec!["music_track".to_string(), "music_artist".to_string()],
+            entity_identifiers
+        );
+    }
+
+    #[test]
+    fn test_match_score_should_ignore_punctuation() {
+        // When
+        let slash_score = MatchScore::compute("ac dc", "AC/DC");
+        let apostrophe_score = MatchScore::compute("what s my age", "What's my age again");
+
+        // Then
+        assert_eq!(1.0, slash_score.score);
+        assert_eq!(0.8, apostrophe_score.score);
+    }
+
+    #[test]
+    fn test_matched_tokens_ratio_should_count_whitespace_separated_tokens() {
+        // When
+        let partial_score = MatchScore::compute("rolling stones", "the rolling stones");
+        let stop_word_score = MatchScore::compute("the", "the rolling stones");
+        let punctuation_score = MatchScore::compute("what's my age", "What's my age again");
+
+        // Then
+        assert_eq!(2.0 / 3.0, partial_score.matched_tokens_ratio);
+        assert_eq!(1.0 / 3.0, stop_word_score.matched_tokens_ratio);
+        assert_eq!(0.75, punctuation_score.matched_tokens_ratio);
+    }
+
+    #[test]
+    fn test_should_report_matched_tokens_ratios_reaching_the_parser_threshold() {
+        // Given
+        let gazetteer_parser = get_test_custom_gazetteer_parser();
+        let parser_thresholds = vec![("music_artist", 0.6), ("music_track", 0.7)];
+        let inputs = vec![
+            "I want to listen to the stones",
+            "play stones stones by rolling stones",
+            "play harder better faster by blink one eight",
+            "what s my age again by the rolling stones!",
+        ];
+
+        for input in inputs {
+            // When
+            let matches = gazetteer_parser
+                .extract_entities_with_scores(input, None, 5, &BTreeMap::new())
+                .unwrap();
+
+            // Then
+            assert!(!matches.is_empty());
+            for entity_match in matches {
+                let threshold = parser_thresholds
+                    .iter()
+                    .find(|(entity, _)| *entity == entity_match.entity_identifier)
+                    .unwrap()
+                    .1;
+                let scores = entity_match.scores.unwrap();
+                assert!(
+                    scores.matched_tokens_ratio >= threshold,
+                    "ratio {} of '{}' is below {}",
+                    scores.matched_tokens_ratio,
+                    entity_match.value,
+                    threshold
+                );
+                for alternative in scores.alternative_matches {
+                    assert!(alternative.matched_tokens_ratio >= threshold);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_should_only_serialize_scores_when_computed() {
+        // Given
+        let gazetteer_parser = get_test_custom_gazetteer_parser();
+        let input = "I want to listen to the stones";
+
+        // When
+        let entities = gazetteer_parser.extract_entities(input, None, 5).unwrap();
+        let scored_entities = gazetteer_parser
+            .extract_entities_with_scores(input, None, 5, &BTreeMap::new())
+            .unwrap();
+        let json = serde_json::to_value(&entities).unwrap();
+        let scored_json = serde_json::to_value(&scored_entities).unwrap();
+
+        // Then
+        let expected_json = serde_json::json!([{
+            "value": "the stones",
+            "resolved_value": "The Rolling Stones",
+            "alternative_resolved_values": [],
+            "range": {"start": 20, "end": 30},
+            "entity_identifier": "music_artist",
+        }]);
+        let expected_scored_json = serde_json::json!([{
+            "value": "the stones",
+            "resolved_value": "The Rolling Stones",
+            "alternative_resolved_values": [],
+            "range": {"start": 20, "end": 30},
+            "entity_identifier": "music_artist",
+            "score": 2.0 / 3.0,
+            "matched_tokens_ratio": 2.0 / 3.0,
+            "alternative_matches": [],
+        }]);
+        assert_eq!(expected_json, json);
+        assert_eq!(expected_scored_json, scored_json);
+    }
+
     #[test]
     fn test_should_load_custom_gazetteer_parser_from_path() {
         // Given
@@ -546,4 +1912,24 @@ mod test {
         let expected_parser = get_test_builtin_gazetteer_parser();
         assert_eq!(Some(expected_parser), parser.ok());
     }
+    #[test]
+    fn test_should_load_builtin_gazetteer_parser_restricted_to_entities() {
+        // Given
+        let path = test_path().join("builtin_gazetteer_parser");
+        let entity_kinds = vec![BuiltinGazetteerEntityKind::MusicArtist];
+
+        // When
+        let parser = GazetteerParser::from_path_with_entities(path, Some(&entity_kinds));
+
+        // Then
+        let expected_parser = GazetteerParserBuilder {
+            entity_parsers: vec![GazetteerEntityParserBuilder {
+                entity_identifier: "snips/musicArtist".to_string(),
+                entity_parser: get_music_artist_parser_builder(),
+            }],
+        }
+        .build()
+        .unwrap();
+        assert_eq!(Some(expected_parser), parser.ok());
+    }
 }
@@ -0,0 +1,404 @@
+use crate::builtin_entity_parser::BuiltinEntityParser;
+use crate::context::ExtractionContext;
+use crate::errors::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use snips_nlu_ontology::{BuiltinEntity, BuiltinEntityKind};
+use snips_nlu_utils::string::{convert_to_byte_range, convert_to_char_index};
+
+const DEFAULT_STABILITY_WINDOW: usize = 3;
+
+/// Entities extracted from a growing partial transcript
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct IncrementalExtraction {
+    /// Entities which are followed by enough tokens to not be affected by upcoming text
+    pub stable_entities: Vec<BuiltinEntity>,
+    /// Entities close to the end of the text, which may still change when text is appended
+    pub tentative_entities: Vec<BuiltinEntity>,
+}
+
+/// Streaming extraction of entities on top of a `BuiltinEntityParser`, meant for the partial
+/// hypotheses of an ASR engine
+///
+/// An entity becomes stable once it is followed by at least `stability_window` tokens, as split
+/// by the tokenizer of the parser. Stable entities are kept as is, and later extractions only
+/// parse the text which follows them, so that the cost of each call does not grow with the
+/// transcript.
+///
+/// As the text preceding the tentative entities and the last `stability_window` tokens is not
+/// parsed again, an entity cannot span text which was already considered stable. An expression
+/// whose parts are separated by more than `stability_window` tokens, such as a period between two
+/// dates, may thus be extracted as several entities, whereas parsing the whole transcript at once
+/// would return a single one.
+///
+/// Relative time expressions are resolved against the reference datetime of the context, which
+/// defaults to the time at which the session was created rather than the time of each extraction.
+pub struct IncrementalParsingSession<'a> {
+    parser: &'a BuiltinEntityParser,
+    filter_entity_kinds: Option<Vec<BuiltinEntityKind>>,
+    max_alternative_resolved_values: usize,
+    context: ExtractionContext,
+    stability_window: usize,
+    text: String,
+    stable_entities: Vec<BuiltinEntity>,
+    /// Byte index of the text from which entities are extracted again
+    unstable_offset: usize,
+}
+
+impl BuiltinEntityParser {
+    pub fn incremental_session(&self) -> IncrementalParsingSession<'_> {
+        IncrementalParsingSession::new(self)
+    }
+}
+
+impl<'a> IncrementalParsingSession<'a> {
+    pub fn new(parser: &'a BuiltinEntityParser) -> Self {
+        Self {
+            parser,
+            filter_entity_kinds: None,
+            max_alternative_resolved_values: 0,
+            context: ExtractionContext::default().reference_datetime(Utc::now()),
+            stability_window: DEFAULT_STABILITY_WINDOW,
+            text: String::new(),
+            stable_entities: vec![],
+            unstable_offset: 0,
+        }
+    }
+
+    pub fn filter_entity_kinds(mut self, filter_entity_kinds: Vec<BuiltinEntityKind>) -> Self {
+        self.filter_entity_kinds = Some(filter_entity_kinds);
+        self
+    }
+
+    pub fn max_alternative_resolved_values(
+        mut self,
+        max_alternative_resolved_values: usize,
+    ) -> Self {
+        self.max_alternative_resolved_values = max_alternative_resolved_values;
+        self
+    }
+
+    /// The context is shared by all the extractions of the session, so that relative time
+    /// expressions are resolved consistently as the transcript grows
+    ///
+    /// When the context has no reference datetime, the time at which the session was created is
+    /// used.
+    pub fn context(mut self, mut context: ExtractionContext) -> Self {
+        if context.reference_datetime.is_none() {
+            context.reference_datetime = self.context.reference_datetime;
+        }
+        self.context = context;
+        self
+    }
+
+    /// Number of tokens which must follow an entity for it to be considered stable
+    pub fn stability_window(mut self, stability_window: usize) -> Self {
+        self.stability_window = stability_window;
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Appends `text` to the transcript, including any separating whitespace, and extracts
+    /// entities from the updated transcript
+    pub fn append(&mut self, text: &str) -> Result<IncrementalExtraction> {
+        self.text.push_str(text);
+        self.extract()
+    }
+
+    /// Replaces the transcript with a new hypothesis and extracts entities from it
+    ///
+    /// Stable entities are kept as long as the hypothesis does not revise the text they were
+    /// extracted from, otherwise the session starts over from the new hypothesis.
+    pub fn set_text(&mut self, text: &str) -> Result<IncrementalExtraction> {
+        if self.unstable_offset > 0 && common_prefix_len(&self.text, text) <= self.unstable_offset {
+            self.reset();
+        }
+        self.text = text.to_string();
+        self.extract()
+    }
+
+    /// Ends the session, all the entities of the transcript being considered stable
+    pub fn finish(mut self) -> Result<Vec<BuiltinEntity>> {
+        let entities = self.extract_unstable_entities()?;
+        self.add_stable_entities(entities);
+        Ok(self.stable_entities)
+    }
+
+    pub fn reset(&mut self) {
+        self.text.clear();
+        self.stable_entities.clear();
+        self.unstable_offset = 0;
+    }
+
+    fn extract(&mut self) -> Result<IncrementalExtraction> {
+        let entities = self.extract_unstable_entities()?;
+        let stability_boundary = self.stability_boundary();
+        let stability_char_boundary = convert_to_char_index(&self.text, stability_boundary);
+        let (stable_entities, tentative_entities): (Vec<_>, Vec<_>) = entities
+            .into_iter()
+            .partition(|entity| entity.range.end <= stability_char_boundary);
+        self.add_stable_entities(stable_entities);
+        // Text which is neither part of a tentative entity nor close to the end of the
+        // transcript does not need to be parsed again
+        self.unstable_offset = tentative_entities
+            .iter()
+            .map(|entity| convert_to_byte_range(&self.text, &entity.range).start)
+            .min()
+            .unwrap_or(stability_boundary)
+            .min(stability_boundary)
+            .max(self.unstable_offset);
+        Ok(IncrementalExtraction {
+            stable_entities: self.stable_entities.clone(),
+            tentative_entities,
+        })
+    }
+
+    /// Adds entities to the stable ones, keeping them sorted by position like the output of the
+    /// parser
+    fn add_stable_entities(&mut self, mut entities: Vec<BuiltinEntity>) {
+        self.stable_entities.append(&mut entities);
+        self.stable_entities.sort_by(|a, b| {
+            a.range
+                .start
+                .cmp(&b.range.start)
+                .then(a.range.end.cmp(&b.range.end))
+        });
+    }
+
+    /// Extracts the entities of the text following `unstable_offset`
+    ///
+    /// With `ConflictResolution::KeepAll`, a tentative entity may overlap stable ones, in which
+    /// case these stable entities are found again when the text is parsed from the start of the
+    /// tentative entity: they are skipped so that they are not reported twice.
+    fn extract_unstable_entities(&self) -> Result<Vec<BuiltinEntity>> {
+        let unstable_text = &self.text[self.unstable_offset..];
+        let char_offset = convert_to_char_index(&self.text, self.unstable_offset);
+        Ok(self
+            .parser
+            .extract_entities_with_context(
+                unstable_text,
+                self.filter_entity_kinds.as_ref().map(|kinds| &**kinds),
+                self.max_alternative_resolved_values,
+                &self.context,
+            )?
+            .into_iter()
+            .map(|entity| BuiltinEntity {
+                range: entity.range.start + char_offset..entity.range.end + char_offset,
+                ..entity
+            })
+            .filter(|entity| {
+                !self.stable_entities.iter().any(|stable_entity| {
+                    stable_entity.range == entity.range
+                        && stable_entity.entity_kind == entity.entity_kind
+                })
+            })
+            .collect())
+    }
+
+    /// Byte index of the start of the last `stability_window` tokens of the transcript
+    fn stability_boundary(&self) -> usize {
+        if self.stability_window == 0 {
+            return self.text.len();
+        }
+        let tokens_starts = self
+            .parser
+            .tokenize(&self.text[self.unstable_offset..])
+            .into_iter()
+            .map(|token| token.start + self.unstable_offset)
+            .collect::<Vec<_>>();
+        if tokens_starts.len() < self.stability_window {
+            return self.unstable_offset;
+        }
+        tokens_starts[tokens_starts.len() - self.stability_window]
+    }
+}
+
+fn common_prefix_len(text: &str, other_text: &str) -> usize {
+    text.char_indices()
+        .zip(other_text.chars())
+        .find(|((_, c), other_c)| c != other_c)
+        .map(|((index, _), _)| index)
+        .unwrap_or_else(|| text.len().min(other_text.len()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::builtin_entity_parser::BuiltinEntityParserLoader;
+    use crate::test_utils::test_path;
+    use crate::tokenization::Tokenizer;
+    use snips_nlu_ontology::Language;
+    use std::ops::Range;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_should_extract_stable_and_tentative_entities() {
+        // Given
+        let parser = BuiltinEntityParserLoader::new(Language::EN).load().unwrap();
+        let mut session = parser
+            .incremental_session()
+            .filter_entity_kinds(vec![BuiltinEntityKind::Number]);
+
+        // When
+        let first_extraction = session.append("I need three").unwrap();
+        let second_extraction = session.append(" apples and two").unwrap();
+        let third_extraction = session.append(" pears please").unwrap();
+        let text = session.text().to_string();
+        let final_entities = session.finish().unwrap();
+
+        // Then
+        let values = |entities: &[BuiltinEntity]| {
+            entities
+                .iter()
+                .map(|entity| entity.value.clone())
+                .collect::<Vec<_>>()
+        };
+        assert!(first_extraction.stable_entities.is_empty());
+        assert_eq!(vec!["three"], values(&first_extraction.tentative_entities));
+        assert_eq!(vec!["three"], values(&second_extraction.stable_entities));
+        assert_eq!(vec!["two"], values(&second_extraction.tentative_entities));
+        assert_eq!(vec!["three"], values(&third_extraction.stable_entities));
+        assert_eq!(vec!["two"], values(&third_extraction.tentative_entities));
+        let expected_entities = parser
+            .extract_entities(&text, Some(&[BuiltinEntityKind::Number]), 0)
+            .unwrap();
+        assert_eq!(expected_entities, final_entities);
+    }
+
+    #[test]
+    fn test_should_resolve_times_against_the_creation_of_the_session() {
+        // Given
+        let parser = BuiltinEntityParserLoader::new(Language::EN).load().unwrap();
+        let before_creation = Utc::now();
+
+        // When
+        let session = parser
+            .incremental_session()
+            .context(ExtractionContext::new().timezone(chrono_tz::Tz::Europe__Paris));
+        let after_creation = Utc::now();
+
+        // Then
+        let reference_datetime = session.context.reference_datetime.unwrap();
+        assert!(before_creation <= reference_datetime && reference_datetime <= after_creation);
+        assert_eq!(Some(chrono_tz::Tz::Europe__Paris), session.context.timezone);
+    }
+
+    #[test]
+    fn test_should_start_over_when_hypothesis_revises_stable_text() {
+        // Given
+        let parser = BuiltinEntityParserLoader::new(Language::EN).load().unwrap();
+        let mut session = parser
+            .incremental_session()
+            .filter_entity_kinds(vec![BuiltinEntityKind::Number]);
+        session.set_text("I need three apples and two").unwrap();
+
+        // When
+        let extended_extraction = session
+            .set_text("I need three apples and two pears")
+            .unwrap();
+        let revised_extraction = session
+            .set_text("I need four apples and two pears")
+            .unwrap();
+
+        // Then
+        assert_eq!(1, extended_extraction.stable_entities.len());
+        assert_eq!("three", extended_extraction.stable_entities[0].value);
+        assert_eq!(1, revised_extraction.stable_entities.len());
+        assert_eq!("four", revised_extraction.stable_entities[0].value);
+        assert_eq!(7..11, revised_extraction.stable_entities[0].range);
+        assert_eq!(1, revised_extraction.tentative_entities.len());
+        assert_eq!("two", revised_extraction.tentative_entities[0].value);
+    }
+
+    #[test]
+    fn test_should_not_duplicate_stable_entities_overlapped_by_tentative_ones() {
+        // Given
+        let parser = BuiltinEntityParserLoader::new(Language::EN)
+            .use_gazetter_parser(test_path().join("builtin_gazetteer_parser"))
+            .load()
+            .unwrap();
+        let entity_kinds = vec![BuiltinEntityKind::Number, BuiltinEntityKind::MusicArtist];
+        let mut session = parser
+            .incremental_session()
+            .filter_entity_kinds(entity_kinds.clone())
+            .stability_window(2);
+
+        // When
+        let first_extraction = session.append("listen to blink one eight two").unwrap();
+        let second_extraction = session.append(" please").unwrap();
+        let text = session.text().to_string();
+        let final_entities = session.finish().unwrap();
+
+        // Then
+        let overlaps_tentative_entity = |entity: &BuiltinEntity| {
+            first_extraction
+                .tentative_entities
+                .iter()
+                .any(|tentative| tentative.range.start < entity.range.end)
+        };
+        assert!(first_extraction
+            .stable_entities
+            .iter()
+            .any(overlaps_tentative_entity));
+        let expected_entities = parser
+            .extract_entities(&text, Some(&entity_kinds), 0)
+            .unwrap();
+        let mut stable_entities_keys = second_extraction
+            .stable_entities
+            .iter()
+            .map(|entity| (entity.range.start, entity.range.end, entity.entity_kind))
+            .collect::<Vec<_>>();
+        let nb_stable_entities = stable_entities_keys.len();
+        stable_entities_keys.dedup();
+        assert_eq!(nb_stable_entities, stable_entities_keys.len());
+        assert_eq!(expected_entities, final_entities);
+    }
+
+    #[test]
+    fn test_should_count_tokens_with_the_tokenizer_of_the_parser() {
+        // Given
+        #[derive(Debug)]
+        struct CharTokenizer;
+
+        impl Tokenizer for CharTokenizer {
+            fn tokenize(&self, sentence: &str) -> Vec<Range<usize>> {
+                sentence
+                    .char_indices()
+                    .filter(|(_, c)| !c.is_whitespace())
+                    .map(|(index, c)| index..index + c.len_utf8())
+                    .collect()
+            }
+
+            fn separator(&self, _previous_token: &str, _next_token: &str) -> &'static str {
+                ""
+            }
+        }
+
+        let parser = BuiltinEntityParserLoader::new(Language::JA)
+            .tokenizer(Arc::new(CharTokenizer))
+            .load()
+            .unwrap();
+        let mut session = parser
+            .incremental_session()
+            .filter_entity_kinds(vec![BuiltinEntityKind::Number]);
+
+        // When
+        let extraction = session.append("三つのりんごをください").unwrap();
+
+        // Then
+        assert_eq!(1, extraction.stable_entities.len());
+        assert_eq!(0, extraction.stable_entities[0].range.start);
+        assert!(extraction.tentative_entities.is_empty());
+    }
+
+    #[test]
+    fn test_common_prefix_len_should_stop_on_char_boundaries() {
+        assert_eq!(0, common_prefix_len("", "abc"));
+        assert_eq!(3, common_prefix_len("abc", "abcd"));
+        assert_eq!(1, common_prefix_len("aé", "aè"));
+        assert_eq!(3, common_prefix_len("aéb", "aéc"));
+    }
+}
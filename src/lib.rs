@@ -2,15 +2,30 @@ pub extern crate gazetteer_entity_parser;
 
 pub use builtin_entities::*;
 pub use builtin_entity_parser::*;
+pub use conflict_resolution::*;
+pub use context::*;
 pub use conversion::*;
 pub use gazetteer_parser::*;
+pub use incremental_session::*;
+pub use offsets::*;
+pub use persistence::*;
+pub use shared_parser::*;
 pub use snips_nlu_ontology::*;
+pub use tokenization::*;
 
 mod builtin_entities;
 mod builtin_entity_parser;
+mod conflict_resolution;
+mod context;
 mod conversion;
 pub mod errors;
 mod gazetteer_parser;
+mod incremental_session;
+mod normalization;
+mod offsets;
+mod persistence;
+mod shared_parser;
 #[cfg(test)]
 mod test_utils;
+mod tokenization;
 mod utils;
@@ -0,0 +1,126 @@
+use std::ops::Range;
+
+/// Lowercased version of a sentence, along with the mapping of its characters to the characters
+/// of the original sentence
+///
+/// Some characters have a lowercase form of a different length, e.g. "İ" becomes "i" followed by
+/// a combining dot, so the ranges found in the normalized sentence must be mapped back before
+/// being used to slice the original sentence.
+pub(crate) struct NormalizedSentence<'a> {
+    original: &'a str,
+    normalized: String,
+    /// Index of the original character of each normalized character, followed by the number of
+    /// characters of the original sentence
+    original_char_indices: Vec<usize>,
+    /// Byte index of each original character, followed by the length of the original sentence
+    original_byte_indices: Vec<usize>,
+}
+
+impl<'a> NormalizedSentence<'a> {
+    pub fn lowercase(original: &'a str) -> Self {
+        let mut normalized = String::with_capacity(original.len());
+        let mut original_char_indices = Vec::with_capacity(original.len() + 1);
+        let mut original_byte_indices = Vec::with_capacity(original.len() + 1);
+        for (char_index, (byte_index, c)) in original.char_indices().enumerate() {
+            original_byte_indices.push(byte_index);
+            for lowercase_c in c.to_lowercase() {
+                normalized.push(lowercase_c);
+                original_char_indices.push(char_index);
+            }
+        }
+        original_char_indices.push(original_byte_indices.len());
+        original_byte_indices.push(original.len());
+        Self {
+            original,
+            normalized,
+            original_char_indices,
+            original_byte_indices,
+        }
+    }
+
+    pub fn normalized(&self) -> &str {
+        &self.normalized
+    }
+
+    /// Converts a range of characters of the normalized sentence into the range of the original
+    /// characters it was produced from
+    pub fn original_char_range(&self, normalized_char_range: &Range<usize>) -> Range<usize> {
+        let nb_original_chars = self.original_byte_indices.len() - 1;
+        let original_char_index = |index: usize| {
+            self.original_char_indices
+                .get(index)
+                .cloned()
+                .unwrap_or(nb_original_chars)
+        };
+        let start = original_char_index(normalized_char_range.start);
+        // The end is mapped from the last character of the range, so that a character which was
+        // lowercased into several characters is entirely included
+        let end = if normalized_char_range.end > normalized_char_range.start {
+            original_char_index(normalized_char_range.end - 1) + 1
+        } else {
+            start
+        };
+        start..end.min(nb_original_chars)
+    }
+
+    /// Returns the part of the original sentence corresponding to a range of original characters
+    pub fn original_substring(&self, original_char_range: &Range<usize>) -> &'a str {
+        let start = self.original_byte_indices[original_char_range.start];
+        let end = self.original_byte_indices[original_char_range.end];
+        &self.original[start..end]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_should_map_normalized_ranges_to_original_ranges() {
+        // Given
+        let sentence = "İstanbul ÉTÉ";
+
+        // When
+        let normalized_sentence = NormalizedSentence::lowercase(sentence);
+
+        // Then
+        assert_eq!("i\u{307}stanbul été", normalized_sentence.normalized());
+        assert_eq!(13, normalized_sentence.normalized().chars().count());
+        let istanbul_range = normalized_sentence.original_char_range(&(0..9));
+        assert_eq!(0..8, istanbul_range);
+        assert_eq!(
+            "İstanbul",
+            normalized_sentence.original_substring(&istanbul_range)
+        );
+        let ete_range = normalized_sentence.original_char_range(&(10..13));
+        assert_eq!(9..12, ete_range);
+        assert_eq!("ÉTÉ", normalized_sentence.original_substring(&ete_range));
+    }
+
+    #[test]
+    fn test_should_include_entire_original_char_when_range_splits_it() {
+        // Given
+        let normalized_sentence = NormalizedSentence::lowercase("aİb");
+
+        // When
+        let range = normalized_sentence.original_char_range(&(2..4));
+
+        // Then
+        assert_eq!(1..3, range);
+        assert_eq!("İb", normalized_sentence.original_substring(&range));
+    }
+
+    #[test]
+    fn test_should_map_empty_ranges() {
+        // Given
+        let normalized_sentence = NormalizedSentence::lowercase("İ");
+
+        // When
+        let start_range = normalized_sentence.original_char_range(&(0..0));
+        let end_range = normalized_sentence.original_char_range(&(2..2));
+
+        // Then
+        assert_eq!(0..0, start_range);
+        assert_eq!(1..1, end_range);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::gazetteer_parser::{EntityIdentifier, GazetteerEntityMatch};
+use serde::{Deserialize, Serialize};
+use snips_nlu_ontology::BuiltinEntity;
+use std::ops::Range;
+
+/// Entity along with its range expressed in bytes and in UTF-16 code units
+///
+/// The `range` of the entity itself is expressed in characters. When serialized, the fields of
+/// the entity are flattened next to `byte_range` and `utf16_range`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WithOffsets<E> {
+    #[serde(flatten)]
+    pub entity: E,
+    pub byte_range: Range<usize>,
+    pub utf16_range: Range<usize>,
+}
+
+/// Entity located in a sentence by a range of characters
+pub trait CharRange {
+    fn char_range(&self) -> Range<usize>;
+}
+
+impl CharRange for BuiltinEntity {
+    fn char_range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+}
+
+impl<T> CharRange for GazetteerEntityMatch<T>
+where
+    T: EntityIdentifier,
+{
+    fn char_range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+}
+
+/// Computes the byte and UTF-16 ranges of entities extracted from `sentence`
+///
+/// The offsets are all derived from the character ranges, so that they are consistent whatever
+/// the parser which extracted the entities.
+pub fn add_offsets<E: CharRange>(sentence: &str, entities: Vec<E>) -> Vec<WithOffsets<E>> {
+    let offsets = SentenceOffsets::new(sentence);
+    entities
+        .into_iter()
+        .map(|entity| {
+            let char_range = entity.char_range();
+            WithOffsets {
+                byte_range: offsets.byte_range(&char_range),
+                utf16_range: offsets.utf16_range(&char_range),
+                entity,
+            }
+        })
+        .collect()
+}
+
+/// Byte and UTF-16 offsets of each character of a sentence, including the end of the sentence
+struct SentenceOffsets {
+    bytes: Vec<usize>,
+    utf16: Vec<usize>,
+}
+
+impl SentenceOffsets {
+    fn new(sentence: &str) -> Self {
+        let mut bytes = Vec::with_capacity(sentence.len() + 1);
+        let mut utf16 = Vec::with_capacity(sentence.len() + 1);
+        let mut utf16_offset = 0;
+        for (byte_offset, c) in sentence.char_indices() {
+            bytes.push(byte_offset);
+            utf16.push(utf16_offset);
+            utf16_offset += c.len_utf16();
+        }
+        bytes.push(sentence.len());
+        utf16.push(utf16_offset);
+        Self { bytes, utf16 }
+    }
+
+    fn byte_range(&self, char_range: &Range<usize>) -> Range<usize> {
+        Self::convert(&self.bytes, char_range)
+    }
+
+    fn utf16_range(&self, char_range: &Range<usize>) -> Range<usize> {
+        Self::convert(&self.utf16, char_range)
+    }
+
+    fn convert(offsets: &[usize], char_range: &Range<usize>) -> Range<usize> {
+        let last_offset = offsets[offsets.len() - 1];
+        let convert_index = |index: usize| offsets.get(index).cloned().unwrap_or(last_offset);
+        convert_index(char_range.start)..convert_index(char_range.end)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use snips_nlu_ontology::{BuiltinEntityKind, NumberValue, SlotValue};
+
+    #[test]
+    fn test_should_add_byte_and_utf16_offsets() {
+        // Given
+        let sentence = "😀 café à 3 heures";
+        let entity = BuiltinEntity {
+            value: "3".to_string(),
+            range: 9..10,
+            entity: SlotValue::Number(NumberValue { value: 3.0 }),
+            alternatives: vec![],
+            entity_kind: BuiltinEntityKind::Number,
+        };
+
+        // When
+        let entities = add_offsets(sentence, vec![entity.clone()]);
+
+        // Then
+        let expected_entities = vec![WithOffsets {
+            entity,
+            byte_range: 14..15,
+            utf16_range: 10..11,
+        }];
+        assert_eq!(expected_entities, entities);
+        assert_eq!("3", &sentence[14..15]);
+    }
+
+    #[test]
+    fn test_should_serialize_entity_with_flattened_offsets() {
+        // Given
+        let entity_match = WithOffsets {
+            entity: GazetteerEntityMatch {
+                value: "été".to_string(),
+                resolved_value: "Été".to_string(),
+                alternative_resolved_values: vec![],
+                range: 1..4,
+                entity_identifier: "season".to_string(),
+                scores: None,
+            },
+            byte_range: 1..6,
+            utf16_range: 1..4,
+        };
+
+        // When
+        let json = serde_json::to_value(&entity_match).unwrap();
+
+        // Then
+        let expected_json = serde_json::json!({
+            "value": "été",
+            "resolved_value": "Été",
+            "alternative_resolved_values": [],
+            "range": {"start": 1, "end": 4},
+            "entity_identifier": "season",
+            "byte_range": {"start": 1, "end": 6},
+            "utf16_range": {"start": 1, "end": 4},
+        });
+        assert_eq!(expected_json, json);
+    }
+}
@@ -0,0 +1,1188 @@
+use crate::errors::{ErrorKind, Result};
+use failure::ResultExt;
+use itertools::Itertools;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Version of the layout of persisted parsers, to be incremented whenever it changes
+///
+/// Version `0` corresponds to the layout used before versions were recorded in the metadata.
+pub const PERSISTENCE_FORMAT_VERSION: u32 = 1;
+
+/// Version of the crate, recorded in the metadata of persisted parsers
+pub const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Versions of the dependencies whose data ends up in persisted parsers
+///
+/// These dependencies are fetched from git tags, whose versions Cargo does not expose to the
+/// crate, so they are checked against the `Cargo.toml` by a unit test.
+pub const RUSTLING_ONTOLOGY_VERSION: &str = "0.19.3";
+pub const SNIPS_NLU_ONTOLOGY_VERSION: &str = "0.67.1";
+pub const GAZETTEER_ENTITY_PARSER_VERSION: &str = "0.8.0";
+
+/// Versions recorded in the metadata of persisted parsers
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PersistenceVersions {
+    #[serde(default)]
+    pub format_version: u32,
+    pub crate_version: Option<String>,
+    pub rustling_ontology_version: Option<String>,
+    pub snips_nlu_ontology_version: Option<String>,
+    pub gazetteer_entity_parser_version: Option<String>,
+}
+
+impl PersistenceVersions {
+    pub fn current() -> Self {
+        Self {
+            format_version: PERSISTENCE_FORMAT_VERSION,
+            crate_version: Some(CRATE_VERSION.to_string()),
+            rustling_ontology_version: Some(RUSTLING_ONTOLOGY_VERSION.to_string()),
+            snips_nlu_ontology_version: Some(SNIPS_NLU_ONTOLOGY_VERSION.to_string()),
+            gazetteer_entity_parser_version: Some(GAZETTEER_ENTITY_PARSER_VERSION.to_string()),
+        }
+    }
+
+    /// Checks that a parser persisted with these versions can be loaded by the current crate
+    ///
+    /// The recorded crate version and dependency versions must be semver-compatible with the
+    /// ones of the current crate, i.e. share the same major version, and the same minor version
+    /// for `0.x` versions.
+    ///
+    /// Parsers persisted with the legacy format `0`, which does not record any version, are
+    /// accepted and loaded as is: there is no migration, as format `1` only added the versions
+    /// and checksums to the metadata, which are optional.
+    pub fn check_compatibility(&self) -> Result<()> {
+        if self.format_version > PERSISTENCE_FORMAT_VERSION {
+            return Err(ErrorKind::IncompatibleVersion(format!(
+                "persistence format version {} is not supported, the maximum supported version \
+                 is {}",
+                self.format_version, PERSISTENCE_FORMAT_VERSION
+            ))
+            .into());
+        }
+        let dependencies = [
+            ("snips-nlu-parsers", &self.crate_version, CRATE_VERSION),
+            (
+                "rustling-ontology",
+                &self.rustling_ontology_version,
+                RUSTLING_ONTOLOGY_VERSION,
+            ),
+            (
+                "snips-nlu-ontology",
+                &self.snips_nlu_ontology_version,
+                SNIPS_NLU_ONTOLOGY_VERSION,
+            ),
+            (
+                "gazetteer-entity-parser",
+                &self.gazetteer_entity_parser_version,
+                GAZETTEER_ENTITY_PARSER_VERSION,
+            ),
+        ];
+        for (dependency, persisted_version, current_version) in dependencies.iter() {
+            if let Some(persisted_version) = persisted_version {
+                if !are_compatible(persisted_version, current_version)? {
+                    return Err(ErrorKind::IncompatibleVersion(format!(
+                        "parser was persisted with {} {} which is not compatible with {}",
+                        dependency, persisted_version, current_version
+                    ))
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Behavior of `persist` when the target directory already exists
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PersistMode {
+    /// Fail if the target directory already exists
+    CreateNew,
+    /// Replace the existing target directory
+    Overwrite,
+}
+
+impl Default for PersistMode {
+    fn default() -> Self {
+        PersistMode::CreateNew
+    }
+}
+
+/// Policy applied to the files of a persisted parser when loading it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadingMode {
+    /// The paths referenced in the metadata are followed as is
+    Trusted,
+    /// The paths referenced in the metadata must be confined to the parser directory, symbolic
+    /// links are rejected and the size of the parser is capped by the provided limits, which is
+    /// meant for parsers coming from untrusted sources
+    Untrusted(LoadingLimits),
+}
+
+impl Default for LoadingMode {
+    fn default() -> Self {
+        LoadingMode::Trusted
+    }
+}
+
+/// Limits enforced when loading a parser in `LoadingMode::Untrusted`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct LoadingLimits {
+    /// Maximum size, in bytes, of each file of the parser
+    pub max_file_size: u64,
+    /// Maximum total size, in bytes, of the files of the parser
+    pub max_total_size: u64,
+    /// Maximum number of files and directories of the parser
+    pub max_entries: usize,
+    /// Maximum number of entity parsers in a gazetteer parser
+    pub max_entity_parsers: usize,
+}
+
+impl Default for LoadingLimits {
+    fn default() -> Self {
+        LoadingLimits {
+            max_file_size: 256 * 1024 * 1024,
+            max_total_size: 1024 * 1024 * 1024,
+            max_entries: 100_000,
+            max_entity_parsers: 1000,
+        }
+    }
+}
+
+impl LoadingLimits {
+    /// Accounts for an entry of `size` bytes, failing once the limits are exceeded
+    fn check_entry(&self, path: &Path, size: u64, usage: &mut LoadingUsage) -> Result<()> {
+        if size > self.max_file_size {
+            return Err(ErrorKind::UnsafeLayout(format!(
+                "file {:?} is {} bytes long, the maximum allowed is {}",
+                path, size, self.max_file_size
+            ))
+            .into());
+        }
+        usage.nb_entries += 1;
+        usage.total_size += size;
+        if usage.nb_entries > self.max_entries {
+            return Err(ErrorKind::UnsafeLayout(format!(
+                "more than {} files and directories found",
+                self.max_entries
+            ))
+            .into());
+        }
+        if usage.total_size > self.max_total_size {
+            return Err(ErrorKind::UnsafeLayout(format!(
+                "files are more than {} bytes long in total",
+                self.max_total_size
+            ))
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Number of entries and total size of the files found so far while checking a parser
+#[derive(Default)]
+struct LoadingUsage {
+    nb_entries: usize,
+    total_size: u64,
+}
+
+impl LoadingMode {
+    /// Checks the content of the parser directory located at `path` before it gets loaded
+    pub(crate) fn check_parser_directory(&self, path: &Path) -> Result<()> {
+        match self {
+            LoadingMode::Trusted => Ok(()),
+            LoadingMode::Untrusted(limits) => {
+                let is_symlink = fs::symlink_metadata(path)
+                    .with_context(|_| {
+                        ErrorKind::Persistence(format!("Cannot read parser directory {:?}", path))
+                    })?
+                    .file_type()
+                    .is_symlink();
+                if is_symlink {
+                    return Err(ErrorKind::UnsafeLayout(format!(
+                        "parser directory {:?} is a symbolic link",
+                        path
+                    ))
+                    .into());
+                }
+                check_directory_content(path, limits, &mut LoadingUsage::default())
+            }
+        }
+    }
+
+    /// Checks the number of entity parsers referenced by a gazetteer parser
+    pub(crate) fn check_nb_entity_parsers(&self, nb_entity_parsers: usize) -> Result<()> {
+        match self {
+            LoadingMode::Untrusted(limits) if nb_entity_parsers > limits.max_entity_parsers => {
+                Err(ErrorKind::UnsafeLayout(format!(
+                    "{} entity parsers found, the maximum allowed is {}",
+                    nb_entity_parsers, limits.max_entity_parsers
+                ))
+                .into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Resolves a path referenced in the metadata of the parser located at `root`
+    pub(crate) fn resolve_path(&self, root: &Path, relative_path: &str) -> Result<PathBuf> {
+        match self {
+            LoadingMode::Trusted => Ok(root.join(relative_path)),
+            LoadingMode::Untrusted(_) => confine_path(root, relative_path),
+        }
+    }
+}
+
+/// Joins `relative_path` onto `root`, failing if it may point outside of `root`
+pub(crate) fn confine_path(root: &Path, relative_path: &str) -> Result<PathBuf> {
+    let path = Path::new(relative_path);
+    if !is_confined(path) {
+        return Err(ErrorKind::UnsafeLayout(format!(
+            "path '{}' referenced in the metadata is not confined to the parser directory",
+            relative_path
+        ))
+        .into());
+    }
+    Ok(root.join(path))
+}
+
+/// Whether `path` is a non-empty relative path which cannot escape the directory it is joined to
+fn is_confined(path: &Path) -> bool {
+    path.components().count() > 0
+        && path.components().all(|component| match component {
+            Component::Normal(_) => true,
+            _ => false,
+        })
+}
+
+fn check_directory_content(
+    dir: &Path,
+    limits: &LoadingLimits,
+    usage: &mut LoadingUsage,
+) -> Result<()> {
+    let entries = fs::read_dir(dir).with_context(|_| {
+        ErrorKind::Persistence(format!("Cannot read parser directory {:?}", dir))
+    })?;
+    for entry in entries {
+        let entry = entry.with_context(|_| {
+            ErrorKind::Persistence(format!("Cannot read parser directory {:?}", dir))
+        })?;
+        let path = entry.path();
+        let metadata = fs::symlink_metadata(&path)
+            .with_context(|_| ErrorKind::Persistence(format!("Cannot read file {:?}", path)))?;
+        let file_type = metadata.file_type();
+        if file_type.is_symlink() {
+            return Err(
+                ErrorKind::UnsafeLayout(format!("symbolic link found at {:?}", path)).into(),
+            );
+        } else if file_type.is_dir() {
+            limits.check_entry(&path, 0, usage)?;
+            check_directory_content(&path, limits, usage)?;
+        } else {
+            limits.check_entry(&path, metadata.len(), usage)?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs `write` on a staging directory located next to `path`, and then moves the staging
+/// directory to `path`, so that a failure while writing never leaves a partially written parser
+/// at `path`
+///
+/// When overwriting, the previous directory and the staging directory are swapped atomically
+/// with `renameat2(RENAME_EXCHANGE)` on Linux. Elsewhere, or when the file system does not
+/// support it, the previous directory is moved aside into the staging directory before the
+/// staging directory takes its place, and restored if this last step fails. If the process is
+/// interrupted between these two moves, `path` is missing and the previous parser is left in the
+/// staging directory, from which it is restored by the next overwrite of the same `path`.
+///
+/// Overwrites of the same `path` are serialized by a lock on a `.{name}.lock` file created next to
+/// `path` and left in place, which also prevents the recovery of an interrupted overwrite from
+/// taking the previous parser of an overwrite in progress. The lock is only taken on Unix.
+pub(crate) fn persist_with_staging<P, F>(path: P, mode: PersistMode, write: F) -> Result<()>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&Path) -> Result<()>,
+{
+    let path = path.as_ref();
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| ErrorKind::Persistence(format!("Invalid persistence path: {:?}", path)))?;
+    let staging_prefix = format!(".{}.", file_name.to_string_lossy());
+    let _lock_file = match mode {
+        PersistMode::CreateNew => {
+            if path.exists() {
+                return Err(ErrorKind::Persistence(format!(
+                    "Cannot persist parser at path {:?} as it already exists",
+                    path
+                ))
+                .into());
+            }
+            None
+        }
+        PersistMode::Overwrite => {
+            let lock_file = lock_persistence_path(path, &staging_prefix)?;
+            recover_interrupted_overwrite(path, &staging_prefix)?;
+            Some(lock_file)
+        }
+    };
+    let parent_dir = get_parent_dir(path);
+    let staging_dir = tempfile::Builder::new()
+        .prefix(&staging_prefix)
+        .tempdir_in(parent_dir)
+        .with_context(|_| {
+            ErrorKind::Persistence(format!(
+                "Cannot create staging directory in {:?}",
+                parent_dir
+            ))
+        })?;
+    let staging_path = staging_dir.path().join("parser");
+    write(&staging_path)?;
+
+    if !path.exists() {
+        fs::rename(&staging_path, path).with_context(|_| {
+            ErrorKind::Persistence(format!("Cannot move persisted parser to path {:?}", path))
+        })?;
+        return Ok(());
+    }
+    if exchange_directories(&staging_path, path).is_ok() {
+        // The previous parser is now in the staging directory, which is removed when dropped
+        return Ok(());
+    }
+    let previous_path = staging_dir.path().join("previous");
+    fs::rename(path, &previous_path).with_context(|_| {
+        ErrorKind::Persistence(format!("Cannot move existing parser at path {:?}", path))
+    })?;
+    let move_result = fs::rename(&staging_path, path);
+    if move_result.is_err() {
+        let _ = fs::rename(&previous_path, path);
+    }
+    move_result.with_context(|_| {
+        ErrorKind::Persistence(format!("Cannot move persisted parser to path {:?}", path))
+    })?;
+    Ok(())
+}
+
+/// Opens the lock file of `path` and waits until no other overwrite of `path` holds its lock,
+/// which is released when the returned file is closed
+fn lock_persistence_path(path: &Path, staging_prefix: &str) -> Result<fs::File> {
+    let lock_path = get_parent_dir(path).join(format!("{}lock", staging_prefix));
+    let lock_error = || {
+        ErrorKind::Persistence(format!(
+            "Cannot lock path {:?} with lock file {:?}",
+            path, lock_path
+        ))
+    };
+    let lock_file = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|_| lock_error())?;
+    lock_file_exclusively(&lock_file).with_context(|_| lock_error())?;
+    Ok(lock_file)
+}
+
+#[cfg(unix)]
+fn lock_file_exclusively(file: &fs::File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+fn lock_file_exclusively(_file: &fs::File) -> io::Result<()> {
+    Ok(())
+}
+
+/// Atomically swaps the directories at `path` and `other_path`
+#[cfg(target_os = "linux")]
+fn exchange_directories(path: &Path, other_path: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    /// Flag of `renameat2`, defined in `linux/fs.h`
+    const RENAME_EXCHANGE: libc::c_uint = 1 << 1;
+    let to_c_path = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))
+    };
+    let c_path = to_c_path(path)?;
+    let other_c_path = to_c_path(other_path)?;
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            libc::AT_FDCWD,
+            c_path.as_ptr(),
+            libc::AT_FDCWD,
+            other_c_path.as_ptr(),
+            RENAME_EXCHANGE,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn exchange_directories(_path: &Path, _other_path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "Atomic exchange of directories is not supported on this platform",
+    ))
+}
+
+/// Moves back the previous parser left in a staging directory by an overwrite which was
+/// interrupted before the new parser took its place at `path`
+///
+/// This must only be called while holding the lock of `path`, so that the staging directories
+/// found belong to interrupted overwrites rather than to an overwrite in progress.
+fn recover_interrupted_overwrite(path: &Path, staging_prefix: &str) -> Result<()> {
+    if path.exists() {
+        return Ok(());
+    }
+    let entries = match fs::read_dir(get_parent_dir(path)) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(()),
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let is_staging_dir = entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(staging_prefix);
+        let previous_path = entry.path().join("previous");
+        if is_staging_dir && previous_path.is_dir() {
+            fs::rename(&previous_path, path).with_context(|_| {
+                ErrorKind::Persistence(format!(
+                    "Cannot restore previous parser from {:?} to path {:?}",
+                    previous_path, path
+                ))
+            })?;
+            let _ = fs::remove_dir_all(entry.path());
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// Writes the parser directory produced by `write` in a temporary location, and packs it into a
+/// single tar archive at `path`
+pub(crate) fn persist_archive<P, F>(path: P, write: F) -> Result<()>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&Path) -> Result<()>,
+{
+    let path = path.as_ref();
+    let archive_file =
+        tempfile::NamedTempFile::new_in(get_parent_dir(path)).with_context(|_| {
+            ErrorKind::Persistence(format!("Cannot create archive file for path {:?}", path))
+        })?;
+    write_archive(archive_file.as_file(), write)?;
+    archive_file.persist_noclobber(path).with_context(|_| {
+        ErrorKind::Persistence(format!("Cannot persist parser archive at path {:?}", path))
+    })?;
+    Ok(())
+}
+
+/// Writes the parser directory produced by `write` in a temporary location, and packs it as a
+/// tar archive into `writer`
+///
+/// Files are archived in a sorted order and without any timestamp or ownership information, so
+/// that identical parser directories always result in identical archives.
+pub(crate) fn write_archive<W, F>(writer: W, write: F) -> Result<()>
+where
+    W: Write,
+    F: FnOnce(&Path) -> Result<()>,
+{
+    let staging_dir = tempfile::tempdir()
+        .with_context(|_| ErrorKind::Persistence("Cannot create staging directory".to_string()))?;
+    let staging_path = staging_dir.path().join("parser");
+    write(&staging_path)?;
+
+    let mut file_paths = vec![];
+    collect_file_paths(&staging_path, Path::new(""), &mut file_paths)?;
+    file_paths.sort();
+    let mut archive_builder = tar::Builder::new(writer);
+    archive_builder.mode(tar::HeaderMode::Deterministic);
+    file_paths
+        .iter()
+        .try_for_each(|relative_path| {
+            archive_builder.append_path_with_name(staging_path.join(relative_path), relative_path)
+        })
+        .and_then(|_| archive_builder.finish())
+        .with_context(|_| ErrorKind::Persistence("Cannot write parser archive".to_string()))?;
+    Ok(())
+}
+
+/// Computes the SHA-256 fingerprint of the archive containing the parser directory produced by
+/// `write`
+pub(crate) fn compute_fingerprint<F>(write: F) -> Result<String>
+where
+    F: FnOnce(&Path) -> Result<()>,
+{
+    let mut hasher = Sha256::new();
+    write_archive(&mut hasher, write)?;
+    Ok(format!("{:x}", hasher.result()))
+}
+
+/// Writes the metadata of a parser in the `metadata.json` file of the parser directory `path`
+///
+/// Keys are sorted, so that the content of the file only depends on the metadata values.
+pub(crate) fn write_metadata<T: Serialize>(path: &Path, metadata: &T) -> Result<()> {
+    let metadata_path = path.join("metadata.json");
+    let metadata = serde_json::to_value(metadata)
+        .with_context(|_| ErrorKind::Persistence("Cannot serialize parser metadata".to_string()))?;
+    let metadata_file = fs::File::create(&metadata_path).with_context(|_| {
+        ErrorKind::Persistence(format!(
+            "Cannot create metadata file at path: {:?}",
+            metadata_path
+        ))
+    })?;
+    serde_json::to_writer_pretty(metadata_file, &metadata)
+        .with_context(|_| ErrorKind::Persistence("Cannot serialize parser metadata".to_string()))?;
+    Ok(())
+}
+
+/// Unpacks the tar archive at `path` in a temporary directory and runs `load` on it
+pub(crate) fn load_archive<P, T, F>(path: P, loading_mode: &LoadingMode, load: F) -> Result<T>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&Path) -> Result<T>,
+{
+    let path = path.as_ref();
+    let archive_file = fs::File::open(path).with_context(|_| {
+        ErrorKind::Persistence(format!("Cannot open parser archive at path {:?}", path))
+    })?;
+    read_archive(archive_file, loading_mode, load)
+}
+
+/// Unpacks the tar archive read from `reader` in a temporary directory and runs `load` on it
+///
+/// In `LoadingMode::Untrusted`, each entry of the archive is checked before being written to
+/// disk: links and special files are rejected, paths must be confined to the parser directory and
+/// file sizes are capped by the loading limits.
+pub(crate) fn read_archive<R, T, F>(reader: R, loading_mode: &LoadingMode, load: F) -> Result<T>
+where
+    R: Read,
+    F: FnOnce(&Path) -> Result<T>,
+{
+    let unpacking_dir = tempfile::tempdir().with_context(|_| {
+        ErrorKind::Persistence("Cannot create directory to unpack parser archive".to_string())
+    })?;
+    let unpacking_path = unpacking_dir.path().join("parser");
+    let mut archive = tar::Archive::new(reader);
+    match loading_mode {
+        LoadingMode::Trusted => archive
+            .unpack(&unpacking_path)
+            .with_context(|_| ErrorKind::Persistence("Cannot unpack parser archive".to_string()))?,
+        LoadingMode::Untrusted(limits) => {
+            unpack_untrusted_archive(&mut archive, &unpacking_path, limits)?
+        }
+    }
+    load(&unpacking_path)
+}
+
+fn unpack_untrusted_archive<R: Read>(
+    archive: &mut tar::Archive<R>,
+    path: &Path,
+    limits: &LoadingLimits,
+) -> Result<()> {
+    let unpacking_error = || ErrorKind::Persistence("Cannot unpack parser archive".to_string());
+    fs::create_dir(path).with_context(|_| unpacking_error())?;
+    let mut usage = LoadingUsage::default();
+    for entry in archive.entries().with_context(|_| unpacking_error())? {
+        let mut entry = entry.with_context(|_| unpacking_error())?;
+        let entry_path = entry
+            .path()
+            .with_context(|_| unpacking_error())?
+            .into_owned();
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            return Err(ErrorKind::UnsafeLayout(format!(
+                "link found at {:?} in parser archive",
+                entry_path
+            ))
+            .into());
+        }
+        if !entry_type.is_file() && !entry_type.is_dir() {
+            return Err(ErrorKind::UnsafeLayout(format!(
+                "unsupported entry type found at {:?} in parser archive",
+                entry_path
+            ))
+            .into());
+        }
+        if !is_confined(&entry_path) {
+            return Err(ErrorKind::UnsafeLayout(format!(
+                "path {:?} in parser archive is not confined to the parser directory",
+                entry_path
+            ))
+            .into());
+        }
+        let entry_size = entry.header().size().with_context(|_| unpacking_error())?;
+        limits.check_entry(&entry_path, entry_size, &mut usage)?;
+        entry.unpack_in(path).with_context(|_| unpacking_error())?;
+    }
+    Ok(())
+}
+
+/// SHA-256 checksums of the files of a persisted parser, indexed by their path relative to the
+/// parser directory
+pub type Checksums = BTreeMap<String, String>;
+
+/// Computes the checksums of all the files contained in the parser directory `path`, except its
+/// root `metadata.json` file, in which they are meant to be stored
+pub(crate) fn compute_checksums<P: AsRef<Path>>(path: P) -> Result<Checksums> {
+    let path = path.as_ref();
+    let mut file_paths = vec![];
+    collect_file_paths(path, Path::new(""), &mut file_paths)?;
+    file_paths
+        .into_iter()
+        .filter(|relative_path| relative_path != Path::new("metadata.json"))
+        .map(|relative_path| {
+            let checksum = compute_file_checksum(&path.join(&relative_path))?;
+            let relative_path = relative_path
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .join("/");
+            Ok((relative_path, checksum))
+        })
+        .collect()
+}
+
+/// Checks that the files contained in the parser directory `path` match the expected checksums
+pub(crate) fn verify_checksums<P: AsRef<Path>>(path: P, expected: &Checksums) -> Result<()> {
+    let path = path.as_ref();
+    let actual = compute_checksums(path)?;
+    for (relative_path, expected_checksum) in expected {
+        match actual.get(relative_path) {
+            None => {
+                return Err(ErrorKind::IntegrityCheck(format!(
+                    "file '{}' is missing in {:?}",
+                    relative_path, path
+                ))
+                .into())
+            }
+            Some(checksum) if checksum != expected_checksum => {
+                return Err(ErrorKind::IntegrityCheck(format!(
+                    "checksum of file '{}' in {:?} does not match",
+                    relative_path, path
+                ))
+                .into())
+            }
+            Some(_) => (),
+        }
+    }
+    if let Some(relative_path) = actual.keys().find(|key| !expected.contains_key(*key)) {
+        return Err(ErrorKind::IntegrityCheck(format!(
+            "unexpected file '{}' in {:?}",
+            relative_path, path
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Copies the content of the directory `from` into a new directory `to`
+pub(crate) fn copy_directory(from: &Path, to: &Path) -> Result<()> {
+    let mut file_paths = vec![];
+    collect_file_paths(from, Path::new(""), &mut file_paths)?;
+    for relative_path in file_paths {
+        let target_path = to.join(&relative_path);
+        fs::create_dir_all(get_parent_dir(&target_path)).with_context(|_| {
+            ErrorKind::Persistence(format!(
+                "Cannot create directory for file {:?}",
+                target_path
+            ))
+        })?;
+        fs::copy(from.join(&relative_path), &target_path).with_context(|_| {
+            ErrorKind::Persistence(format!("Cannot copy file to {:?}", target_path))
+        })?;
+    }
+    Ok(())
+}
+
+fn collect_file_paths(
+    root: &Path,
+    relative_dir: &Path,
+    file_paths: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let dir = root.join(relative_dir);
+    let entries = fs::read_dir(&dir).with_context(|_| {
+        ErrorKind::Persistence(format!("Cannot read parser directory {:?}", dir))
+    })?;
+    for entry in entries {
+        let entry = entry.with_context(|_| {
+            ErrorKind::Persistence(format!("Cannot read parser directory {:?}", dir))
+        })?;
+        let relative_path = relative_dir.join(entry.file_name());
+        if entry.path().is_dir() {
+            collect_file_paths(root, &relative_path, file_paths)?;
+        } else {
+            file_paths.push(relative_path);
+        }
+    }
+    Ok(())
+}
+
+fn compute_file_checksum(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)
+        .with_context(|_| ErrorKind::Persistence(format!("Cannot open file {:?}", path)))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .with_context(|_| ErrorKind::Persistence(format!("Cannot read file {:?}", path)))?;
+    Ok(format!("{:x}", hasher.result()))
+}
+
+fn get_parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent_dir) if !parent_dir.as_os_str().is_empty() => parent_dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Two versions are compatible when they share the same major version, and the same minor
+/// version for `0.x` versions
+fn are_compatible(version: &str, other_version: &str) -> Result<bool> {
+    let parse_version = |version: &str| {
+        Version::parse(version).with_context(|_| {
+            ErrorKind::IncompatibleVersion(format!("invalid version '{}'", version))
+        })
+    };
+    let version = parse_version(version)?;
+    let other_version = parse_version(other_version)?;
+    Ok(version.major == other_version.major
+        && (version.major > 0 || version.minor == other_version.minor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_persist_with_staging() {
+        // Given
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("parser");
+        let write_file = |content: &'static str| {
+            move |staging_path: &Path| -> Result<()> {
+                fs::create_dir(staging_path).unwrap();
+                fs::write(staging_path.join("content"), content).unwrap();
+                Ok(())
+            }
+        };
+        let failing_write = |staging_path: &Path| -> Result<()> {
+            fs::create_dir(staging_path).unwrap();
+            Err(ErrorKind::Persistence("crash".to_string()).into())
+        };
+
+        // When
+        persist_with_staging(&path, PersistMode::CreateNew, write_file("first")).unwrap();
+        let create_new_result =
+            persist_with_staging(&path, PersistMode::CreateNew, write_file("second"));
+        let failing_result = persist_with_staging(&path, PersistMode::Overwrite, failing_write);
+        persist_with_staging(&path, PersistMode::Overwrite, write_file("third")).unwrap();
+
+        // Then
+        assert!(create_new_result.is_err());
+        assert!(failing_result.is_err());
+        assert_eq!("third", fs::read_to_string(path.join("content")).unwrap());
+        assert_eq!(
+            vec![".parser.lock".to_string(), "parser".to_string()],
+            list_dir(temp_dir.path())
+        );
+    }
+
+    fn list_dir(path: &Path) -> Vec<String> {
+        fs::read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .sorted()
+            .collect()
+    }
+
+    #[test]
+    fn test_should_restore_previous_parser_after_interrupted_overwrite() {
+        // Given
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("parser");
+        let write_file = |content: &'static str| {
+            move |staging_path: &Path| -> Result<()> {
+                fs::create_dir(staging_path).unwrap();
+                fs::write(staging_path.join("content"), content).unwrap();
+                Ok(())
+            }
+        };
+        persist_with_staging(&path, PersistMode::CreateNew, write_file("first")).unwrap();
+        // Simulates an overwrite interrupted after the previous parser was moved aside
+        let leftover_staging_dir = temp_dir.path().join(".parser.interrupted");
+        fs::create_dir(&leftover_staging_dir).unwrap();
+        fs::rename(&path, leftover_staging_dir.join("previous")).unwrap();
+
+        // When
+        let create_new_result = persist_with_staging(&path, PersistMode::CreateNew, |_| {
+            Err(ErrorKind::Persistence("crash".to_string()).into())
+        });
+        let is_left_by_create_new = leftover_staging_dir.join("previous").is_dir();
+        persist_with_staging(&path, PersistMode::Overwrite, write_file("third")).unwrap();
+
+        // Then
+        assert!(create_new_result.is_err());
+        assert!(is_left_by_create_new);
+        assert_eq!("third", fs::read_to_string(path.join("content")).unwrap());
+        assert!(!leftover_staging_dir.exists());
+        assert_eq!(
+            vec![".parser.lock".to_string(), "parser".to_string()],
+            list_dir(temp_dir.path())
+        );
+    }
+
+    #[test]
+    fn test_should_restore_previous_parser_when_overwrite_fails_after_interruption() {
+        // Given
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("parser");
+        persist_with_staging(&path, PersistMode::CreateNew, |staging_path| {
+            fs::create_dir(staging_path).unwrap();
+            fs::write(staging_path.join("content"), "first").unwrap();
+            Ok(())
+        })
+        .unwrap();
+        // Simulates an overwrite interrupted after the previous parser was moved aside
+        let leftover_staging_dir = temp_dir.path().join(".parser.interrupted");
+        fs::create_dir(&leftover_staging_dir).unwrap();
+        fs::rename(&path, leftover_staging_dir.join("previous")).unwrap();
+
+        // When
+        let failing_result = persist_with_staging(&path, PersistMode::Overwrite, |_| {
+            Err(ErrorKind::Persistence("crash".to_string()).into())
+        });
+
+        // Then
+        assert!(failing_result.is_err());
+        assert_eq!("first", fs::read_to_string(path.join("content")).unwrap());
+        assert!(!leftover_staging_dir.exists());
+    }
+
+    #[test]
+    fn test_should_reject_links_in_untrusted_archives() {
+        // Given
+        let archive_with_link = |entry_type: tar::EntryType| {
+            let mut archive_builder = tar::Builder::new(vec![]);
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(entry_type);
+            header.set_size(0);
+            header.set_mode(0o644);
+            header.set_link_name("/etc/passwd").unwrap();
+            archive_builder
+                .append_data(&mut header, "metadata.json", io::empty())
+                .unwrap();
+            archive_builder.into_inner().unwrap()
+        };
+        let loading_mode = LoadingMode::Untrusted(LoadingLimits::default());
+
+        // When
+        let symlink_archive = archive_with_link(tar::EntryType::Symlink);
+        let symlink_result = read_archive(&*symlink_archive, &loading_mode, |_| Ok(()));
+        let hard_link_archive = archive_with_link(tar::EntryType::Link);
+        let hard_link_result = read_archive(&*hard_link_archive, &loading_mode, |_| Ok(()));
+
+        // Then
+        for result in vec![symlink_result, hard_link_result] {
+            match result.unwrap_err().kind() {
+                ErrorKind::UnsafeLayout(_) => (),
+                kind => panic!("Unexpected error kind: {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn test_should_limit_file_sizes_in_untrusted_archives() {
+        // Given
+        let mut archive_builder = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(10);
+        header.set_mode(0o644);
+        archive_builder
+            .append_data(&mut header, "parser_1/parser", &b"0123456789"[..])
+            .unwrap();
+        archive_builder
+            .append_data(&mut header, "parser_1/copy", &b"0123456789"[..])
+            .unwrap();
+        let archive = archive_builder.into_inner().unwrap();
+        let read_content = |path: &Path| -> Result<String> {
+            Ok(fs::read_to_string(path.join("parser_1").join("parser")).unwrap())
+        };
+        let strict_loading_mode = LoadingMode::Untrusted(LoadingLimits {
+            max_file_size: 5,
+            ..LoadingLimits::default()
+        });
+        let small_total_loading_mode = LoadingMode::Untrusted(LoadingLimits {
+            max_total_size: 15,
+            ..LoadingLimits::default()
+        });
+        let few_entries_loading_mode = LoadingMode::Untrusted(LoadingLimits {
+            max_entries: 1,
+            ..LoadingLimits::default()
+        });
+        let default_loading_mode = LoadingMode::Untrusted(LoadingLimits::default());
+
+        // When
+        let strict_result = read_archive(&*archive, &strict_loading_mode, read_content);
+        let small_total_result = read_archive(&*archive, &small_total_loading_mode, read_content);
+        let few_entries_result = read_archive(&*archive, &few_entries_loading_mode, read_content);
+        let default_result = read_archive(&*archive, &default_loading_mode, read_content);
+
+        // Then
+        match strict_result.unwrap_err().kind() {
+            ErrorKind::UnsafeLayout(_) => (),
+            kind => panic!("Unexpected error kind: {:?}", kind),
+        }
+        for result in vec![small_total_result, few_entries_result] {
+            match result.unwrap_err().kind() {
+                ErrorKind::UnsafeLayout(_) => (),
+                kind => panic!("Unexpected error kind: {:?}", kind),
+            }
+        }
+        assert_eq!("0123456789", default_result.unwrap());
+    }
+
+    #[test]
+    fn test_should_write_deterministic_archives() {
+        // Given
+        let write_files = |file_names: &'static [&'static str]| {
+            move |staging_path: &Path| -> Result<()> {
+                fs::create_dir_all(staging_path.join("parser_1")).unwrap();
+                for file_name in file_names {
+                    fs::write(staging_path.join("parser_1").join(file_name), *file_name).unwrap();
+                }
+                Ok(())
+            }
+        };
+
+        // When
+        let mut archive = vec![];
+        write_archive(&mut archive, write_files(&["a", "b"])).unwrap();
+        let mut other_archive = vec![];
+        write_archive(&mut other_archive, write_files(&["b", "a"])).unwrap();
+        let fingerprint = compute_fingerprint(write_files(&["a", "b"])).unwrap();
+        let other_fingerprint = compute_fingerprint(write_files(&["a", "c"])).unwrap();
+
+        // Then
+        assert_eq!(archive, other_archive);
+        assert_ne!(fingerprint, other_fingerprint);
+    }
+
+    #[test]
+    fn test_should_verify_checksums() {
+        // Given
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path();
+        fs::create_dir(path.join("parser_1")).unwrap();
+        fs::write(path.join("metadata.json"), "{}").unwrap();
+        fs::write(path.join("parser_1").join("data"), "some data").unwrap();
+        let checksums = compute_checksums(path).unwrap();
+
+        // When
+        let valid_result = verify_checksums(path, &checksums);
+        fs::write(path.join("metadata.json"), "{\"checksums\": {}}").unwrap();
+        let modified_metadata_result = verify_checksums(path, &checksums);
+        fs::write(path.join("parser_1").join("data"), "some").unwrap();
+        let truncated_result = verify_checksums(path, &checksums);
+        fs::remove_file(path.join("parser_1").join("data")).unwrap();
+        let missing_result = verify_checksums(path, &checksums);
+
+        // Then
+        assert_eq!(vec!["parser_1/data"], checksums.keys().collect::<Vec<_>>());
+        assert!(valid_result.is_ok());
+        assert!(modified_metadata_result.is_ok());
+        assert_eq!(
+            &ErrorKind::IntegrityCheck(format!(
+                "checksum of file 'parser_1/data' in {:?} does not match",
+                path
+            )),
+            truncated_result.unwrap_err().kind()
+        );
+        assert_eq!(
+            &ErrorKind::IntegrityCheck(format!("file 'parser_1/data' is missing in {:?}", path)),
+            missing_result.unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn test_should_confine_paths() {
+        // Given
+        let root = Path::new("parser");
+
+        // When / Then
+        assert_eq!(
+            root.join("parser_1"),
+            confine_path(root, "parser_1").unwrap()
+        );
+        for unsafe_path in &["", ".", "../parser", "parser_1/../../etc", "/etc"] {
+            match confine_path(root, unsafe_path).unwrap_err().kind() {
+                ErrorKind::UnsafeLayout(_) => (),
+                kind => panic!("Unexpected error kind: {:?}", kind),
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_should_check_untrusted_parser_directory() {
+        // Given
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("parser");
+        fs::create_dir_all(path.join("parser_1")).unwrap();
+        fs::write(path.join("parser_1").join("data"), "some data").unwrap();
+        let loading_mode = LoadingMode::Untrusted(LoadingLimits::default());
+        let small_files_mode = LoadingMode::Untrusted(LoadingLimits {
+            max_file_size: 4,
+            ..LoadingLimits::default()
+        });
+        let small_total_mode = LoadingMode::Untrusted(LoadingLimits {
+            max_total_size: 12,
+            ..LoadingLimits::default()
+        });
+        let few_entries_mode = LoadingMode::Untrusted(LoadingLimits {
+            max_entries: 2,
+            ..LoadingLimits::default()
+        });
+        let linked_path = temp_dir.path().join("linked_parser");
+        std::os::unix::fs::symlink(&path, &linked_path).unwrap();
+
+        // When
+        let valid_result = loading_mode.check_parser_directory(&path);
+        let large_file_result = small_files_mode.check_parser_directory(&path);
+        let valid_total_result = small_total_mode.check_parser_directory(&path);
+        fs::write(path.join("parser_1").join("other_data"), "other data").unwrap();
+        let large_total_result = small_total_mode.check_parser_directory(&path);
+        let many_entries_result = few_entries_mode.check_parser_directory(&path);
+        let linked_root_result = loading_mode.check_parser_directory(&linked_path);
+        std::os::unix::fs::symlink("/etc", path.join("parser_1").join("link")).unwrap();
+        let symlink_result = loading_mode.check_parser_directory(&path);
+        let trusted_result = LoadingMode::Trusted.check_parser_directory(&path);
+
+        // Then
+        assert!(valid_result.is_ok());
+        assert!(valid_total_result.is_ok());
+        assert!(trusted_result.is_ok());
+        for result in vec![
+            large_file_result,
+            large_total_result,
+            many_entries_result,
+            linked_root_result,
+            symlink_result,
+        ] {
+            match result.unwrap_err().kind() {
+                ErrorKind::UnsafeLayout(_) => (),
+                kind => panic!("Unexpected error kind: {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn test_should_accept_compatible_versions() {
+        // Given
+        let legacy_versions = PersistenceVersions::default();
+        let patched_versions = PersistenceVersions {
+            gazetteer_entity_parser_version: Some("0.8.7".to_string()),
+            ..PersistenceVersions::current()
+        };
+        let prerelease_versions = PersistenceVersions {
+            gazetteer_entity_parser_version: Some("0.8.1-beta.1".to_string()),
+            ..PersistenceVersions::current()
+        };
+
+        // When / Then
+        assert!(legacy_versions.check_compatibility().is_ok());
+        assert!(PersistenceVersions::current().check_compatibility().is_ok());
+        assert!(patched_versions.check_compatibility().is_ok());
+        assert!(prerelease_versions.check_compatibility().is_ok());
+    }
+
+    #[test]
+    fn test_should_refuse_incompatible_versions() {
+        // Given
+        let future_format = PersistenceVersions {
+            format_version: PERSISTENCE_FORMAT_VERSION + 1,
+            ..PersistenceVersions::current()
+        };
+        let incompatible_gazetteer = PersistenceVersions {
+            gazetteer_entity_parser_version: Some("0.7.2".to_string()),
+            ..PersistenceVersions::current()
+        };
+        let incompatible_crate = PersistenceVersions {
+            crate_version: Some("1.0.0".to_string()),
+            ..PersistenceVersions::current()
+        };
+        let invalid_version = PersistenceVersions {
+            rustling_ontology_version: Some("0.19".to_string()),
+            ..PersistenceVersions::current()
+        };
+
+        // When
+        let future_format_error = future_format.check_compatibility().unwrap_err();
+        let incompatible_gazetteer_error =
+            incompatible_gazetteer.check_compatibility().unwrap_err();
+        let incompatible_crate_error = incompatible_crate.check_compatibility().unwrap_err();
+        let invalid_version_error = invalid_version.check_compatibility().unwrap_err();
+
+        // Then
+        for error in &[
+            future_format_error,
+            incompatible_crate_error,
+            invalid_version_error,
+        ] {
+            match error.kind() {
+                ErrorKind::IncompatibleVersion(_) => (),
+                kind => panic!("Unexpected error kind: {:?}", kind),
+            }
+        }
+        assert_eq!(
+            &ErrorKind::IncompatibleVersion(format!(
+                "parser was persisted with gazetteer-entity-parser 0.7.2 which is not compatible \
+                 with {}",
+                GAZETTEER_ENTITY_PARSER_VERSION
+            )),
+            incompatible_gazetteer_error.kind()
+        );
+    }
+
+    #[test]
+    fn test_dependency_versions_should_match_cargo_manifest() {
+        // Given
+        let manifest = include_str!("../Cargo.toml");
+        let dependency_tag = |dependency: &str| {
+            manifest
+                .lines()
+                .find(|line| line.starts_with(&format!("{} = ", dependency)))
+                .and_then(|line| line.split("tag = \"").nth(1))
+                .and_then(|tag| tag.split('"').next())
+                .map(|tag| tag.to_string())
+        };
+
+        // When / Then
+        assert_eq!(
+            Some(RUSTLING_ONTOLOGY_VERSION.to_string()),
+            dependency_tag("rustling-ontology")
+        );
+        assert_eq!(
+            Some(SNIPS_NLU_ONTOLOGY_VERSION.to_string()),
+            dependency_tag("snips-nlu-ontology")
+        );
+        assert_eq!(
+            Some(GAZETTEER_ENTITY_PARSER_VERSION.to_string()),
+            dependency_tag("gazetteer-entity-parser")
+        );
+    }
+}
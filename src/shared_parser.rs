@@ -0,0 +1,123 @@
+use crate::builtin_entity_parser::BuiltinEntityParser;
+use crate::errors::Result;
+use crate::gazetteer_parser::GazetteerParser;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard};
+
+/// Handle to a parser which can be cloned and used concurrently from several threads
+///
+/// Read-only operations, such as extractions, share a read lock and run concurrently. Updates
+/// performed with `update`, such as the extension of gazetteer entities, take a write lock: they
+/// are applied atomically, concurrent extractions seeing the parser either entirely before or
+/// entirely after an update. Extractions only wait for the updates which are in progress, so
+/// updates should prepare their input, e.g. deserialize the values to inject, before calling
+/// `update`.
+#[derive(Debug)]
+pub struct SharedParser<P> {
+    parser: Arc<RwLock<P>>,
+}
+
+pub type SharedBuiltinEntityParser = SharedParser<BuiltinEntityParser>;
+pub type SharedGazetteerParser<T> = SharedParser<GazetteerParser<T>>;
+
+impl<P> SharedParser<P> {
+    pub fn new(parser: P) -> Self {
+        Self {
+            parser: Arc::new(RwLock::new(parser)),
+        }
+    }
+
+    /// Gives read access to the parser, preventing updates until the returned guard is dropped
+    pub fn read(&self) -> RwLockReadGuard<P> {
+        // A panic during an update cannot leave the parser in a state which is unsafe to read
+        self.parser.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Applies `update` to the parser while holding the write lock
+    pub fn update<F, R>(&self, update: F) -> Result<R>
+    where
+        F: FnOnce(&mut P) -> Result<R>,
+    {
+        let mut parser = self.parser.write().unwrap_or_else(PoisonError::into_inner);
+        update(&mut parser)
+    }
+}
+
+impl<P> Clone for SharedParser<P> {
+    fn clone(&self) -> Self {
+        Self {
+            parser: self.parser.clone(),
+        }
+    }
+}
+
+impl<P> From<P> for SharedParser<P> {
+    fn from(parser: P) -> Self {
+        Self::new(parser)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::builtin_entity_parser::BuiltinEntityParserLoader;
+    use crate::test_utils::test_path;
+    use gazetteer_entity_parser::EntityValue;
+    use snips_nlu_ontology::{BuiltinEntityKind, BuiltinGazetteerEntityKind, Language};
+    use std::thread;
+
+    #[test]
+    fn test_should_extend_shared_parser_while_extracting() {
+        // Given
+        let parser = BuiltinEntityParserLoader::new(Language::EN)
+            .use_gazetter_parser(test_path().join("builtin_gazetteer_parser"))
+            .load()
+            .unwrap();
+        let shared_parser = SharedBuiltinEntityParser::new(parser);
+        let sentence = "I want to listen to my extended artist please";
+        let entity_kinds = vec![BuiltinEntityKind::MusicArtist];
+
+        // When
+        let extraction_threads = (0..4)
+            .map(|_| {
+                let shared_parser = shared_parser.clone();
+                let entity_kinds = entity_kinds.clone();
+                thread::spawn(move || {
+                    (0..20)
+                        .map(|_| {
+                            shared_parser
+                                .read()
+                                .extract_entities(sentence, Some(&entity_kinds), 5)
+                                .unwrap()
+                                .len()
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let entity_values = vec![EntityValue {
+            raw_value: "my extended artist".to_string(),
+            resolved_value: "My resolved extended artist".to_string(),
+        }];
+        shared_parser
+            .update(|parser| {
+                parser.extend_gazetteer_entity(
+                    BuiltinGazetteerEntityKind::MusicArtist,
+                    entity_values.into_iter(),
+                )
+            })
+            .unwrap();
+        let nb_extracted_entities = extraction_threads
+            .into_iter()
+            .flat_map(|extraction_thread| extraction_thread.join().unwrap())
+            .collect::<Vec<_>>();
+        let entities = shared_parser
+            .read()
+            .extract_entities(sentence, Some(&entity_kinds), 5)
+            .unwrap();
+
+        // Then
+        assert!(nb_extracted_entities.iter().all(|nb| *nb <= 1));
+        assert_eq!(1, entities.len());
+        assert_eq!("my extended artist", entities[0].value);
+    }
+}
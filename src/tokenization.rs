@@ -0,0 +1,338 @@
+use crate::utils::NON_SPACE_REGEX;
+use serde::{Deserialize, Serialize};
+use snips_nlu_ontology::{BuiltinEntityKind, Language};
+use std::fmt::Debug;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Splits sentences into tokens and decides how they are joined before being parsed
+///
+/// Sentences of languages which are not separated by spaces are parsed once their tokens are
+/// joined, and only the entities which start and end on token boundaries are kept.
+pub trait Tokenizer: Debug + Send + Sync {
+    /// Returns the byte ranges of the tokens of `sentence`
+    fn tokenize(&self, sentence: &str) -> Vec<Range<usize>>;
+
+    /// Returns the separator to insert between two consecutive tokens when joining them
+    fn separator(&self, previous_token: &str, next_token: &str) -> &'static str;
+}
+
+/// Tokenizer splitting sentences on whitespace and joining tokens without separator, which suits
+/// languages such as Japanese
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JoiningTokenizer;
+
+impl Tokenizer for JoiningTokenizer {
+    fn tokenize(&self, sentence: &str) -> Vec<Range<usize>> {
+        whitespace_tokenize(sentence)
+    }
+
+    fn separator(&self, _previous_token: &str, _next_token: &str) -> &'static str {
+        ""
+    }
+}
+
+/// Tokenizer splitting sentences on whitespace and only joining tokens without separator between
+/// Chinese or Japanese characters, so that words written in other scripts remain separated
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CjkTokenizer;
+
+impl Tokenizer for CjkTokenizer {
+    fn tokenize(&self, sentence: &str) -> Vec<Range<usize>> {
+        whitespace_tokenize(sentence)
+    }
+
+    fn separator(&self, previous_token: &str, next_token: &str) -> &'static str {
+        let previous_char = previous_token.chars().last();
+        let next_char = next_token.chars().next();
+        match (previous_char, next_char) {
+            (Some(previous_char), Some(next_char))
+                if is_cjk_char(previous_char) && is_cjk_char(next_char) =>
+            {
+                ""
+            }
+            _ => " ",
+        }
+    }
+}
+
+pub(crate) fn whitespace_tokenize(sentence: &str) -> Vec<Range<usize>> {
+    NON_SPACE_REGEX
+        .find_iter(sentence)
+        .map(|token| token.start()..token.end())
+        .collect()
+}
+
+fn is_cjk_char(c: char) -> bool {
+    let code_point = c as u32;
+    // CJK symbols and punctuation, hiragana and katakana
+    (0x3000..=0x30FF).contains(&code_point)
+        // CJK unified ideographs, including extension A
+        || (0x3400..=0x4DBF).contains(&code_point)
+        || (0x4E00..=0x9FFF).contains(&code_point)
+        // CJK compatibility ideographs
+        || (0xF900..=0xFAFF).contains(&code_point)
+        // Halfwidth and fullwidth forms
+        || (0xFF00..=0xFFEF).contains(&code_point)
+}
+
+/// Tokenization applied by a `BuiltinEntityParser` before parsing sentences
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Tokenization {
+    /// Sentences are parsed as is, which suits languages whose words are separated by spaces
+    SpaceSeparated,
+    /// Sentences are parsed once whitespace is removed, using a `JoiningTokenizer`
+    NonSpaceSeparated,
+    /// Whitespace is only removed between Chinese or Japanese characters, using a `CjkTokenizer`
+    CjkMixed,
+}
+
+impl Tokenization {
+    pub fn default_for_language(language: Language) -> Self {
+        match language {
+            Language::JA => Tokenization::NonSpaceSeparated,
+            _ => Tokenization::SpaceSeparated,
+        }
+    }
+
+    /// Returns the tokenizer to use, or `None` when sentences are parsed as is
+    pub fn tokenizer(&self) -> Option<Arc<dyn Tokenizer>> {
+        match self {
+            Tokenization::SpaceSeparated => None,
+            Tokenization::NonSpaceSeparated => Some(Arc::new(JoiningTokenizer)),
+            Tokenization::CjkMixed => Some(Arc::new(CjkTokenizer)),
+        }
+    }
+}
+
+/// Handling of the entities which do not start or end on token boundaries, when sentences are
+/// parsed once their tokens are joined
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryAlignment {
+    /// The entity is dropped
+    Drop,
+    /// The entity is expanded to the tokens it overlaps
+    Expand,
+    /// The entity is shrunk to the tokens it fully contains, and dropped if there are none
+    Shrink,
+}
+
+impl Default for BoundaryAlignment {
+    fn default() -> Self {
+        BoundaryAlignment::Drop
+    }
+}
+
+/// Entity whose range was adjusted to fall on token boundaries
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BoundaryAdjustment {
+    pub entity_kind: BuiltinEntityKind,
+    /// Range of characters matched by the parser
+    pub matched_range: Range<usize>,
+    /// Range of characters of the returned entity
+    pub range: Range<usize>,
+}
+
+/// Sentence obtained by joining the tokens of an original sentence
+pub(crate) struct JoinedSentence<'a> {
+    original: &'a str,
+    joined: String,
+    original_tokens: Vec<Range<usize>>,
+    joined_tokens: Vec<Range<usize>>,
+}
+
+impl<'a> JoinedSentence<'a> {
+    pub fn new(original: &'a str, tokenizer: &dyn Tokenizer) -> Self {
+        let original_tokens = tokenizer.tokenize(original);
+        let mut joined = String::with_capacity(original.len());
+        let mut joined_tokens = Vec::with_capacity(original_tokens.len());
+        for (token_index, token_range) in original_tokens.iter().enumerate() {
+            let token = &original[token_range.clone()];
+            if token_index > 0 {
+                let previous_token = &original[original_tokens[token_index - 1].clone()];
+                joined.push_str(tokenizer.separator(previous_token, token));
+            }
+            let start = joined.len();
+            joined.push_str(token);
+            joined_tokens.push(start..joined.len());
+        }
+        Self {
+            original,
+            joined,
+            original_tokens,
+            joined_tokens,
+        }
+    }
+
+    pub fn joined(&self) -> &str {
+        &self.joined
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.original_tokens.is_empty()
+    }
+
+    /// Converts a byte range of the joined sentence into a byte range of the original sentence,
+    /// aligned on token boundaries according to `alignment`
+    ///
+    /// `None` is returned when the range cannot be aligned. The returned boolean indicates whether
+    /// the range had to be adjusted to fall on token boundaries.
+    pub fn aligned_original_byte_range(
+        &self,
+        joined_byte_range: &Range<usize>,
+        alignment: BoundaryAlignment,
+    ) -> Option<(Range<usize>, bool)> {
+        let tokens = &self.joined_tokens;
+        let (start_token_index, end_token_index) = match alignment {
+            BoundaryAlignment::Drop => (
+                tokens
+                    .iter()
+                    .position(|token| token.start == joined_byte_range.start)?,
+                tokens
+                    .iter()
+                    .position(|token| token.end == joined_byte_range.end)?,
+            ),
+            BoundaryAlignment::Expand => (
+                tokens
+                    .iter()
+                    .position(|token| token.end > joined_byte_range.start)?,
+                tokens
+                    .iter()
+                    .rposition(|token| token.start < joined_byte_range.end)?,
+            ),
+            BoundaryAlignment::Shrink => (
+                tokens
+                    .iter()
+                    .position(|token| token.start >= joined_byte_range.start)?,
+                tokens
+                    .iter()
+                    .rposition(|token| token.end <= joined_byte_range.end)?,
+            ),
+        };
+        if end_token_index < start_token_index {
+            return None;
+        }
+        let adjusted = tokens[start_token_index].start != joined_byte_range.start
+            || tokens[end_token_index].end != joined_byte_range.end;
+        let original_range = self.original_tokens[start_token_index].start
+            ..self.original_tokens[end_token_index].end;
+        Some((original_range, adjusted))
+    }
+
+    /// Converts a byte range of the joined sentence into a byte range of the original sentence,
+    /// without aligning it on token boundaries
+    pub fn original_byte_range(&self, joined_byte_range: &Range<usize>) -> Range<usize> {
+        let start = self
+            .joined_tokens
+            .iter()
+            .zip(self.original_tokens.iter())
+            .find(|(joined_token, _)| joined_token.end > joined_byte_range.start)
+            .map(|(joined_token, original_token)| {
+                original_token.start + joined_byte_range.start.saturating_sub(joined_token.start)
+            })
+            .unwrap_or_else(|| self.original.len());
+        let end = self
+            .joined_tokens
+            .iter()
+            .zip(self.original_tokens.iter())
+            .rev()
+            .find(|(joined_token, _)| joined_token.start < joined_byte_range.end)
+            .map(|(joined_token, original_token)| {
+                original_token.end - joined_token.end.saturating_sub(joined_byte_range.end)
+            })
+            .unwrap_or(0);
+        start..end.max(start)
+    }
+
+    pub fn original_substring(&self, original_byte_range: &Range<usize>) -> &'a str {
+        &self.original[original_byte_range.clone()]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_joining_tokenizer_should_join_all_tokens() {
+        // Given
+        let sentence = "明日 の 3時 に";
+
+        // When
+        let joined_sentence = JoinedSentence::new(sentence, &JoiningTokenizer);
+
+        // Then
+        assert_eq!("明日の3時に", joined_sentence.joined());
+        let (range, adjusted) = joined_sentence
+            .aligned_original_byte_range(&(9..13), BoundaryAlignment::Drop)
+            .unwrap();
+        assert_eq!("3時", joined_sentence.original_substring(&range));
+        assert!(!adjusted);
+        assert_eq!(
+            None,
+            joined_sentence.aligned_original_byte_range(&(10..13), BoundaryAlignment::Drop)
+        );
+    }
+
+    #[test]
+    fn test_cjk_tokenizer_should_keep_spaces_between_non_cjk_tokens() {
+        // Given
+        let sentence = "明日 の meeting at 3pm に 行く";
+
+        // When
+        let joined_sentence = JoinedSentence::new(sentence, &CjkTokenizer);
+
+        // Then
+        assert_eq!("明日の meeting at 3pm に行く", joined_sentence.joined());
+        let (range, _) = joined_sentence
+            .aligned_original_byte_range(&(18..24), BoundaryAlignment::Drop)
+            .unwrap();
+        assert_eq!("at 3pm", joined_sentence.original_substring(&range));
+    }
+
+    #[test]
+    fn test_should_align_misaligned_ranges_on_token_boundaries() {
+        // Given
+        let sentence = "二 千 十三 年";
+        let joined_sentence = JoinedSentence::new(sentence, &JoiningTokenizer);
+        // "千十" ends in the middle of the "十三" token
+        let joined_byte_range = 3..9;
+
+        // When
+        let dropped = joined_sentence
+            .aligned_original_byte_range(&joined_byte_range, BoundaryAlignment::Drop);
+        let expanded = joined_sentence
+            .aligned_original_byte_range(&joined_byte_range, BoundaryAlignment::Expand);
+        let shrunk = joined_sentence
+            .aligned_original_byte_range(&joined_byte_range, BoundaryAlignment::Shrink);
+        let matched = joined_sentence.original_byte_range(&joined_byte_range);
+
+        // Then
+        assert_eq!(None, dropped);
+        let (expanded_range, expanded_adjusted) = expanded.unwrap();
+        assert_eq!(
+            "千 十三",
+            joined_sentence.original_substring(&expanded_range)
+        );
+        assert!(expanded_adjusted);
+        let (shrunk_range, shrunk_adjusted) = shrunk.unwrap();
+        assert_eq!("千", joined_sentence.original_substring(&shrunk_range));
+        assert!(shrunk_adjusted);
+        assert_eq!("千 十", joined_sentence.original_substring(&matched));
+    }
+
+    #[test]
+    fn test_should_use_language_default_tokenization() {
+        assert_eq!(
+            Tokenization::NonSpaceSeparated,
+            Tokenization::default_for_language(Language::JA)
+        );
+        assert_eq!(
+            Tokenization::SpaceSeparated,
+            Tokenization::default_for_language(Language::KO)
+        );
+        assert!(Tokenization::SpaceSeparated.tokenizer().is_none());
+    }
+}